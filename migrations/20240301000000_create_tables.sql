@@ -0,0 +1,76 @@
+-- Tables created by init.sql before migrations were introduced, hence IF NOT EXISTS
+CREATE TABLE IF NOT EXISTS fees
+(
+    tx_hash   TEXT             NOT NULL,
+    PRIMARY KEY (tx_hash),
+    fee_eth   DOUBLE PRECISION NOT NULL,
+    fee_usdt  DOUBLE PRECISION NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS basis
+(
+    tx_hash      TEXT             NOT NULL,
+    block_number BIGINT           NOT NULL,
+    timestamp_ms BIGINT           NOT NULL,
+    pool_price   DOUBLE PRECISION NOT NULL,
+    cex_price    DOUBLE PRECISION NOT NULL,
+    basis_bps    DOUBLE PRECISION NOT NULL
+);
+CREATE INDEX IF NOT EXISTS basis_timestamp_idx ON basis (timestamp_ms);
+
+CREATE TABLE IF NOT EXISTS opportunities
+(
+    tx_hash       TEXT             NOT NULL,
+    block_number  BIGINT           NOT NULL,
+    timestamp_ms  BIGINT           NOT NULL,
+    basis_bps     DOUBLE PRECISION NOT NULL,
+    threshold_bps DOUBLE PRECISION NOT NULL,
+    gas_cost_usd  DOUBLE PRECISION NOT NULL
+);
+CREATE INDEX IF NOT EXISTS opportunities_timestamp_idx ON opportunities (timestamp_ms);
+
+CREATE TABLE IF NOT EXISTS alerts
+(
+    id               TEXT             NOT NULL,
+    PRIMARY KEY (id),
+    tx_hash          TEXT             NOT NULL,
+    pool_address     TEXT             NOT NULL,
+    kind             TEXT             NOT NULL,
+    notional_usd     DOUBLE PRECISION NOT NULL,
+    price_impact_bps DOUBLE PRECISION,
+    timestamp_ms     BIGINT           NOT NULL
+);
+CREATE INDEX IF NOT EXISTS alerts_timestamp_idx ON alerts (timestamp_ms);
+
+CREATE TABLE IF NOT EXISTS alert_deliveries
+(
+    alert_id     TEXT    NOT NULL,
+    webhook_url  TEXT    NOT NULL,
+    attempt      INTEGER NOT NULL,
+    status_code  INTEGER,
+    error        TEXT,
+    delivered    BOOLEAN NOT NULL,
+    timestamp_ms BIGINT  NOT NULL
+);
+CREATE INDEX IF NOT EXISTS alert_deliveries_alert_idx ON alert_deliveries (alert_id);
+
+CREATE TABLE IF NOT EXISTS swaps
+(
+    tx_hash      TEXT             NOT NULL,
+    log_index    BIGINT           NOT NULL,
+    PRIMARY KEY (tx_hash, log_index),
+    pool_address TEXT             NOT NULL,
+    block_number BIGINT           NOT NULL,
+    timestamp_ms BIGINT           NOT NULL,
+    sender       TEXT             NOT NULL,
+    recipient    TEXT             NOT NULL,
+    tx_from      TEXT             NOT NULL,
+    amount0      NUMERIC          NOT NULL,
+    amount1      NUMERIC          NOT NULL,
+    price        DOUBLE PRECISION NOT NULL,
+    notional_usd DOUBLE PRECISION NOT NULL
+);
+CREATE INDEX IF NOT EXISTS swaps_timestamp_idx ON swaps (timestamp_ms);
+CREATE INDEX IF NOT EXISTS swaps_sender_idx ON swaps (sender);
+CREATE INDEX IF NOT EXISTS swaps_recipient_idx ON swaps (recipient);
+CREATE INDEX IF NOT EXISTS swaps_tx_from_idx ON swaps (tx_from);
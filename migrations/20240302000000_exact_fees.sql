@@ -0,0 +1,17 @@
+-- Store the fee in wei and the ETH/USDT price as exact NUMERIC instead of DOUBLE PRECISION
+ALTER TABLE fees
+    ADD COLUMN gas_used            NUMERIC,
+    ADD COLUMN effective_gas_price NUMERIC,
+    ADD COLUMN fee_wei             NUMERIC,
+    ADD COLUMN eth_usdt_price      NUMERIC;
+
+-- Existing rows only have the floating point fees: keep their best exact approximation
+UPDATE fees
+SET fee_wei        = ROUND(fee_eth::NUMERIC * 1e18),
+    eth_usdt_price = CASE WHEN fee_eth = 0 THEN 0 ELSE fee_usdt::NUMERIC / fee_eth::NUMERIC END;
+
+ALTER TABLE fees
+    ALTER COLUMN fee_wei SET NOT NULL,
+    ALTER COLUMN eth_usdt_price SET NOT NULL,
+    DROP COLUMN fee_eth,
+    DROP COLUMN fee_usdt;
@@ -0,0 +1,6 @@
+-- Split the fee into base fee burned by the protocol and priority fee paid to the block builder.
+-- Rows stored before this migration keep these columns empty.
+ALTER TABLE fees
+    ADD COLUMN base_fee_per_gas NUMERIC,
+    ADD COLUMN burned_fee_wei   NUMERIC,
+    ADD COLUMN priority_fee_wei NUMERIC;
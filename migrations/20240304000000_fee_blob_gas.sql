@@ -0,0 +1,5 @@
+-- Blob gas fee of blob transactions (EIP-4844), empty for other transactions
+ALTER TABLE fees
+    ADD COLUMN blob_gas_used  NUMERIC,
+    ADD COLUMN blob_gas_price NUMERIC,
+    ADD COLUMN blob_fee_wei   NUMERIC;
@@ -0,0 +1,4 @@
+-- L1 component of the fee of transactions on L2s: `l1Fee` on OP-stack chains, `gasUsedForL1` on Arbitrum
+ALTER TABLE fees
+    ADD COLUMN l1_gas_used NUMERIC,
+    ADD COLUMN l1_fee_wei  NUMERIC;
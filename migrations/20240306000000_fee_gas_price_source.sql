@@ -0,0 +1,3 @@
+-- How the gas price of the transaction was determined: `receipt`, `legacy_gas_price` or `dynamic_fee`
+ALTER TABLE fees
+    ADD COLUMN gas_price_source TEXT;
@@ -0,0 +1,20 @@
+-- Gas price statistics of a block, cached for `/blocks/{number}/gas`
+CREATE TABLE IF NOT EXISTS block_gas
+(
+    block_number        BIGINT  NOT NULL,
+    PRIMARY KEY (block_number),
+    block_hash          TEXT    NOT NULL,
+    timestamp_ms        BIGINT  NOT NULL,
+    tx_count            INTEGER NOT NULL,
+    base_fee_per_gas    NUMERIC,
+    min_gas_price       NUMERIC,
+    median_gas_price    NUMERIC,
+    p90_gas_price       NUMERIC,
+    max_gas_price       NUMERIC,
+    min_priority_fee    NUMERIC,
+    median_priority_fee NUMERIC,
+    p90_priority_fee    NUMERIC,
+    max_priority_fee    NUMERIC,
+    eth_usdt_price      NUMERIC NOT NULL,
+    swap_gas            BIGINT  NOT NULL
+);
@@ -0,0 +1,7 @@
+-- Ranking of the priority fee of a transaction against its block and recent swaps on the same pool
+ALTER TABLE fees
+    ADD COLUMN block_percentile          DOUBLE PRECISION,
+    ADD COLUMN block_median_priority_fee NUMERIC,
+    ADD COLUMN overpayment_wei           NUMERIC,
+    ADD COLUMN pool_percentile           DOUBLE PRECISION,
+    ADD COLUMN pool_sample_size          INTEGER;
@@ -0,0 +1,15 @@
+-- Block headers received from the `newHeads` subscription
+CREATE TABLE IF NOT EXISTS blocks
+(
+    block_number     BIGINT  NOT NULL,
+    PRIMARY KEY (block_number),
+    block_hash       TEXT    NOT NULL,
+    parent_hash      TEXT    NOT NULL,
+    timestamp_ms     BIGINT  NOT NULL,
+    base_fee_per_gas NUMERIC,
+    gas_used         NUMERIC NOT NULL,
+    gas_limit        NUMERIC NOT NULL,
+    blob_base_fee    NUMERIC
+);
+CREATE INDEX IF NOT EXISTS blocks_hash_idx ON blocks (block_hash);
+CREATE INDEX IF NOT EXISTS blocks_timestamp_idx ON blocks (timestamp_ms);
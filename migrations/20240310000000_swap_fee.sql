@@ -0,0 +1,3 @@
+-- Share of the tx fee attributed to each swap of the transaction
+ALTER TABLE swaps
+    ADD COLUMN fee_wei NUMERIC;
@@ -0,0 +1,4 @@
+-- Attributed fee of each swap in USD, and as a share of the swap notional
+ALTER TABLE swaps
+    ADD COLUMN gas_usd   DOUBLE PRECISION,
+    ADD COLUMN gas_share DOUBLE PRECISION;
@@ -0,0 +1,9 @@
+-- Next block to process of each backfill, to resume an interrupted backfill
+CREATE TABLE IF NOT EXISTS backfill_progress
+(
+    pool_address TEXT   NOT NULL,
+    from_block   BIGINT NOT NULL,
+    to_block     BIGINT NOT NULL,
+    PRIMARY KEY (pool_address, from_block, to_block),
+    next_block   BIGINT NOT NULL
+);
@@ -0,0 +1,7 @@
+-- Last fully processed block of each pool, from which ingestion resumes after a restart
+CREATE TABLE IF NOT EXISTS checkpoints
+(
+    pool_address TEXT   NOT NULL,
+    PRIMARY KEY (pool_address),
+    block_number BIGINT NOT NULL
+);
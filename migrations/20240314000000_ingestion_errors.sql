@@ -0,0 +1,10 @@
+-- Swap events that could not be processed, kept for audit and to be backfilled
+CREATE TABLE IF NOT EXISTS ingestion_errors
+(
+    tx_hash      TEXT   NOT NULL,
+    log_index    BIGINT NOT NULL,
+    block_number BIGINT NOT NULL,
+    error        TEXT   NOT NULL,
+    timestamp_ms BIGINT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS ingestion_errors_timestamp_idx ON ingestion_errors (timestamp_ms);
@@ -0,0 +1,16 @@
+-- Hash of the block each swap was ingested from, to detect chain reorganizations
+ALTER TABLE swaps
+    ADD COLUMN block_hash TEXT;
+
+-- Chain reorganizations detected, and the number of swaps rolled back
+CREATE TABLE IF NOT EXISTS reorgs
+(
+    pool_address         TEXT   NOT NULL,
+    block_number         BIGINT NOT NULL,
+    orphaned_block_hash  TEXT   NOT NULL,
+    canonical_block_hash TEXT,
+    detected_by          TEXT   NOT NULL,
+    swaps_rolled_back    BIGINT NOT NULL,
+    timestamp_ms         BIGINT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS reorgs_timestamp_idx ON reorgs (timestamp_ms);
@@ -0,0 +1,16 @@
+-- Finality of the stored fees and swaps: pending, confirmed or finalized
+ALTER TABLE fees
+    ADD COLUMN block_number BIGINT,
+    ADD COLUMN finality     TEXT NOT NULL DEFAULT 'pending';
+ALTER TABLE swaps
+    ADD COLUMN finality TEXT NOT NULL DEFAULT 'pending';
+
+-- Fees stored before block numbers were recorded get the block of their swaps, if any
+UPDATE fees f
+SET block_number = s.block_number
+FROM (SELECT DISTINCT tx_hash, block_number FROM swaps) s
+WHERE s.tx_hash = f.tx_hash;
+
+-- Rows are promoted by block number as long as they are not finalized
+CREATE INDEX IF NOT EXISTS fees_unfinalized_idx ON fees (block_number) WHERE finality <> 'finalized';
+CREATE INDEX IF NOT EXISTS swaps_unfinalized_idx ON swaps (block_number) WHERE finality <> 'finalized';
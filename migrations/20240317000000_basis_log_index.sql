@@ -0,0 +1,48 @@
+-- Basis points and opportunities are observed once per swap: key them by the swap's tx hash and log index
+ALTER TABLE basis
+    ADD COLUMN log_index BIGINT;
+ALTER TABLE opportunities
+    ADD COLUMN log_index BIGINT;
+
+-- Existing rows get the log index of their swap, matched by price
+UPDATE basis b
+SET log_index = s.log_index
+FROM swaps s
+WHERE s.tx_hash = b.tx_hash
+  AND s.price = b.pool_price;
+UPDATE opportunities o
+SET log_index = b.log_index
+FROM basis b
+WHERE b.tx_hash = o.tx_hash
+  AND b.basis_bps = o.basis_bps;
+
+-- Drop the duplicates of reprocessed swaps
+DELETE FROM basis a USING basis b
+WHERE a.tx_hash = b.tx_hash
+  AND (a.log_index = b.log_index OR (a.log_index IS NULL AND b.log_index IS NULL AND a.pool_price = b.pool_price))
+  AND a.ctid > b.ctid;
+DELETE FROM opportunities a USING opportunities b
+WHERE a.tx_hash = b.tx_hash
+  AND (a.log_index = b.log_index OR (a.log_index IS NULL AND b.log_index IS NULL AND a.basis_bps = b.basis_bps))
+  AND a.ctid > b.ctid;
+
+-- Rows whose swap is not stored keep a negative index, that no swap has
+UPDATE basis b
+SET log_index = -n.row_number
+FROM (SELECT ctid, ROW_NUMBER() OVER (PARTITION BY tx_hash ORDER BY ctid) AS row_number
+      FROM basis
+      WHERE log_index IS NULL) n
+WHERE b.ctid = n.ctid;
+UPDATE opportunities o
+SET log_index = -n.row_number
+FROM (SELECT ctid, ROW_NUMBER() OVER (PARTITION BY tx_hash ORDER BY ctid) AS row_number
+      FROM opportunities
+      WHERE log_index IS NULL) n
+WHERE o.ctid = n.ctid;
+
+ALTER TABLE basis
+    ALTER COLUMN log_index SET NOT NULL;
+ALTER TABLE opportunities
+    ALTER COLUMN log_index SET NOT NULL;
+CREATE UNIQUE INDEX IF NOT EXISTS basis_swap_idx ON basis (tx_hash, log_index);
+CREATE UNIQUE INDEX IF NOT EXISTS opportunities_swap_idx ON opportunities (tx_hash, log_index);
@@ -0,0 +1,140 @@
+use crate::db::{insert_alert, Alert, AlertDelivery, DbRecord};
+use crate::util::now_ms;
+use ethers::prelude::U256;
+use sqlx::PgPool;
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct AlertSettings {
+    /// URLs to which every alert is POSTed as JSON
+    pub webhooks: Vec<String>,
+    /// Maximum number of delivery attempts per webhook
+    pub max_retries: u32,
+    pub pools: Vec<PoolAlertThreshold>,
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct PoolAlertThreshold {
+    pub pool_address: String,
+    pub min_notional_usd: f64,
+    pub max_price_impact_bps: f64,
+}
+
+impl AlertSettings {
+    /// Thresholds configured for the given pool, if any
+    pub fn thresholds(&self, pool_address: &str) -> Option<&PoolAlertThreshold> {
+        self.pools
+            .iter()
+            .find(|pool| pool.pool_address.eq_ignore_ascii_case(pool_address))
+    }
+}
+
+/// Price impact of a swap in bps, given the pool sqrtPriceX96 before and after the swap
+pub fn price_impact_bps(sqrt_price_before: U256, sqrt_price_after: U256) -> f64 {
+    let before: f64 = sqrt_price_before.to_string().parse().unwrap_or(0.0);
+    let after: f64 = sqrt_price_after.to_string().parse().unwrap_or(0.0);
+    if before == 0.0 {
+        return 0.0;
+    }
+    ((after / before).powi(2) - 1.0).abs() * 10_000.0
+}
+
+/// Returns an alert for every threshold of the pool passed by the swap
+pub fn check_swap(
+    thresholds: &PoolAlertThreshold,
+    tx_hash: &str,
+    log_index: u64,
+    notional_usd: f64,
+    price_impact_bps: Option<f64>,
+) -> Vec<Alert> {
+    let mut kinds = vec![];
+    if notional_usd >= thresholds.min_notional_usd {
+        kinds.push("notional");
+    }
+    if price_impact_bps.is_some_and(|impact| impact >= thresholds.max_price_impact_bps) {
+        kinds.push("price_impact");
+    }
+    let timestamp_ms = now_ms();
+    kinds
+        .into_iter()
+        .map(|kind| Alert {
+            id: format!("{}-{}-{}", tx_hash, log_index, kind),
+            tx_hash: tx_hash.to_string(),
+            pool_address: thresholds.pool_address.clone(),
+            kind: kind.to_string(),
+            notional_usd,
+            price_impact_bps,
+            timestamp_ms,
+        })
+        .collect()
+}
+
+/// Stores the alert and delivers it, unless it was already raised for the same swap,
+/// so that a swap re-ingested after a reorg or a restart is not delivered twice
+pub async fn raise_alert(
+    alert: Alert,
+    webhooks: Vec<String>,
+    max_retries: u32,
+    sender: Sender<DbRecord>,
+    db_connection: PgPool,
+) {
+    match insert_alert(&alert, &db_connection).await {
+        Ok(true) => deliver_alert(alert, webhooks, max_retries, sender).await,
+        Ok(false) => info!("Alert {} already raised", alert.id),
+        Err(err) => error!("Could not store alert {:?}: {}", alert, err),
+    }
+}
+
+/// POST the alert to every webhook, retrying with exponential backoff,
+/// and send every attempt to the queue to be stored in the delivery log
+pub async fn deliver_alert(
+    alert: Alert,
+    webhooks: Vec<String>,
+    max_retries: u32,
+    sender: Sender<DbRecord>,
+) {
+    let client = reqwest::Client::new();
+    for webhook_url in webhooks {
+        for attempt in 1..=max_retries {
+            let res = client.post(webhook_url.as_str()).json(&alert).send().await;
+            let (status_code, error, delivered) = match res {
+                Ok(res) => {
+                    let status = res.status();
+                    let error = (!status.is_success()).then(|| format!("HTTP status {}", status));
+                    (Some(status.as_u16() as i32), error, status.is_success())
+                }
+                Err(err) => (None, Some(err.to_string()), false),
+            };
+            let delivery = AlertDelivery {
+                alert_id: alert.id.clone(),
+                webhook_url: webhook_url.clone(),
+                attempt: attempt as i32,
+                status_code,
+                error,
+                delivered,
+                timestamp_ms: now_ms(),
+            };
+            if sender
+                .send(DbRecord::AlertDelivery(delivery.clone()))
+                .is_err()
+            {
+                error!("Could not send to queue alert delivery {:?}", delivery);
+            }
+            if delivered {
+                info!("Delivered alert {} to {}", alert.id, webhook_url);
+                break;
+            }
+            if attempt == max_retries {
+                warn!(
+                    "Giving up delivering alert {} to {} after {} attempts",
+                    alert.id, webhook_url, attempt
+                );
+                break;
+            }
+            // Wait 0.5s, 1s, 2s, ... before the next attempt
+            tokio::time::sleep(Duration::from_millis(500 * 2u64.pow(attempt - 1))).await;
+        }
+    }
+}
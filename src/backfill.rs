@@ -0,0 +1,214 @@
+use crate::db::{
+    get_backfill_progress_from_db, get_tx_fee_from_db, insert_ingestion_error, insert_tx_fee,
+    upsert_backfill_progress, IngestionError,
+};
+use crate::rpc::EthClient;
+use crate::util::{now_ms, try_get_tx_receipt};
+use crate::Application;
+use anyhow::{anyhow, Result};
+use ethers::prelude::{Address, Filter, Log, Middleware, TxHash, H256};
+use ethers::utils::hex::ToHexExt;
+use futures_util::{stream, StreamExt};
+use std::collections::{HashMap, VecDeque};
+use std::str::FromStr;
+use tracing::{info, warn};
+
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct BackfillSettings {
+    /// Number of blocks of each `eth_getLogs` range, halved when the provider returns too many results
+    pub chunk_blocks: u64,
+    /// Number of ranges processed at the same time
+    pub concurrency: usize,
+}
+
+/// Whether an `eth_getLogs` error means the block range must be narrowed.
+/// Providers word it differently, e.g. "query returned more than 10000 results" or "Log response size exceeded".
+fn is_too_many_results(err: &str) -> bool {
+    let err = err.to_lowercase();
+    [
+        "too many results",
+        "query returned more than",
+        "response size exceeded",
+        "block range is too large",
+        "block range is too wide",
+        "range too large",
+        "range limit exceeded",
+        "exceed maximum block range",
+    ]
+    .iter()
+    .any(|pattern| err.contains(pattern))
+}
+
+/// Get the logs matching the filter from `from_block` to `to_block` included, halving the range
+/// as long as the provider returns too many results. Logs are returned in block order.
+pub async fn get_logs_adaptive(
+    filter: &Filter,
+    from_block: u64,
+    to_block: u64,
+    eth_client: &EthClient,
+) -> Result<Vec<Log>> {
+    let mut logs = vec![];
+    let mut ranges = VecDeque::from([(from_block, to_block)]);
+    while let Some((from, to)) = ranges.pop_front() {
+        let range_filter = filter.clone().from_block(from).to_block(to);
+        match eth_client.get_logs(&range_filter).await {
+            Ok(range_logs) => logs.extend(range_logs),
+            Err(err) if from < to && is_too_many_results(err.to_string().as_str()) => {
+                let mid = from + (to - from) / 2;
+                info!(
+                    "Too many logs in blocks {}-{}, splitting at block {}",
+                    from, to, mid
+                );
+                ranges.push_front((mid + 1, to));
+                ranges.push_front((from, mid));
+            }
+            Err(err) => {
+                return Err(anyhow!(
+                    "could not get logs of blocks {}-{}: {}",
+                    from,
+                    to,
+                    err
+                ))
+            }
+        }
+    }
+    Ok(logs)
+}
+
+/// Computes and stores the fee of every transaction with a swap on the watched pool in the blocks of the range.
+/// A transaction whose fee cannot be computed is recorded as an ingestion error and skipped.
+/// Returns the last block of the range.
+async fn backfill_range(
+    app: &Application,
+    filter: &Filter,
+    from_block: u64,
+    to_block: u64,
+) -> Result<u64> {
+    let logs = get_logs_adaptive(filter, from_block, to_block, &app.eth_client).await?;
+    let mut txs: Vec<(u64, TxHash)> = vec![];
+    // Index of the first swap log of each transaction, to record its ingestion error
+    let mut log_indexes: HashMap<TxHash, u64> = HashMap::new();
+    for log in logs {
+        let (Some(block_number), Some(tx_hash)) = (log.block_number, log.transaction_hash) else {
+            continue;
+        };
+        log_indexes
+            .entry(tx_hash)
+            .or_insert(log.log_index.unwrap_or_default().as_u64());
+        if txs.contains(&(block_number.as_u64(), tx_hash))
+            || get_tx_fee_from_db(&tx_hash, &app.db_connection)
+                .await
+                .is_ok()
+        {
+            continue;
+        }
+        txs.push((block_number.as_u64(), tx_hash));
+    }
+    info!(
+        "Backfilling {} txs of blocks {}-{}",
+        txs.len(),
+        from_block,
+        to_block
+    );
+    let mut receipts = app.receipt_fetcher.get_receipts_by_block(&txs).await?;
+    let heads = app.get_finality_update().await;
+    for (block_number, tx_hash) in txs {
+        // The fee is priced with the kline at the time of the block, not the live ticker, and not ranked
+        // as the fees stored by the watcher. It is inserted directly rather than through the queue,
+        // so that the progress is only saved once the fees of the range are stored.
+        let tx_receipt = match receipts.remove(&tx_hash) {
+            Some(tx_receipt) => Ok(tx_receipt),
+            None => try_get_tx_receipt(tx_hash, &app.eth_client).await,
+        };
+        let fee = match tx_receipt {
+            Ok(tx_receipt) => {
+                app.get_receipt_tx_fee(&tx_receipt, heads.as_ref(), false)
+                    .await
+            }
+            Err(err) => Err(err),
+        };
+        match fee {
+            Ok(fee) => insert_tx_fee(&fee, &app.db_connection).await?,
+            Err(err) => {
+                warn!(
+                    "Could not backfill fee of tx hash {}: {}",
+                    tx_hash.encode_hex_with_prefix(),
+                    err
+                );
+                let ingestion_error = IngestionError {
+                    tx_hash: tx_hash.encode_hex_with_prefix(),
+                    log_index: log_indexes.get(&tx_hash).copied().unwrap_or_default() as i64,
+                    block_number: block_number as i64,
+                    error: err.to_string(),
+                    timestamp_ms: now_ms(),
+                };
+                insert_ingestion_error(&ingestion_error, &app.db_connection).await?;
+            }
+        }
+    }
+    Ok(to_block)
+}
+
+/// Fills the fees of the past swaps on the watched pool from `from_block` to `to_block` included.
+/// Ranges are processed concurrently, and the progress is saved after each range so that an interrupted
+/// backfill of the same blocks resumes where it stopped.
+pub async fn backfill(app: &Application, from_block: u64, to_block: u64) -> Result<()> {
+    let settings = &app.config.backfill;
+    let pool_address = app.config.pool_address.to_lowercase();
+    let filter = Filter::new()
+        .address(Address::from_str(pool_address.as_str())?)
+        .topic0(H256::from_str(app.config.swap_topic.as_str())?);
+
+    let start_block = match get_backfill_progress_from_db(
+        pool_address.as_str(),
+        from_block as i64,
+        to_block as i64,
+        &app.db_connection,
+    )
+    .await?
+    {
+        Some(next_block) => {
+            info!("Resuming backfill from block {}", next_block);
+            next_block as u64
+        }
+        None => from_block,
+    };
+    if start_block > to_block {
+        info!(
+            "Backfill of blocks {}-{} already done",
+            from_block, to_block
+        );
+        return Ok(());
+    }
+
+    let chunk_blocks = settings.chunk_blocks.max(1);
+    let ranges = (start_block..=to_block)
+        .step_by(chunk_blocks as usize)
+        .map(|from| (from, (from + chunk_blocks - 1).min(to_block)));
+    // Ranges complete in order, so the progress only moves past fully processed blocks
+    let mut results = stream::iter(ranges)
+        .map(|(from, to)| backfill_range(app, &filter, from, to))
+        .buffered(settings.concurrency.max(1));
+    while let Some(res) = results.next().await {
+        let last_block = match res {
+            Ok(last_block) => last_block,
+            Err(err) => {
+                warn!("Backfill of {} stopped: {}", pool_address, err);
+                return Err(err);
+            }
+        };
+        upsert_backfill_progress(
+            pool_address.as_str(),
+            from_block as i64,
+            to_block as i64,
+            last_block as i64 + 1,
+            &app.db_connection,
+        )
+        .await?;
+        info!(
+            "Backfilled blocks {}-{} of {}",
+            from_block, last_block, pool_address
+        );
+    }
+    Ok(())
+}
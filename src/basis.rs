@@ -0,0 +1,68 @@
+use crate::db::{BasisPoint, Opportunity};
+use anyhow::Result;
+use ethers::prelude::U256;
+use ethers::utils::format_units;
+
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct BasisSettings {
+    /// Fee tier of the watched pool, in bps (the 0.05% pool is 5 bps)
+    pub fee_tier_bps: f64,
+    /// Gas units of a typical swap on the watched pool
+    pub swap_gas: u64,
+    /// Notional of the arbitrage trade used to amortize the gas cost
+    pub trade_size_usd: f64,
+}
+
+/// Basis of the pool price against the CEX price, in bps
+pub fn compute_basis_bps(pool_price: f64, cex_price: f64) -> f64 {
+    (pool_price - cex_price) / cex_price * 10_000.0
+}
+
+/// Minimum absolute basis in bps for an arbitrage of `trade_size_usd` to be profitable,
+/// i.e. the pool fee plus the gas cost expressed relative to the trade size
+pub fn opportunity_threshold_bps(settings: &BasisSettings, gas_cost_usd: f64) -> f64 {
+    settings.fee_tier_bps + gas_cost_usd / settings.trade_size_usd * 10_000.0
+}
+
+/// Given the price of a swap on the pool and the live CEX price, computes the basis and
+/// returns an opportunity if the basis exceeds the cost of arbitraging it away
+#[allow(clippy::too_many_arguments)]
+pub fn observe_basis(
+    settings: &BasisSettings,
+    tx_hash: String,
+    log_index: i64,
+    block_number: i64,
+    timestamp_ms: i64,
+    pool_price: f64,
+    cex_price: f64,
+    gas_price: U256,
+) -> Result<(BasisPoint, Option<Opportunity>)> {
+    let basis_bps = compute_basis_bps(pool_price, cex_price);
+    let gas_cost_eth: f64 = format_units(gas_price * settings.swap_gas, "ether")?.parse()?;
+    let gas_cost_usd = gas_cost_eth * cex_price;
+    let threshold_bps = opportunity_threshold_bps(settings, gas_cost_usd);
+
+    let point = BasisPoint {
+        tx_hash: tx_hash.clone(),
+        log_index,
+        block_number,
+        timestamp_ms,
+        pool_price,
+        cex_price,
+        basis_bps,
+    };
+    let opportunity = if basis_bps.abs() > threshold_bps {
+        Some(Opportunity {
+            tx_hash,
+            log_index,
+            block_number,
+            timestamp_ms,
+            basis_bps,
+            threshold_bps,
+            gas_cost_usd,
+        })
+    } else {
+        None
+    };
+    Ok((point, opportunity))
+}
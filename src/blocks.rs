@@ -0,0 +1,100 @@
+use crate::db::BlockHeader;
+use crate::fee::Chain;
+use anyhow::{anyhow, Result};
+use ethers::prelude::{Block, H256, U256};
+use ethers::utils::hex::ToHexExt;
+use std::str::FromStr;
+
+/// Minimum blob base fee per blob gas (EIP-4844)
+const MIN_BLOB_BASE_FEE: u64 = 1;
+/// Update fraction of the blob base fee (EIP-4844) by fork on mainnet, with the timestamp the fork activated at:
+/// Cancun, Prague (EIP-7691), then the first two blob parameter only forks of Osaka (EIP-7892)
+const MAINNET_BLOB_BASE_FEE_UPDATE_FRACTIONS: [(u64, u64); 4] = [
+    (0, 3338477),
+    (1746612311, 5007716),
+    (1765290071, 8346193),
+    (1767747671, 11684671),
+];
+
+/// Approximates `factor * e ** (numerator / denominator)` using Taylor expansion, as specified by EIP-4844
+fn fake_exponential(factor: U256, numerator: U256, denominator: U256) -> U256 {
+    let mut i = U256::one();
+    let mut output = U256::zero();
+    let mut numerator_accum = factor * denominator;
+    while !numerator_accum.is_zero() {
+        output += numerator_accum;
+        numerator_accum = numerator_accum * numerator / (denominator * i);
+        i += U256::one();
+    }
+    output / denominator
+}
+
+/// Blob base fee update fractions by fork of a chain, None for the chains that do not carry blobs
+/// themselves: L2s post their data in blobs on L1, whose fee is charged through their own L1 fee
+fn blob_base_fee_update_fractions(chain: Chain) -> Option<&'static [(u64, u64)]> {
+    match chain {
+        Chain::Ethereum => Some(&MAINNET_BLOB_BASE_FEE_UPDATE_FRACTIONS),
+        Chain::Optimism | Chain::Base | Chain::Arbitrum => None,
+    }
+}
+
+/// Base fee per blob gas of a block of `chain`, given its excess blob gas and its timestamp in seconds.
+/// None if the chain has no blob base fee schedule.
+pub fn blob_base_fee(chain: Chain, excess_blob_gas: U256, timestamp: u64) -> Option<U256> {
+    let update_fractions = blob_base_fee_update_fractions(chain)?;
+    let update_fraction = update_fractions
+        .iter()
+        .rev()
+        .find(|(activation, _)| timestamp >= *activation)
+        .unwrap_or(&update_fractions[0])
+        .1;
+    Some(fake_exponential(
+        U256::from(MIN_BLOB_BASE_FEE),
+        excess_blob_gas,
+        U256::from(update_fraction),
+    ))
+}
+
+impl BlockHeader {
+    /// Given a block of `chain`, or a header received from a `newHeads` subscription, extracts the fields to store
+    pub fn new<TX>(block: &Block<TX>, chain: Chain) -> Result<Self> {
+        Ok(BlockHeader {
+            block_number: block
+                .number
+                .ok_or(anyhow!("block number not found"))?
+                .as_u64() as i64,
+            block_hash: block
+                .hash
+                .ok_or(anyhow!("block hash not found"))?
+                .encode_hex_with_prefix(),
+            parent_hash: block.parent_hash.encode_hex_with_prefix(),
+            timestamp_ms: (block.timestamp.as_u64() * 1000) as i64,
+            base_fee_per_gas: block.base_fee_per_gas.map(|fee| fee.to_string()),
+            gas_used: block.gas_used.to_string(),
+            gas_limit: block.gas_limit.to_string(),
+            blob_base_fee: block
+                .excess_blob_gas
+                .and_then(|excess| blob_base_fee(chain, excess, block.timestamp.as_u64()))
+                .map(|fee| fee.to_string()),
+        })
+    }
+
+    /// Block with the stored fields only, enough to compute the fee of its transactions
+    pub fn to_block(&self) -> Result<Block<H256>> {
+        let base_fee_per_gas = self
+            .base_fee_per_gas
+            .as_deref()
+            .map(U256::from_dec_str)
+            .transpose()?;
+        Ok(Block {
+            hash: Some(H256::from_str(self.block_hash.as_str())?),
+            parent_hash: H256::from_str(self.parent_hash.as_str())?,
+            number: Some((self.block_number as u64).into()),
+            timestamp: U256::from(self.timestamp_ms as u64 / 1000),
+            base_fee_per_gas,
+            gas_used: U256::from_dec_str(self.gas_used.as_str())?,
+            gas_limit: U256::from_dec_str(self.gas_limit.as_str())?,
+            ..Default::default()
+        })
+    }
+}
@@ -29,25 +29,389 @@ impl DatabaseSettings {
     }
 }
 
-#[derive(Clone, Debug, FromRow, PartialEq)]
+/// Tx fee as stored in db. Numeric values are exact decimal strings,
+/// decimals in ETH and USDT are derived only when presenting the fee.
+#[derive(Clone, Debug, Default, FromRow, PartialEq)]
 pub struct TxFee {
     pub tx_hash: String,
-    pub fee_eth: f64,
-    pub fee_usdt: f64,
+    /// Missing for fees stored before gas used and gas price were recorded
+    pub gas_used: Option<String>,
+    pub effective_gas_price: Option<String>,
+    /// Total fee paid by the transaction, including the blob gas fee and the L1 fee on OP-stack chains
+    pub fee_wei: String,
+    /// ETH/USDT price used to convert the fee
+    pub eth_usdt_price: String,
+    /// Missing for fees stored before the fee was split into burned and priority fee
+    pub base_fee_per_gas: Option<String>,
+    pub burned_fee_wei: Option<String>,
+    pub priority_fee_wei: Option<String>,
+    /// Only set for blob transactions (EIP-4844)
+    pub blob_gas_used: Option<String>,
+    pub blob_gas_price: Option<String>,
+    pub blob_fee_wei: Option<String>,
+    /// Only set for transactions on L2s
+    pub l1_gas_used: Option<String>,
+    pub l1_fee_wei: Option<String>,
+    /// How the gas price was determined: `receipt`, `legacy_gas_price` or `dynamic_fee`
+    pub gas_price_source: Option<String>,
+    /// Ranking of the priority fee, only set for fees looked up through the API
+    pub block_percentile: Option<f64>,
+    pub block_median_priority_fee: Option<String>,
+    /// Signed fee paid above the block median priority fee
+    pub overpayment_wei: Option<String>,
+    pub pool_percentile: Option<f64>,
+    pub pool_sample_size: Option<i32>,
+    /// Missing for fees stored before block numbers were recorded, which stay pending
+    pub block_number: Option<i64>,
+    pub finality: Finality,
+}
+
+/// Basis between the pool price and the CEX price observed at a swap
+#[derive(Clone, Debug, FromRow, PartialEq, serde::Serialize)]
+pub struct BasisPoint {
+    pub tx_hash: String,
+    pub log_index: i64,
+    pub block_number: i64,
+    pub timestamp_ms: i64,
+    pub pool_price: f64,
+    pub cex_price: f64,
+    pub basis_bps: f64,
+}
+
+/// Basis large enough to cover the pool fee and the gas cost of an arbitrage
+#[derive(Clone, Debug, FromRow, PartialEq, serde::Serialize)]
+pub struct Opportunity {
+    pub tx_hash: String,
+    pub log_index: i64,
+    pub block_number: i64,
+    pub timestamp_ms: i64,
+    pub basis_bps: f64,
+    pub threshold_bps: f64,
+    pub gas_cost_usd: f64,
+}
+
+/// Swap on a watched pool that passed one of the configured alert thresholds
+#[derive(Clone, Debug, FromRow, PartialEq, serde::Serialize)]
+pub struct Alert {
+    /// Unique id made of tx hash, log index and kind
+    pub id: String,
+    pub tx_hash: String,
+    pub pool_address: String,
+    /// Threshold that was passed: `notional` or `price_impact`
+    pub kind: String,
+    pub notional_usd: f64,
+    pub price_impact_bps: Option<f64>,
+    pub timestamp_ms: i64,
+}
+
+/// Attempt to deliver an alert to a webhook
+#[derive(Clone, Debug, FromRow, PartialEq, serde::Serialize)]
+pub struct AlertDelivery {
+    pub alert_id: String,
+    pub webhook_url: String,
+    pub attempt: i32,
+    pub status_code: Option<i32>,
+    pub error: Option<String>,
+    pub delivered: bool,
+    pub timestamp_ms: i64,
+}
+
+/// Swap event decoded from a watched pool
+#[derive(Clone, Debug, FromRow, PartialEq, serde::Serialize)]
+pub struct Swap {
+    pub tx_hash: String,
+    pub log_index: i64,
+    pub pool_address: String,
+    pub block_number: i64,
+    /// Hash of the block the swap was ingested from, missing for swaps stored before reorgs were tracked
+    pub block_hash: Option<String>,
+    pub timestamp_ms: i64,
+    pub sender: String,
+    pub recipient: String,
+    /// Sender of the transaction containing the swap
+    pub tx_from: String,
+    /// Signed token amounts received by the pool, as decimal integers
+    pub amount0: String,
+    pub amount1: String,
+    pub price: f64,
+    pub notional_usd: f64,
+    /// Share of the tx fee attributed to the swap, missing for swaps stored before fees were attributed
+    pub fee_wei: Option<String>,
+    /// Attributed fee in USD, and as a share of the notional (0.01 is 1%)
+    pub gas_usd: Option<f64>,
+    pub gas_share: Option<f64>,
+}
+
+/// Distribution of the gas share of notional of the swaps on a pool over a time bucket
+#[derive(Clone, Debug, FromRow, PartialEq, serde::Serialize)]
+pub struct GasShareBucket {
+    pub pool_address: String,
+    pub bucket_start_ms: i64,
+    pub swap_count: i64,
+    /// Number of swaps whose gas share is above the requested threshold
+    pub above_threshold_count: i64,
+    pub avg_gas_share: f64,
+    pub median_gas_share: f64,
+    pub p90_gas_share: f64,
+}
+
+/// How final the block of a stored row is. Rows are stored as pending, confirmed once
+/// `finality.confirmations` blocks were mined on top of their block, and finalized once
+/// the `finalized` block tag passed their block.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Eq,
+    Ord,
+    PartialEq,
+    PartialOrd,
+    serde::Deserialize,
+    serde::Serialize,
+    sqlx::Type,
+)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum Finality {
+    #[default]
+    Pending,
+    Confirmed,
+    Finalized,
+}
+
+impl Finality {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Finality::Pending => "pending",
+            Finality::Confirmed => "confirmed",
+            Finality::Finalized => "finalized",
+        }
+    }
+
+    /// Levels at least as final as this one, to filter the rows of a query
+    fn at_least(&self) -> Vec<&'static str> {
+        [Finality::Pending, Finality::Confirmed, Finality::Finalized]
+            .into_iter()
+            .filter(|level| level >= self)
+            .map(|level| level.as_str())
+            .collect()
+    }
+}
+
+/// Role of an address in a swap
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AddressRole {
+    Sender,
+    Recipient,
+    From,
+}
+
+impl AddressRole {
+    fn column(&self) -> &'static str {
+        match self {
+            AddressRole::Sender => "sender",
+            AddressRole::Recipient => "recipient",
+            AddressRole::From => "tx_from",
+        }
+    }
+}
+
+/// Trading activity of an address over a time window
+#[derive(Clone, Debug, FromRow, PartialEq, serde::Serialize)]
+pub struct AddressActivity {
+    pub address: String,
+    pub swap_count: i64,
+    pub volume_usd: f64,
+    pub avg_size_usd: f64,
+    /// Gas paid by the transactions containing the swaps, counted once per transaction
+    pub gas_paid_usdt: f64,
+    /// Net token flow of the address: the opposite of the amounts received by the pool
+    pub net_amount0: String,
+    pub net_amount1: String,
+}
+
+/// Gas price statistics of a block as stored in db, as exact decimal strings.
+/// Statistics are missing for blocks without transactions.
+#[derive(Clone, Debug, Default, FromRow, PartialEq)]
+pub struct BlockGas {
+    pub block_number: i64,
+    pub block_hash: String,
+    pub timestamp_ms: i64,
+    pub tx_count: i32,
+    /// Missing for pre-London blocks
+    pub base_fee_per_gas: Option<String>,
+    pub min_gas_price: Option<String>,
+    pub median_gas_price: Option<String>,
+    pub p90_gas_price: Option<String>,
+    pub max_gas_price: Option<String>,
+    pub min_priority_fee: Option<String>,
+    pub median_priority_fee: Option<String>,
+    pub p90_priority_fee: Option<String>,
+    pub max_priority_fee: Option<String>,
+    /// ETH/USDT price at the time of the block
+    pub eth_usdt_price: String,
+    /// Gas units of the standard swap used to derive swap costs
+    pub swap_gas: i64,
+}
+
+/// Block header received from the `newHeads` subscription. Numeric values are exact decimal strings.
+#[derive(Clone, Debug, Default, FromRow, PartialEq, serde::Serialize)]
+pub struct BlockHeader {
+    pub block_number: i64,
+    pub block_hash: String,
+    pub parent_hash: String,
+    pub timestamp_ms: i64,
+    /// Missing for pre-London blocks
+    pub base_fee_per_gas: Option<String>,
+    pub gas_used: String,
+    pub gas_limit: String,
+    /// Base fee per blob gas, missing for blocks before EIP-4844 and on L2s
+    pub blob_base_fee: Option<String>,
+}
+
+/// Last block whose swaps on a pool are all processed, from which ingestion resumes after a restart
+#[derive(Clone, Debug, FromRow, PartialEq)]
+pub struct Checkpoint {
+    pub pool_address: String,
+    pub block_number: i64,
+}
+
+/// Blocks up to which the stored rows are promoted to confirmed and finalized
+#[derive(Clone, Debug, PartialEq)]
+pub struct FinalityUpdate {
+    pub confirmed_block: i64,
+    /// Missing if the node does not support the `finalized` block tag
+    pub finalized_block: Option<i64>,
+}
+
+impl FinalityUpdate {
+    /// Finality of the rows of a block
+    pub fn finality(&self, block_number: i64) -> Finality {
+        if self
+            .finalized_block
+            .is_some_and(|finalized| block_number <= finalized)
+        {
+            Finality::Finalized
+        } else if block_number <= self.confirmed_block {
+            Finality::Confirmed
+        } else {
+            Finality::Pending
+        }
+    }
+}
+
+/// Chain reorganization detected on a pool: the swaps from `block_number` onwards were ingested from blocks
+/// that are no longer canonical, and are rolled back to be ingested again
+#[derive(Clone, Debug, FromRow, PartialEq, serde::Serialize)]
+pub struct Reorg {
+    pub pool_address: String,
+    /// First orphaned block
+    pub block_number: i64,
+    pub orphaned_block_hash: String,
+    /// Hash of the canonical block at the same height, if known
+    pub canonical_block_hash: Option<String>,
+    /// `removed_log` or `parent_hash`
+    pub detected_by: String,
+    /// Number of swaps rolled back, counted when the rollback is stored
+    pub swaps_rolled_back: i64,
+    pub timestamp_ms: i64,
+}
+
+/// Swap event that could not be processed, e.g. because its receipt or the ETH/USDT price could not be fetched
+#[derive(Clone, Debug, FromRow, PartialEq, serde::Serialize)]
+pub struct IngestionError {
+    pub tx_hash: String,
+    pub log_index: i64,
+    pub block_number: i64,
+    pub error: String,
+    pub timestamp_ms: i64,
 }
 
-/// Insert tx in db
+/// Element of the queue consumed by `run_queue_receiver`
+#[derive(Clone, Debug)]
+pub enum DbRecord {
+    TxFee(TxFee),
+    /// Ranking of a stored fee that was not ranked when stored
+    TxFeeRanking(TxFee),
+    Basis(BasisPoint),
+    Opportunity(Opportunity),
+    AlertDelivery(AlertDelivery),
+    Swap(Swap),
+    BlockGas(BlockGas),
+    BlockHeader(BlockHeader),
+    Checkpoint(Checkpoint),
+    IngestionError(IngestionError),
+    Reorg(Reorg),
+    Finality(FinalityUpdate),
+}
+
+impl From<TxFee> for DbRecord {
+    fn from(data: TxFee) -> Self {
+        DbRecord::TxFee(data)
+    }
+}
+
+/// Insert tx in db, unless its fee is already stored
 pub async fn insert_tx_fee(data: &TxFee, pool: &PgPool) -> Result<()> {
     info!("Inserting in db TxFee={:?}", data);
     _ = sqlx::query(
         r#"
-        INSERT INTO fees (tx_hash, fee_eth, fee_usdt)
-        VALUES ($1, $2, $3)
+        INSERT INTO fees (tx_hash, gas_used, effective_gas_price, fee_wei, eth_usdt_price,
+                          base_fee_per_gas, burned_fee_wei, priority_fee_wei,
+                          blob_gas_used, blob_gas_price, blob_fee_wei, l1_gas_used, l1_fee_wei,
+                          gas_price_source, block_percentile, block_median_priority_fee,
+                          overpayment_wei, pool_percentile, pool_sample_size, block_number, finality)
+        VALUES ($1, $2::NUMERIC, $3::NUMERIC, $4::NUMERIC, $5::NUMERIC, $6::NUMERIC, $7::NUMERIC, $8::NUMERIC,
+                $9::NUMERIC, $10::NUMERIC, $11::NUMERIC, $12::NUMERIC, $13::NUMERIC,
+                $14, $15, $16::NUMERIC, $17::NUMERIC, $18, $19, $20, $21)
+        ON CONFLICT (tx_hash) DO NOTHING
         "#,
     )
     .bind(data.tx_hash.clone())
-    .bind(data.fee_eth)
-    .bind(data.fee_usdt)
+    .bind(data.gas_used.clone())
+    .bind(data.effective_gas_price.clone())
+    .bind(data.fee_wei.clone())
+    .bind(data.eth_usdt_price.clone())
+    .bind(data.base_fee_per_gas.clone())
+    .bind(data.burned_fee_wei.clone())
+    .bind(data.priority_fee_wei.clone())
+    .bind(data.blob_gas_used.clone())
+    .bind(data.blob_gas_price.clone())
+    .bind(data.blob_fee_wei.clone())
+    .bind(data.l1_gas_used.clone())
+    .bind(data.l1_fee_wei.clone())
+    .bind(data.gas_price_source.clone())
+    .bind(data.block_percentile)
+    .bind(data.block_median_priority_fee.clone())
+    .bind(data.overpayment_wei.clone())
+    .bind(data.pool_percentile)
+    .bind(data.pool_sample_size)
+    .bind(data.block_number)
+    .bind(data.finality)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Store the ranking of a stored tx fee
+pub async fn update_tx_fee_ranking(data: &TxFee, pool: &PgPool) -> Result<()> {
+    info!("Updating in db ranking of tx_hash {}", data.tx_hash);
+    sqlx::query(
+        r#"
+        UPDATE fees
+        SET block_percentile = $2, block_median_priority_fee = $3::NUMERIC, overpayment_wei = $4::NUMERIC,
+            pool_percentile = $5, pool_sample_size = $6
+        WHERE tx_hash = $1
+        "#,
+    )
+    .bind(data.tx_hash.clone())
+    .bind(data.block_percentile)
+    .bind(data.block_median_priority_fee.clone())
+    .bind(data.overpayment_wei.clone())
+    .bind(data.pool_percentile)
+    .bind(data.pool_sample_size)
     .execute(pool)
     .await?;
     Ok(())
@@ -61,7 +425,28 @@ pub async fn get_tx_fee_from_db(tx_hash: &TxHash, pool: &PgPool) -> Result<TxFee
     );
     let res = sqlx::query_as::<_, TxFee>(
         r#"
-        SELECT * FROM fees
+        SELECT tx_hash,
+               gas_used::TEXT AS gas_used,
+               effective_gas_price::TEXT AS effective_gas_price,
+               fee_wei::TEXT AS fee_wei,
+               eth_usdt_price::TEXT AS eth_usdt_price,
+               base_fee_per_gas::TEXT AS base_fee_per_gas,
+               burned_fee_wei::TEXT AS burned_fee_wei,
+               priority_fee_wei::TEXT AS priority_fee_wei,
+               blob_gas_used::TEXT AS blob_gas_used,
+               blob_gas_price::TEXT AS blob_gas_price,
+               blob_fee_wei::TEXT AS blob_fee_wei,
+               l1_gas_used::TEXT AS l1_gas_used,
+               l1_fee_wei::TEXT AS l1_fee_wei,
+               gas_price_source,
+               block_percentile,
+               block_median_priority_fee::TEXT AS block_median_priority_fee,
+               overpayment_wei::TEXT AS overpayment_wei,
+               pool_percentile,
+               pool_sample_size,
+               block_number,
+               finality
+        FROM fees
         WHERE tx_hash = $1
         "#,
     )
@@ -71,14 +456,778 @@ pub async fn get_tx_fee_from_db(tx_hash: &TxHash, pool: &PgPool) -> Result<TxFee
     Ok(res)
 }
 
+/// Get the priority fees per gas of the swaps on a pool between the given blocks, excluding the given tx.
+/// Every transaction is counted once, whatever its number of swaps.
+pub async fn get_pool_priority_fees_from_db(
+    pool_address: &str,
+    from_block: i64,
+    to_block: i64,
+    exclude_tx_hash: &str,
+    pool: &PgPool,
+) -> Result<Vec<String>> {
+    let res: Vec<(String,)> = sqlx::query_as(
+        r#"
+        SELECT GREATEST(f.effective_gas_price - f.base_fee_per_gas, 0)::TEXT
+        FROM fees f
+        WHERE f.tx_hash IN (
+            SELECT tx_hash FROM swaps
+            WHERE pool_address = $1 AND block_number >= $2 AND block_number <= $3
+        )
+          AND f.tx_hash <> $4
+          AND f.effective_gas_price IS NOT NULL
+          AND f.base_fee_per_gas IS NOT NULL
+        "#,
+    )
+    .bind(pool_address.to_lowercase())
+    .bind(from_block)
+    .bind(to_block)
+    .bind(exclude_tx_hash)
+    .fetch_all(pool)
+    .await?;
+    Ok(res.into_iter().map(|(fee,)| fee).collect())
+}
+
+/// Median gas used by the `limit` most recent swap transactions on a pool, and the number of transactions
+pub async fn get_pool_swap_gas_from_db(
+    pool_address: &str,
+    limit: i64,
+    pool: &PgPool,
+) -> Result<(Option<i64>, i64)> {
+    let res: (Option<i64>, i64) = sqlx::query_as(
+        r#"
+        SELECT ROUND(PERCENTILE_CONT(0.5) WITHIN GROUP (ORDER BY recent.gas_used))::BIGINT,
+               COUNT(*)
+        FROM (
+            SELECT f.gas_used
+            FROM fees f
+            JOIN (SELECT DISTINCT tx_hash, block_number FROM swaps WHERE pool_address = $1) s
+                ON s.tx_hash = f.tx_hash
+            WHERE f.gas_used IS NOT NULL
+            ORDER BY s.block_number DESC
+            LIMIT $2
+        ) recent
+        "#,
+    )
+    .bind(pool_address.to_lowercase())
+    .bind(limit)
+    .fetch_one(pool)
+    .await?;
+    Ok(res)
+}
+
+pub async fn upsert_checkpoint(data: &Checkpoint, pool: &PgPool) -> Result<()> {
+    info!("Inserting in db Checkpoint={:?}", data);
+    sqlx::query(
+        r#"
+        INSERT INTO checkpoints (pool_address, block_number)
+        VALUES ($1, $2)
+        ON CONFLICT (pool_address) DO UPDATE SET block_number = EXCLUDED.block_number
+        "#,
+    )
+    .bind(data.pool_address.to_lowercase())
+    .bind(data.block_number)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Last fully processed block of a pool, None if no swap on the pool was ever processed
+pub async fn get_checkpoint_from_db(pool_address: &str, pool: &PgPool) -> Result<Option<i64>> {
+    let res: Option<(i64,)> =
+        sqlx::query_as("SELECT block_number FROM checkpoints WHERE pool_address = $1")
+            .bind(pool_address.to_lowercase())
+            .fetch_optional(pool)
+            .await?;
+    Ok(res.map(|(block_number,)| block_number))
+}
+
+/// Next block to process of the backfill of a pool over the given blocks, None if it was never started
+pub async fn get_backfill_progress_from_db(
+    pool_address: &str,
+    from_block: i64,
+    to_block: i64,
+    pool: &PgPool,
+) -> Result<Option<i64>> {
+    let res: Option<(i64,)> = sqlx::query_as(
+        r#"
+        SELECT next_block FROM backfill_progress
+        WHERE pool_address = $1 AND from_block = $2 AND to_block = $3
+        "#,
+    )
+    .bind(pool_address.to_lowercase())
+    .bind(from_block)
+    .bind(to_block)
+    .fetch_optional(pool)
+    .await?;
+    Ok(res.map(|(next_block,)| next_block))
+}
+
+/// Save the next block to process of the backfill of a pool over the given blocks
+pub async fn upsert_backfill_progress(
+    pool_address: &str,
+    from_block: i64,
+    to_block: i64,
+    next_block: i64,
+    pool: &PgPool,
+) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO backfill_progress (pool_address, from_block, to_block, next_block)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (pool_address, from_block, to_block) DO UPDATE SET next_block = EXCLUDED.next_block
+        "#,
+    )
+    .bind(pool_address.to_lowercase())
+    .bind(from_block)
+    .bind(to_block)
+    .bind(next_block)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Insert basis point in db
+pub async fn insert_basis(data: &BasisPoint, pool: &PgPool) -> Result<()> {
+    _ = sqlx::query(
+        r#"
+        INSERT INTO basis (tx_hash, log_index, block_number, timestamp_ms, pool_price, cex_price, basis_bps)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        ON CONFLICT (tx_hash, log_index) DO NOTHING
+        "#,
+    )
+    .bind(data.tx_hash.clone())
+    .bind(data.log_index)
+    .bind(data.block_number)
+    .bind(data.timestamp_ms)
+    .bind(data.pool_price)
+    .bind(data.cex_price)
+    .bind(data.basis_bps)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Insert arbitrage opportunity in db
+pub async fn insert_opportunity(data: &Opportunity, pool: &PgPool) -> Result<()> {
+    info!("Inserting in db Opportunity={:?}", data);
+    _ = sqlx::query(
+        r#"
+        INSERT INTO opportunities (tx_hash, log_index, block_number, timestamp_ms, basis_bps, threshold_bps,
+                                   gas_cost_usd)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        ON CONFLICT (tx_hash, log_index) DO NOTHING
+        "#,
+    )
+    .bind(data.tx_hash.clone())
+    .bind(data.log_index)
+    .bind(data.block_number)
+    .bind(data.timestamp_ms)
+    .bind(data.basis_bps)
+    .bind(data.threshold_bps)
+    .bind(data.gas_cost_usd)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Get basis series between the given timestamps, most recent first
+pub async fn get_basis_from_db(
+    from_ms: Option<i64>,
+    to_ms: Option<i64>,
+    limit: i64,
+    pool: &PgPool,
+) -> Result<Vec<BasisPoint>> {
+    let res = sqlx::query_as::<_, BasisPoint>(
+        r#"
+        SELECT * FROM basis
+        WHERE ($1::BIGINT IS NULL OR timestamp_ms >= $1)
+          AND ($2::BIGINT IS NULL OR timestamp_ms <= $2)
+        ORDER BY timestamp_ms DESC
+        LIMIT $3
+        "#,
+    )
+    .bind(from_ms)
+    .bind(to_ms)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(res)
+}
+
+/// Get arbitrage opportunities between the given timestamps, most recent first
+pub async fn get_opportunities_from_db(
+    from_ms: Option<i64>,
+    to_ms: Option<i64>,
+    limit: i64,
+    pool: &PgPool,
+) -> Result<Vec<Opportunity>> {
+    let res = sqlx::query_as::<_, Opportunity>(
+        r#"
+        SELECT * FROM opportunities
+        WHERE ($1::BIGINT IS NULL OR timestamp_ms >= $1)
+          AND ($2::BIGINT IS NULL OR timestamp_ms <= $2)
+        ORDER BY timestamp_ms DESC
+        LIMIT $3
+        "#,
+    )
+    .bind(from_ms)
+    .bind(to_ms)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(res)
+}
+
+/// Insert alert in db. Returns whether it was inserted, i.e. not already raised for the same swap,
+/// e.g. before a reorg or a restart.
+pub async fn insert_alert(data: &Alert, pool: &PgPool) -> Result<bool> {
+    info!("Inserting in db Alert={:?}", data);
+    let res: Option<(String,)> = sqlx::query_as(
+        r#"
+        INSERT INTO alerts (id, tx_hash, pool_address, kind, notional_usd, price_impact_bps, timestamp_ms)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        ON CONFLICT (id) DO NOTHING
+        RETURNING id
+        "#,
+    )
+    .bind(data.id.clone())
+    .bind(data.tx_hash.clone())
+    .bind(data.pool_address.clone())
+    .bind(data.kind.clone())
+    .bind(data.notional_usd)
+    .bind(data.price_impact_bps)
+    .bind(data.timestamp_ms)
+    .fetch_optional(pool)
+    .await?;
+    Ok(res.is_some())
+}
+
+/// Insert alert delivery attempt in db
+pub async fn insert_alert_delivery(data: &AlertDelivery, pool: &PgPool) -> Result<()> {
+    _ = sqlx::query(
+        r#"
+        INSERT INTO alert_deliveries (alert_id, webhook_url, attempt, status_code, error, delivered, timestamp_ms)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+    )
+    .bind(data.alert_id.clone())
+    .bind(data.webhook_url.clone())
+    .bind(data.attempt)
+    .bind(data.status_code)
+    .bind(data.error.clone())
+    .bind(data.delivered)
+    .bind(data.timestamp_ms)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Get alerts between the given timestamps, most recent first
+pub async fn get_alerts_from_db(
+    from_ms: Option<i64>,
+    to_ms: Option<i64>,
+    limit: i64,
+    pool: &PgPool,
+) -> Result<Vec<Alert>> {
+    let res = sqlx::query_as::<_, Alert>(
+        r#"
+        SELECT * FROM alerts
+        WHERE ($1::BIGINT IS NULL OR timestamp_ms >= $1)
+          AND ($2::BIGINT IS NULL OR timestamp_ms <= $2)
+        ORDER BY timestamp_ms DESC
+        LIMIT $3
+        "#,
+    )
+    .bind(from_ms)
+    .bind(to_ms)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(res)
+}
+
+pub async fn insert_ingestion_error(data: &IngestionError, pool: &PgPool) -> Result<()> {
+    info!("Inserting in db IngestionError={:?}", data);
+    sqlx::query(
+        r#"
+        INSERT INTO ingestion_errors (tx_hash, log_index, block_number, error, timestamp_ms)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+    )
+    .bind(data.tx_hash.clone())
+    .bind(data.log_index)
+    .bind(data.block_number)
+    .bind(data.error.clone())
+    .bind(data.timestamp_ms)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Promotes the fees and swaps of the blocks up to `finalized_block` to finalized,
+/// and the pending ones of the blocks up to `confirmed_block` to confirmed
+pub async fn promote_finality(data: &FinalityUpdate, pool: &PgPool) -> Result<()> {
+    info!("Promoting in db FinalityUpdate={:?}", data);
+    for table in ["fees", "swaps"] {
+        if let Some(finalized_block) = data.finalized_block {
+            sqlx::query(
+                format!(
+                    "UPDATE {} SET finality = 'finalized' WHERE finality <> 'finalized' AND block_number <= $1",
+                    table
+                )
+                .as_str(),
+            )
+            .bind(finalized_block)
+            .execute(pool)
+            .await?;
+        }
+        sqlx::query(
+            format!(
+                "UPDATE {} SET finality = 'confirmed' WHERE finality = 'pending' AND block_number <= $1",
+                table
+            )
+            .as_str(),
+        )
+        .bind(data.confirmed_block)
+        .execute(pool)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Rolls back a reorg: deletes the swaps of the pool from the first orphaned block onwards, with the fee,
+/// basis and opportunity rows of their transactions, moves the checkpoint back before the orphaned block,
+/// and records the reorg with the number of swaps rolled back
+pub async fn rollback_reorg(data: &Reorg, pool: &PgPool) -> Result<()> {
+    info!("Rolling back in db Reorg={:?}", data);
+    let pool_address = data.pool_address.to_lowercase();
+    let mut tx = pool.begin().await?;
+    let tx_hashes: Vec<(String,)> = sqlx::query_as(
+        "SELECT DISTINCT tx_hash FROM swaps WHERE pool_address = $1 AND block_number >= $2",
+    )
+    .bind(pool_address.clone())
+    .bind(data.block_number)
+    .fetch_all(&mut tx)
+    .await?;
+    let tx_hashes: Vec<String> = tx_hashes.into_iter().map(|(tx_hash,)| tx_hash).collect();
+    for table in ["fees", "basis", "opportunities"] {
+        sqlx::query(format!("DELETE FROM {} WHERE tx_hash = ANY($1)", table).as_str())
+            .bind(tx_hashes.clone())
+            .execute(&mut tx)
+            .await?;
+    }
+    let swaps_rolled_back =
+        sqlx::query("DELETE FROM swaps WHERE pool_address = $1 AND block_number >= $2")
+            .bind(pool_address.clone())
+            .bind(data.block_number)
+            .execute(&mut tx)
+            .await?
+            .rows_affected();
+    sqlx::query(
+        "UPDATE checkpoints SET block_number = $2 WHERE pool_address = $1 AND block_number >= $2",
+    )
+    .bind(pool_address.clone())
+    .bind(data.block_number - 1)
+    .execute(&mut tx)
+    .await?;
+    sqlx::query(
+        r#"
+        INSERT INTO reorgs (pool_address, block_number, orphaned_block_hash, canonical_block_hash,
+                            detected_by, swaps_rolled_back, timestamp_ms)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+    )
+    .bind(pool_address)
+    .bind(data.block_number)
+    .bind(data.orphaned_block_hash.clone())
+    .bind(data.canonical_block_hash.clone())
+    .bind(data.detected_by.clone())
+    .bind(swaps_rolled_back as i64)
+    .bind(data.timestamp_ms)
+    .execute(&mut tx)
+    .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Get the reorgs detected, most recent first
+pub async fn get_reorgs_from_db(
+    from_ms: Option<i64>,
+    to_ms: Option<i64>,
+    limit: i64,
+    pool: &PgPool,
+) -> Result<Vec<Reorg>> {
+    let res = sqlx::query_as::<_, Reorg>(
+        r#"
+        SELECT * FROM reorgs
+        WHERE ($1::BIGINT IS NULL OR timestamp_ms >= $1)
+          AND ($2::BIGINT IS NULL OR timestamp_ms <= $2)
+        ORDER BY timestamp_ms DESC
+        LIMIT $3
+        "#,
+    )
+    .bind(from_ms)
+    .bind(to_ms)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(res)
+}
+
+/// Get the swap events that could not be processed, most recent first
+pub async fn get_ingestion_errors_from_db(
+    from_ms: Option<i64>,
+    to_ms: Option<i64>,
+    limit: i64,
+    pool: &PgPool,
+) -> Result<Vec<IngestionError>> {
+    let res = sqlx::query_as::<_, IngestionError>(
+        r#"
+        SELECT * FROM ingestion_errors
+        WHERE ($1::BIGINT IS NULL OR timestamp_ms >= $1)
+          AND ($2::BIGINT IS NULL OR timestamp_ms <= $2)
+        ORDER BY timestamp_ms DESC
+        LIMIT $3
+        "#,
+    )
+    .bind(from_ms)
+    .bind(to_ms)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(res)
+}
+
+/// Get the delivery log of an alert
+pub async fn get_alert_deliveries_from_db(
+    alert_id: &str,
+    pool: &PgPool,
+) -> Result<Vec<AlertDelivery>> {
+    let res = sqlx::query_as::<_, AlertDelivery>(
+        r#"
+        SELECT * FROM alert_deliveries
+        WHERE alert_id = $1
+        ORDER BY timestamp_ms
+        "#,
+    )
+    .bind(alert_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(res)
+}
+
+/// Insert swap in db, unless it is already stored, e.g. when its block is processed again
+pub async fn insert_swap(data: &Swap, pool: &PgPool) -> Result<()> {
+    info!("Inserting in db Swap={:?}", data);
+    _ = sqlx::query(
+        r#"
+        INSERT INTO swaps (tx_hash, log_index, pool_address, block_number, block_hash, timestamp_ms,
+                           sender, recipient, tx_from, amount0, amount1, price, notional_usd, fee_wei,
+                           gas_usd, gas_share)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10::NUMERIC, $11::NUMERIC, $12, $13, $14::NUMERIC, $15, $16)
+        ON CONFLICT (tx_hash, log_index) DO NOTHING
+        "#,
+    )
+    .bind(data.tx_hash.clone())
+    .bind(data.log_index)
+    .bind(data.pool_address.clone())
+    .bind(data.block_number)
+    .bind(data.block_hash.clone())
+    .bind(data.timestamp_ms)
+    .bind(data.sender.clone())
+    .bind(data.recipient.clone())
+    .bind(data.tx_from.clone())
+    .bind(data.amount0.clone())
+    .bind(data.amount1.clone())
+    .bind(data.price)
+    .bind(data.notional_usd)
+    .bind(data.fee_wei.clone())
+    .bind(data.gas_usd)
+    .bind(data.gas_share)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Insert block gas statistics in db, keeping the existing ones if the block is already stored
+pub async fn insert_block_gas(data: &BlockGas, pool: &PgPool) -> Result<()> {
+    info!("Inserting in db BlockGas={:?}", data);
+    _ = sqlx::query(
+        r#"
+        INSERT INTO block_gas (block_number, block_hash, timestamp_ms, tx_count, base_fee_per_gas,
+                               min_gas_price, median_gas_price, p90_gas_price, max_gas_price,
+                               min_priority_fee, median_priority_fee, p90_priority_fee, max_priority_fee,
+                               eth_usdt_price, swap_gas)
+        VALUES ($1, $2, $3, $4, $5::NUMERIC, $6::NUMERIC, $7::NUMERIC, $8::NUMERIC, $9::NUMERIC,
+                $10::NUMERIC, $11::NUMERIC, $12::NUMERIC, $13::NUMERIC, $14::NUMERIC, $15)
+        ON CONFLICT (block_number) DO NOTHING
+        "#,
+    )
+    .bind(data.block_number)
+    .bind(data.block_hash.clone())
+    .bind(data.timestamp_ms)
+    .bind(data.tx_count)
+    .bind(data.base_fee_per_gas.clone())
+    .bind(data.min_gas_price.clone())
+    .bind(data.median_gas_price.clone())
+    .bind(data.p90_gas_price.clone())
+    .bind(data.max_gas_price.clone())
+    .bind(data.min_priority_fee.clone())
+    .bind(data.median_priority_fee.clone())
+    .bind(data.p90_priority_fee.clone())
+    .bind(data.max_priority_fee.clone())
+    .bind(data.eth_usdt_price.clone())
+    .bind(data.swap_gas)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Get block gas statistics from db
+pub async fn get_block_gas_from_db(block_number: i64, pool: &PgPool) -> Result<BlockGas> {
+    info!("Getting from db block gas of block {}", block_number);
+    let res = sqlx::query_as::<_, BlockGas>(
+        r#"
+        SELECT block_number, block_hash, timestamp_ms, tx_count,
+               base_fee_per_gas::TEXT AS base_fee_per_gas,
+               min_gas_price::TEXT AS min_gas_price,
+               median_gas_price::TEXT AS median_gas_price,
+               p90_gas_price::TEXT AS p90_gas_price,
+               max_gas_price::TEXT AS max_gas_price,
+               min_priority_fee::TEXT AS min_priority_fee,
+               median_priority_fee::TEXT AS median_priority_fee,
+               p90_priority_fee::TEXT AS p90_priority_fee,
+               max_priority_fee::TEXT AS max_priority_fee,
+               eth_usdt_price::TEXT AS eth_usdt_price,
+               swap_gas
+        FROM block_gas
+        WHERE block_number = $1
+        "#,
+    )
+    .bind(block_number)
+    .fetch_one(pool)
+    .await?;
+    Ok(res)
+}
+
+/// Insert block header in db, replacing the header stored for the same block number if any
+pub async fn insert_block_header(data: &BlockHeader, pool: &PgPool) -> Result<()> {
+    info!("Inserting in db BlockHeader={:?}", data);
+    _ = sqlx::query(
+        r#"
+        INSERT INTO blocks (block_number, block_hash, parent_hash, timestamp_ms, base_fee_per_gas,
+                            gas_used, gas_limit, blob_base_fee)
+        VALUES ($1, $2, $3, $4, $5::NUMERIC, $6::NUMERIC, $7::NUMERIC, $8::NUMERIC)
+        ON CONFLICT (block_number) DO UPDATE
+        SET block_hash = EXCLUDED.block_hash,
+            parent_hash = EXCLUDED.parent_hash,
+            timestamp_ms = EXCLUDED.timestamp_ms,
+            base_fee_per_gas = EXCLUDED.base_fee_per_gas,
+            gas_used = EXCLUDED.gas_used,
+            gas_limit = EXCLUDED.gas_limit,
+            blob_base_fee = EXCLUDED.blob_base_fee
+        "#,
+    )
+    .bind(data.block_number)
+    .bind(data.block_hash.clone())
+    .bind(data.parent_hash.clone())
+    .bind(data.timestamp_ms)
+    .bind(data.base_fee_per_gas.clone())
+    .bind(data.gas_used.clone())
+    .bind(data.gas_limit.clone())
+    .bind(data.blob_base_fee.clone())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+const BLOCK_HEADER_COLUMNS: &str = r#"
+    block_number, block_hash, parent_hash, timestamp_ms,
+    base_fee_per_gas::TEXT AS base_fee_per_gas,
+    gas_used::TEXT AS gas_used,
+    gas_limit::TEXT AS gas_limit,
+    blob_base_fee::TEXT AS blob_base_fee
+"#;
+
+/// Get block header from db given the block hash
+pub async fn get_block_header_from_db(block_hash: &str, pool: &PgPool) -> Result<BlockHeader> {
+    let query = format!(
+        "SELECT {} FROM blocks WHERE block_hash = $1",
+        BLOCK_HEADER_COLUMNS
+    );
+    let res = sqlx::query_as::<_, BlockHeader>(query.as_str())
+        .bind(block_hash.to_lowercase())
+        .fetch_one(pool)
+        .await?;
+    Ok(res)
+}
+
+/// Get block headers between the given timestamps, most recent first
+pub async fn get_block_headers_from_db(
+    from_ms: Option<i64>,
+    to_ms: Option<i64>,
+    limit: i64,
+    pool: &PgPool,
+) -> Result<Vec<BlockHeader>> {
+    let query = format!(
+        r#"
+        SELECT {} FROM blocks
+        WHERE ($1::BIGINT IS NULL OR timestamp_ms >= $1)
+          AND ($2::BIGINT IS NULL OR timestamp_ms <= $2)
+        ORDER BY timestamp_ms DESC
+        LIMIT $3
+        "#,
+        BLOCK_HEADER_COLUMNS
+    );
+    let res = sqlx::query_as::<_, BlockHeader>(query.as_str())
+        .bind(from_ms)
+        .bind(to_ms)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+    Ok(res)
+}
+
+/// Distribution of the gas share of notional of the swaps between the given timestamps,
+/// per pool and per time bucket of `bucket_ms`, most recent first.
+/// Only swaps with a notional of at most `max_notional_usd` and at least `min_finality` are counted, if given.
+#[allow(clippy::too_many_arguments)]
+pub async fn get_gas_share_buckets_from_db(
+    pool_address: Option<&str>,
+    from_ms: Option<i64>,
+    to_ms: Option<i64>,
+    bucket_ms: i64,
+    max_notional_usd: Option<f64>,
+    threshold: f64,
+    min_finality: Option<Finality>,
+    pool: &PgPool,
+) -> Result<Vec<GasShareBucket>> {
+    let res = sqlx::query_as::<_, GasShareBucket>(
+        r#"
+        SELECT pool_address,
+               timestamp_ms / $4 * $4 AS bucket_start_ms,
+               COUNT(*) AS swap_count,
+               COUNT(*) FILTER (WHERE gas_share > $6) AS above_threshold_count,
+               AVG(gas_share) AS avg_gas_share,
+               PERCENTILE_CONT(0.5) WITHIN GROUP (ORDER BY gas_share) AS median_gas_share,
+               PERCENTILE_CONT(0.9) WITHIN GROUP (ORDER BY gas_share) AS p90_gas_share
+        FROM swaps
+        WHERE gas_share IS NOT NULL
+          AND ($1::TEXT IS NULL OR pool_address = $1)
+          AND ($2::BIGINT IS NULL OR timestamp_ms >= $2)
+          AND ($3::BIGINT IS NULL OR timestamp_ms <= $3)
+          AND ($5::DOUBLE PRECISION IS NULL OR notional_usd <= $5)
+          AND ($7::TEXT[] IS NULL OR finality = ANY($7))
+        GROUP BY pool_address, bucket_start_ms
+        ORDER BY bucket_start_ms DESC, pool_address
+        "#,
+    )
+    .bind(pool_address.map(|address| address.to_lowercase()))
+    .bind(from_ms)
+    .bind(to_ms)
+    .bind(bucket_ms)
+    .bind(max_notional_usd)
+    .bind(threshold)
+    .bind(min_finality.map(|finality| finality.at_least()))
+    .fetch_all(pool)
+    .await?;
+    Ok(res)
+}
+
+/// Aggregate the swaps between the given timestamps by the address having the given role.
+/// If `address` is given, only the activity of that address is returned,
+/// otherwise the addresses with the largest volume are returned.
+/// Only swaps of at least `min_finality` are aggregated, if given.
+pub async fn get_address_activity_from_db(
+    role: AddressRole,
+    address: Option<&str>,
+    from_ms: Option<i64>,
+    to_ms: Option<i64>,
+    min_finality: Option<Finality>,
+    limit: i64,
+    pool: &PgPool,
+) -> Result<Vec<AddressActivity>> {
+    let query = format!(
+        r#"
+        WITH s AS (
+            SELECT {column} AS address, tx_hash, amount0, amount1, notional_usd, fee_wei FROM swaps
+            WHERE ($1::TEXT IS NULL OR {column} = $1)
+              AND ($2::BIGINT IS NULL OR timestamp_ms >= $2)
+              AND ($3::BIGINT IS NULL OR timestamp_ms <= $3)
+              AND ($5::TEXT[] IS NULL OR finality = ANY($5))
+        ),
+        gas AS (
+            SELECT address, SUM(gas_paid_usdt)::DOUBLE PRECISION AS gas_paid_usdt
+            FROM (
+                -- Swaps pay the share of the tx fee attributed to them
+                SELECT s.address, s.fee_wei * f.eth_usdt_price / 1e18 AS gas_paid_usdt
+                FROM s JOIN fees f ON f.tx_hash = s.tx_hash
+                WHERE s.fee_wei IS NOT NULL
+                UNION ALL
+                -- Swaps stored before fees were attributed pay the whole tx fee, once per tx
+                SELECT t.address, f.fee_wei * f.eth_usdt_price / 1e18 AS gas_paid_usdt
+                FROM (SELECT DISTINCT address, tx_hash FROM s WHERE fee_wei IS NULL) t
+                JOIN fees f ON f.tx_hash = t.tx_hash
+            ) g
+            GROUP BY address
+        )
+        SELECT s.address,
+               COUNT(*) AS swap_count,
+               SUM(s.notional_usd) AS volume_usd,
+               AVG(s.notional_usd) AS avg_size_usd,
+               COALESCE(MAX(gas.gas_paid_usdt), 0) AS gas_paid_usdt,
+               (-SUM(s.amount0))::TEXT AS net_amount0,
+               (-SUM(s.amount1))::TEXT AS net_amount1
+        FROM s LEFT JOIN gas ON gas.address = s.address
+        GROUP BY s.address
+        ORDER BY volume_usd DESC
+        LIMIT $4
+        "#,
+        column = role.column()
+    );
+    let res = sqlx::query_as::<_, AddressActivity>(query.as_str())
+        .bind(address)
+        .bind(from_ms)
+        .bind(to_ms)
+        .bind(limit)
+        .bind(min_finality.map(|finality| finality.at_least()))
+        .fetch_all(pool)
+        .await?;
+    Ok(res)
+}
+
+/// Insert a queue element in the corresponding table
+pub async fn insert_record(record: &DbRecord, pool: &PgPool) -> Result<()> {
+    match record {
+        DbRecord::TxFee(data) => insert_tx_fee(data, pool).await,
+        DbRecord::TxFeeRanking(data) => update_tx_fee_ranking(data, pool).await,
+        DbRecord::Basis(data) => insert_basis(data, pool).await,
+        DbRecord::Opportunity(data) => insert_opportunity(data, pool).await,
+        DbRecord::AlertDelivery(data) => insert_alert_delivery(data, pool).await,
+        DbRecord::Swap(data) => insert_swap(data, pool).await,
+        DbRecord::BlockGas(data) => insert_block_gas(data, pool).await,
+        DbRecord::BlockHeader(data) => insert_block_header(data, pool).await,
+        DbRecord::Checkpoint(data) => upsert_checkpoint(data, pool).await,
+        DbRecord::IngestionError(data) => insert_ingestion_error(data, pool).await,
+        DbRecord::Reorg(data) => rollback_reorg(data, pool).await,
+        DbRecord::Finality(data) => promote_finality(data, pool).await,
+    }
+}
+
+/// Apply the migrations in `migrations/` not yet applied to the database
+pub async fn run_migrations(pool: &PgPool) -> Result<()> {
+    sqlx::migrate!().run(pool).await?;
+    Ok(())
+}
+
 /// Keep consuming elements from the queue and insert them in db
-pub async fn run_queue_receiver(rx: Receiver<TxFee>, pool: PgPool) {
+pub async fn run_queue_receiver(rx: Receiver<DbRecord>, pool: PgPool) {
     info!("Running queue receiver");
     loop {
         match rx.try_recv() {
-            Ok(txfee) => match insert_tx_fee(&txfee, &pool).await {
+            Ok(record) => match insert_record(&record, &pool).await {
                 Ok(_) => info!("Receiver inserted new data in db"),
-                Err(err) => error!("Error inserting tx fee {:?} in db: {:?}", txfee, err),
+                Err(err) => error!("Error inserting {:?} in db: {:?}", record, err),
             },
             Err(err) => {
                 match err {
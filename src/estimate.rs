@@ -0,0 +1,133 @@
+use crate::fee::FeeAmount;
+use anyhow::{anyhow, Result};
+use ethers::prelude::{U256, U512};
+use serde::Serialize;
+
+/// Fee tiers of UniswapV3 pools are expressed in hundredths of a bip
+const FEE_DENOMINATOR: u64 = 1_000_000;
+
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct EstimateSettings {
+    /// Number of most recent swap transactions on the pool whose median gas used is the gas estimate
+    pub gas_sample_swaps: i64,
+}
+
+/// State of a UniswapV3 pool at a block, as read from `slot0`, `liquidity` and `fee`
+#[derive(Clone, Debug, PartialEq)]
+pub struct PoolState {
+    pub sqrt_price_x96: U256,
+    pub liquidity: u128,
+    /// Fee tier in hundredths of a bip, e.g. 500 for 0.05%
+    pub fee: u32,
+}
+
+/// Output of an exact input swap against the current liquidity of a pool
+#[derive(Clone, Debug, PartialEq)]
+pub struct SwapQuote {
+    pub amount_out: U256,
+    /// Pool fee paid, in token in
+    pub fee_amount: U256,
+    pub sqrt_price_x96_after: U256,
+}
+
+/// All-in cost of a hypothetical exact input swap, as served by the API
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SwapEstimate {
+    pub pool_address: String,
+    pub block_number: u64,
+    pub token_in: String,
+    pub token_out: String,
+    pub amount_in: String,
+    pub amount_out: String,
+    /// Pool fee paid, in token in
+    pub pool_fee: String,
+    /// Move of the pool price caused by the swap
+    pub price_impact_bps: f64,
+    /// Median gas used by the recent swaps on the pool, or `basis.swap_gas` if none is stored
+    pub gas_estimate: u64,
+    /// Number of stored swap transactions the gas estimate is computed from
+    pub gas_sample_size: i64,
+    pub base_fee_per_gas: String,
+    pub priority_fee_per_gas: String,
+    pub eth_usdt_price: String,
+    pub gas_cost: FeeAmount,
+}
+
+/// A swap whose amounts or prices do not fit in the UniswapV3 swap math
+#[derive(Debug)]
+pub struct QuoteOverflow;
+
+impl std::fmt::Display for QuoteOverflow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "swap amount overflow")
+    }
+}
+
+impl std::error::Error for QuoteOverflow {}
+
+fn to_f64(value: U256) -> f64 {
+    value.to_string().parse().unwrap_or(f64::NAN)
+}
+
+fn to_u256(value: U512) -> Result<U256> {
+    U256::try_from(value).map_err(|_| QuoteOverflow.into())
+}
+
+fn checked_mul(a: U512, b: U512) -> Result<U512> {
+    a.checked_mul(b).ok_or(QuoteOverflow.into())
+}
+
+impl PoolState {
+    /// Exact input swap of `amount_in`, following the UniswapV3 swap math and rounding.
+    /// The swap is assumed to stay within the current tick range: crossing an initialized tick
+    /// changes the liquidity, so the output of large swaps is an approximation.
+    pub fn quote(&self, amount_in: U256, zero_for_one: bool) -> Result<SwapQuote> {
+        if self.liquidity == 0 {
+            return Err(anyhow!("pool has no liquidity in range"));
+        }
+        let fee_complement = FEE_DENOMINATOR
+            .checked_sub(self.fee as u64)
+            .ok_or(anyhow!("invalid pool fee {}", self.fee))?;
+        let amount =
+            U512::from(amount_in) * U512::from(fee_complement) / U512::from(FEE_DENOMINATOR);
+        let amount_in_less_fee = to_u256(amount)?;
+        let sqrt_price = U512::from(self.sqrt_price_x96);
+        let liquidity_x96 = U512::from(self.liquidity) << 96;
+
+        let (sqrt_price_after, amount_out) = if zero_for_one {
+            // Price of token0 decreases: sqrt_price_after = L * sqrt_price / (L + amount * sqrt_price), rounded up
+            let denominator = liquidity_x96
+                .checked_add(checked_mul(amount, sqrt_price)?)
+                .ok_or(QuoteOverflow)?;
+            let sqrt_price_after = checked_mul(liquidity_x96, sqrt_price)?.div_mod(denominator);
+            let sqrt_price_after = if sqrt_price_after.1.is_zero() {
+                sqrt_price_after.0
+            } else {
+                sqrt_price_after.0 + 1
+            };
+            let amount_out =
+                checked_mul(U512::from(self.liquidity), sqrt_price - sqrt_price_after)? >> 96;
+            (sqrt_price_after, amount_out)
+        } else {
+            // Price of token0 increases: sqrt_price_after = sqrt_price + amount / L, rounded down
+            let sqrt_price_after = sqrt_price + (amount << 96) / U512::from(self.liquidity);
+            let amount_out = checked_mul(liquidity_x96, sqrt_price_after - sqrt_price)?
+                / sqrt_price_after
+                / sqrt_price;
+            (sqrt_price_after, amount_out)
+        };
+        Ok(SwapQuote {
+            amount_out: to_u256(amount_out)?,
+            fee_amount: amount_in - amount_in_less_fee,
+            sqrt_price_x96_after: to_u256(sqrt_price_after)?,
+        })
+    }
+}
+
+impl SwapQuote {
+    /// Move of the pool price from `sqrt_price_x96` to the price after the swap, in bps
+    pub fn price_impact_bps(&self, sqrt_price_x96: U256) -> f64 {
+        let ratio = to_f64(self.sqrt_price_x96_after) / to_f64(sqrt_price_x96);
+        ((ratio * ratio) - 1.0).abs() * 10_000.0
+    }
+}
@@ -0,0 +1,402 @@
+use crate::db::{Finality, TxFee};
+use crate::gas::{percentile, percentile_rank, priority_fee_per_gas};
+use crate::util::{
+    compute_blob_fee_wei, compute_burned_fee_wei, get_receipt_field, mul_decimal, parse_decimal,
+};
+use anyhow::{anyhow, Result};
+use ethers::prelude::{Block, Transaction, TransactionReceipt, H256, U256};
+use ethers::utils::hex::ToHexExt;
+use serde::Serialize;
+
+/// Chain the watched pools are deployed on, which selects the fee model
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Chain {
+    Ethereum,
+    Optimism,
+    Base,
+    Arbitrum,
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct RankingSettings {
+    /// Number of blocks before the transaction in which swaps on the same pool are ranked against
+    pub lookback_blocks: u64,
+}
+
+/// How the fee of a transaction is split across its swaps
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GasAttribution {
+    /// Every swap pays the same share
+    Equal,
+    /// Every swap pays in proportion to its notional
+    Notional,
+}
+
+impl GasAttribution {
+    /// Split a fee across swaps given their notionals, in any unit. Rounding dust goes to the last swap,
+    /// so that the shares always add up to the fee. Swaps without notional are split equally.
+    pub fn split(&self, fee_wei: U256, notionals: &[U256]) -> Vec<U256> {
+        let total: U256 = notionals.iter().fold(U256::zero(), |acc, n| acc + n);
+        let mut shares: Vec<U256> = match self {
+            GasAttribution::Notional if !total.is_zero() => notionals
+                .iter()
+                .map(|notional| fee_wei * notional / total)
+                .collect(),
+            _ => vec![fee_wei / notionals.len().max(1); notionals.len()],
+        };
+        let attributed = shares.iter().fold(U256::zero(), |acc, s| acc + s);
+        if let Some(last) = shares.last_mut() {
+            *last += fee_wei - attributed;
+        }
+        shares
+    }
+}
+
+/// Gas fee in USD of a swap, given the share of the tx fee attributed to it, and the gas fee
+/// as a share of the swap notional. The share is None for swaps without notional.
+pub fn gas_share_of_notional(
+    fee_wei: &str,
+    eth_usdt_price: &str,
+    notional_usd: f64,
+) -> Result<(f64, Option<f64>)> {
+    let gas_usd: f64 = mul_decimal(fee_wei, eth_usdt_price, 18)?.parse()?;
+    let gas_share = (notional_usd > 0.0).then(|| gas_usd / notional_usd);
+    Ok((gas_usd, gas_share))
+}
+
+/// L1 component of the fee of a transaction on a L2
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct L1Fee {
+    pub l1_gas_used: Option<U256>,
+    pub l1_fee_wei: U256,
+    /// Whether the L1 fee is charged on top of the L2 gas fee, or is already part of it
+    pub additional: bool,
+}
+
+impl Chain {
+    /// Given a tx receipt and its gas price, computes the L1 component of the fee, if any:
+    /// - OP-stack chains charge the L1 data fee `l1Fee` on top of the L2 gas fee
+    /// - Arbitrum includes the L1 component in `gasUsed`: `gasUsedForL1` of it is paid for L1 calldata
+    pub fn l1_fee(&self, tx: &TransactionReceipt, gas_price: U256) -> Result<Option<L1Fee>> {
+        match self {
+            Chain::Ethereum => Ok(None),
+            Chain::Optimism | Chain::Base => {
+                let l1_fee_wei = get_receipt_field(tx, "l1Fee")?;
+                Ok(l1_fee_wei.map(|l1_fee_wei| L1Fee {
+                    l1_gas_used: get_receipt_field(tx, "l1GasUsed").ok().flatten(),
+                    l1_fee_wei,
+                    additional: true,
+                }))
+            }
+            Chain::Arbitrum => {
+                let l1_gas_used = get_receipt_field(tx, "gasUsedForL1")?;
+                Ok(l1_gas_used.map(|l1_gas_used| L1Fee {
+                    l1_gas_used: Some(l1_gas_used),
+                    l1_fee_wei: l1_gas_used * gas_price,
+                    additional: false,
+                }))
+            }
+        }
+    }
+}
+
+/// How the gas price paid by a transaction was determined
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GasPriceSource {
+    /// `effectiveGasPrice` of the tx receipt
+    Receipt,
+    /// `gasPrice` of a legacy (type 0) or access list (type 1) transaction
+    LegacyGasPrice,
+    /// `min(maxFeePerGas, baseFee + maxPriorityFeePerGas)` of a dynamic fee (type 2 or 3) transaction
+    DynamicFee,
+}
+
+impl GasPriceSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GasPriceSource::Receipt => "receipt",
+            GasPriceSource::LegacyGasPrice => "legacy_gas_price",
+            GasPriceSource::DynamicFee => "dynamic_fee",
+        }
+    }
+}
+
+/// Gas price paid by a transaction. Receipts from older nodes and pre-London blocks have no
+/// `effectiveGasPrice`: in that case the price is derived from the transaction itself.
+pub fn resolve_gas_price(
+    tx_receipt: &TransactionReceipt,
+    transaction: Option<&Transaction>,
+    base_fee_per_gas: Option<U256>,
+) -> Result<(U256, GasPriceSource)> {
+    if let Some(gas_price) = tx_receipt.effective_gas_price {
+        return Ok((gas_price, GasPriceSource::Receipt));
+    }
+    let transaction = transaction.ok_or(anyhow!(
+        "effective gas price not found in tx receipt and transaction not available"
+    ))?;
+    let tx_type = transaction
+        .transaction_type
+        .map(|t| t.as_u64())
+        .unwrap_or(0);
+    match tx_type {
+        0 | 1 => {
+            let gas_price = transaction.gas_price.ok_or(anyhow!(
+                "gas price not found in type {} transaction",
+                tx_type
+            ))?;
+            Ok((gas_price, GasPriceSource::LegacyGasPrice))
+        }
+        2 | 3 => {
+            let max_fee = transaction.max_fee_per_gas.ok_or(anyhow!(
+                "max fee per gas not found in type {} transaction",
+                tx_type
+            ))?;
+            let max_priority_fee = transaction.max_priority_fee_per_gas.ok_or(anyhow!(
+                "max priority fee per gas not found in type {} transaction",
+                tx_type
+            ))?;
+            let base_fee = base_fee_per_gas.ok_or(anyhow!(
+                "base fee not found in the block of a type {} transaction",
+                tx_type
+            ))?;
+            Ok((
+                max_fee.min(base_fee + max_priority_fee),
+                GasPriceSource::DynamicFee,
+            ))
+        }
+        _ => Err(anyhow!("unsupported transaction type {}", tx_type)),
+    }
+}
+
+/// Amount of ETH as exact decimal strings, in wei, ETH and USDT
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FeeAmount {
+    pub wei: String,
+    pub eth: String,
+    pub usdt: String,
+}
+
+impl FeeAmount {
+    pub fn new(wei: &str, eth_usdt_price: &str) -> Result<Self> {
+        // Validate the amount before deriving the decimals from it
+        parse_decimal(wei)?;
+        Ok(Self {
+            wei: wei.to_string(),
+            eth: mul_decimal(wei, "1", 18)?,
+            usdt: mul_decimal(wei, eth_usdt_price, 18)?,
+        })
+    }
+}
+
+/// Priority fee of a transaction ranked against the other transactions of its block
+/// and against recent swaps on the same pool
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FeeRanking {
+    pub priority_fee_per_gas: String,
+    /// Percentile of the priority fee among the other transactions of the block
+    pub block_percentile: f64,
+    pub block_median_priority_fee: String,
+    /// Fee paid above the block median priority fee, negative when paying less
+    pub overpayment: FeeAmount,
+    /// Percentile of the priority fee among swaps on the same pool in the previous blocks,
+    /// missing if the transaction has no swap or no such swap is stored
+    pub pool_percentile: Option<f64>,
+    pub pool_sample_size: i32,
+}
+
+/// Tx fee as served by the API, with every value as an exact decimal string
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TxFeeDetails {
+    pub tx_hash: String,
+    pub gas_used: Option<String>,
+    pub effective_gas_price: Option<String>,
+    pub eth_usdt_price: String,
+    pub fee: FeeAmount,
+    pub base_fee_per_gas: Option<String>,
+    /// Base fee burned by the protocol
+    pub burned: Option<FeeAmount>,
+    /// Priority fee paid to the block builder
+    pub priority: Option<FeeAmount>,
+    pub blob_gas_used: Option<String>,
+    pub blob_gas_price: Option<String>,
+    /// Blob gas fee of blob transactions (EIP-4844)
+    pub blob: Option<FeeAmount>,
+    pub l1_gas_used: Option<String>,
+    /// L1 data fee of transactions on L2s
+    pub l1: Option<FeeAmount>,
+    /// How the gas price was determined, see `GasPriceSource`
+    pub gas_price_source: Option<String>,
+    /// Only set for fees ranked when they were looked up
+    pub ranking: Option<FeeRanking>,
+    pub finality: Finality,
+}
+
+impl TxFee {
+    /// Given a tx receipt, the block containing the transaction and the ETH/USDT price at that time,
+    /// computes the fee and splits it into burned base fee, priority fee, blob gas fee and L1 fee.
+    /// The transaction is only needed when the receipt has no effective gas price.
+    pub fn new(
+        tx_receipt: &TransactionReceipt,
+        transaction: Option<&Transaction>,
+        block: &Block<H256>,
+        eth_usdt_price: String,
+        chain: Chain,
+    ) -> Result<Self> {
+        // gas_fee = gas_used * gas_price
+        let (gas_price, gas_price_source) =
+            resolve_gas_price(tx_receipt, transaction, block.base_fee_per_gas)?;
+        let gas_used = tx_receipt
+            .gas_used
+            .ok_or(anyhow!("gas used not found in tx receipt"))?;
+        let gas_fee_wei = gas_used * gas_price;
+        // burned = gas_used * base_fee, the rest of the gas fee is the priority fee
+        let burned_fee_wei = compute_burned_fee_wei(tx_receipt, block.base_fee_per_gas)?;
+        let priority_fee_wei = gas_fee_wei.saturating_sub(burned_fee_wei);
+        // Blob transactions also pay for blob gas, on top of the gas fee
+        let blob_fee_wei = compute_blob_fee_wei(tx_receipt)?;
+        // On L2s the transaction also pays for posting its data on L1
+        let l1_fee = chain.l1_fee(tx_receipt, gas_price)?;
+        let mut fee_wei = gas_fee_wei + blob_fee_wei.unwrap_or_default();
+        if let Some(l1_fee) = l1_fee.as_ref().filter(|l1_fee| l1_fee.additional) {
+            fee_wei += l1_fee.l1_fee_wei;
+        }
+        Ok(TxFee {
+            tx_hash: tx_receipt.transaction_hash.encode_hex_with_prefix(),
+            gas_used: Some(gas_used.to_string()),
+            effective_gas_price: Some(gas_price.to_string()),
+            fee_wei: fee_wei.to_string(),
+            eth_usdt_price,
+            base_fee_per_gas: block.base_fee_per_gas.map(|fee| fee.to_string()),
+            burned_fee_wei: Some(burned_fee_wei.to_string()),
+            priority_fee_wei: Some(priority_fee_wei.to_string()),
+            blob_gas_used: get_receipt_field(tx_receipt, "blobGasUsed")?.map(|gas| gas.to_string()),
+            blob_gas_price: get_receipt_field(tx_receipt, "blobGasPrice")?
+                .map(|price| price.to_string()),
+            blob_fee_wei: blob_fee_wei.map(|fee| fee.to_string()),
+            l1_gas_used: l1_fee
+                .as_ref()
+                .and_then(|l1_fee| l1_fee.l1_gas_used)
+                .map(|gas| gas.to_string()),
+            l1_fee_wei: l1_fee.map(|l1_fee| l1_fee.l1_fee_wei.to_string()),
+            gas_price_source: Some(gas_price_source.as_str().to_string()),
+            block_number: tx_receipt.block_number.map(|number| number.as_u64() as i64),
+            ..Default::default()
+        })
+    }
+
+    /// Fee in ETH as an exact decimal string
+    pub fn fee_eth(&self) -> Result<String> {
+        mul_decimal(self.fee_wei.as_str(), "1", 18)
+    }
+
+    /// Fee in USDT as an exact decimal string
+    pub fn fee_usdt(&self) -> Result<String> {
+        mul_decimal(self.fee_wei.as_str(), self.eth_usdt_price.as_str(), 18)
+    }
+
+    /// Derive the decimal values of the fee for presentation
+    pub fn details(&self) -> Result<TxFeeDetails> {
+        Ok(TxFeeDetails {
+            tx_hash: self.tx_hash.clone(),
+            gas_used: self.gas_used.clone(),
+            effective_gas_price: self.effective_gas_price.clone(),
+            eth_usdt_price: self.eth_usdt_price.clone(),
+            fee: FeeAmount::new(self.fee_wei.as_str(), self.eth_usdt_price.as_str())?,
+            base_fee_per_gas: self.base_fee_per_gas.clone(),
+            burned: self.fee_amount(&self.burned_fee_wei)?,
+            priority: self.fee_amount(&self.priority_fee_wei)?,
+            blob_gas_used: self.blob_gas_used.clone(),
+            blob_gas_price: self.blob_gas_price.clone(),
+            blob: self.fee_amount(&self.blob_fee_wei)?,
+            l1_gas_used: self.l1_gas_used.clone(),
+            l1: self.fee_amount(&self.l1_fee_wei)?,
+            gas_price_source: self.gas_price_source.clone(),
+            ranking: self.ranking()?,
+            finality: self.finality,
+        })
+    }
+
+    /// Priority fee per gas paid by the transaction, if its gas price and base fee are known
+    pub fn priority_fee_per_gas(&self) -> Result<Option<U256>> {
+        match (&self.effective_gas_price, &self.base_fee_per_gas) {
+            (Some(gas_price), Some(base_fee)) => Ok(Some(
+                U256::from_dec_str(gas_price)?.saturating_sub(U256::from_dec_str(base_fee)?),
+            )),
+            _ => Ok(None),
+        }
+    }
+
+    /// Rank the priority fee per gas of the transaction against the other transactions of its block,
+    /// given their receipts, and against the priority fees per gas of recent swaps on the same pool.
+    /// The overpayment is the fee paid above the block median priority fee.
+    pub fn rank(
+        &mut self,
+        block_receipts: &[TransactionReceipt],
+        pool_priority_fees: &[U256],
+    ) -> Result<()> {
+        let (Some(priority_fee), Some(gas_used)) = (self.priority_fee_per_gas()?, &self.gas_used)
+        else {
+            return Ok(());
+        };
+        let base_fee = self
+            .base_fee_per_gas
+            .as_deref()
+            .map(U256::from_dec_str)
+            .transpose()?;
+        let mut block_fees: Vec<U256> = block_receipts
+            .iter()
+            .filter(|receipt| receipt.transaction_hash.encode_hex_with_prefix() != self.tx_hash)
+            .filter_map(|receipt| priority_fee_per_gas(receipt, base_fee))
+            .collect();
+        block_fees.sort();
+        let (Some(block_percentile), Some(median)) = (
+            percentile_rank(&block_fees, priority_fee),
+            percentile(&block_fees, 50.0),
+        ) else {
+            return Ok(());
+        };
+        let overpayment_per_gas = if priority_fee >= median {
+            (priority_fee - median).to_string()
+        } else {
+            format!("-{}", median - priority_fee)
+        };
+        self.block_percentile = Some(block_percentile);
+        self.block_median_priority_fee = Some(median.to_string());
+        self.overpayment_wei = Some(mul_decimal(
+            overpayment_per_gas.as_str(),
+            gas_used.as_str(),
+            0,
+        )?);
+        self.pool_percentile = percentile_rank(pool_priority_fees, priority_fee);
+        self.pool_sample_size = Some(pool_priority_fees.len() as i32);
+        Ok(())
+    }
+
+    fn ranking(&self) -> Result<Option<FeeRanking>> {
+        let (Some(priority_fee), Some(block_percentile), Some(median), Some(overpayment_wei)) = (
+            self.priority_fee_per_gas()?,
+            self.block_percentile,
+            &self.block_median_priority_fee,
+            &self.overpayment_wei,
+        ) else {
+            return Ok(None);
+        };
+        Ok(Some(FeeRanking {
+            priority_fee_per_gas: priority_fee.to_string(),
+            block_percentile,
+            block_median_priority_fee: median.clone(),
+            overpayment: FeeAmount::new(overpayment_wei, self.eth_usdt_price.as_str())?,
+            pool_percentile: self.pool_percentile,
+            pool_sample_size: self.pool_sample_size.unwrap_or_default(),
+        }))
+    }
+
+    fn fee_amount(&self, wei: &Option<String>) -> Result<Option<FeeAmount>> {
+        wei.as_ref()
+            .map(|wei| FeeAmount::new(wei.as_str(), self.eth_usdt_price.as_str()))
+            .transpose()
+    }
+}
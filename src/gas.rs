@@ -0,0 +1,192 @@
+use crate::db::BlockGas;
+use crate::fee::{resolve_gas_price, FeeAmount};
+use crate::util::mul_decimal;
+use anyhow::{anyhow, Result};
+use ethers::prelude::{Block, Transaction, TransactionReceipt, H256, U256};
+use ethers::utils::hex::ToHexExt;
+use serde::Serialize;
+
+/// Min, median, 90th percentile and max of a distribution
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Percentiles<T> {
+    pub min: T,
+    pub median: T,
+    pub p90: T,
+    pub max: T,
+}
+
+impl<T> Percentiles<T> {
+    pub fn map<U>(self, f: impl Fn(T) -> U) -> Percentiles<U> {
+        Percentiles {
+            min: f(self.min),
+            median: f(self.median),
+            p90: f(self.p90),
+            max: f(self.max),
+        }
+    }
+
+    pub fn try_map<U>(self, f: impl Fn(T) -> Result<U>) -> Result<Percentiles<U>> {
+        Ok(Percentiles {
+            min: f(self.min)?,
+            median: f(self.median)?,
+            p90: f(self.p90)?,
+            max: f(self.max)?,
+        })
+    }
+}
+
+/// Value at the given percentile of sorted values, using the nearest-rank method
+pub fn percentile(sorted: &[U256], p: f64) -> Option<U256> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+/// Percentile rank of a value among other values: the share of values below it,
+/// counting equal values as half below. None if there are no values.
+pub fn percentile_rank(values: &[U256], value: U256) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    let below = values.iter().filter(|v| **v < value).count() as f64;
+    let equal = values.iter().filter(|v| **v == value).count() as f64;
+    Some((below + equal / 2.0) / values.len() as f64 * 100.0)
+}
+
+/// Priority fee per gas paid by a transaction, i.e. its gas price above the base fee.
+/// None if the receipt has no effective gas price.
+pub fn priority_fee_per_gas(receipt: &TransactionReceipt, base_fee: Option<U256>) -> Option<U256> {
+    receipt
+        .effective_gas_price
+        .map(|gas_price| gas_price.saturating_sub(base_fee.unwrap_or_default()))
+}
+
+/// Percentiles of the given values, None if there are no values
+pub fn percentiles(mut values: Vec<U256>) -> Option<Percentiles<U256>> {
+    values.sort();
+    Some(Percentiles {
+        min: *values.first()?,
+        median: percentile(&values, 50.0)?,
+        p90: percentile(&values, 90.0)?,
+        max: *values.last()?,
+    })
+}
+
+/// Gas price statistics of a block as served by the API, with every value as an exact decimal string
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct BlockGasDetails {
+    pub block_number: i64,
+    pub block_hash: String,
+    pub timestamp_ms: i64,
+    pub tx_count: i32,
+    pub base_fee_per_gas: Option<String>,
+    /// Effective gas price paid by the transactions of the block
+    pub gas_price: Option<Percentiles<String>>,
+    /// Effective priority fee per gas, i.e. the gas price above the base fee
+    pub priority_fee: Option<Percentiles<String>>,
+    pub eth_usdt_price: String,
+    /// Gas units of the standard swap priced in `swap_cost`
+    pub swap_gas: i64,
+    /// Cost of a standard swap at each gas price
+    pub swap_cost: Option<Percentiles<FeeAmount>>,
+}
+
+impl BlockGas {
+    /// Given a block and the receipts of all its transactions, computes the distribution of
+    /// the gas prices paid. The price of the receipts without effective gas price is derived from
+    /// their transaction, that must then be among `transactions`.
+    pub fn new(
+        block: &Block<H256>,
+        receipts: &[TransactionReceipt],
+        transactions: &[Transaction],
+        eth_usdt_price: String,
+        swap_gas: u64,
+    ) -> Result<Self> {
+        let gas_prices = receipts
+            .iter()
+            .map(|receipt| {
+                let transaction = transactions
+                    .iter()
+                    .find(|tx| tx.hash == receipt.transaction_hash);
+                let (gas_price, _) =
+                    resolve_gas_price(receipt, transaction, block.base_fee_per_gas)?;
+                Ok(gas_price)
+            })
+            .collect::<Result<Vec<U256>>>()?;
+        let priority_fees = gas_prices
+            .iter()
+            .map(|gas_price| gas_price.saturating_sub(block.base_fee_per_gas.unwrap_or_default()))
+            .collect();
+        let gas_price = percentiles(gas_prices).map(|p| p.map(|price| price.to_string()));
+        let priority_fee = percentiles(priority_fees).map(|p| p.map(|fee| fee.to_string()));
+        Ok(BlockGas {
+            block_number: block
+                .number
+                .ok_or(anyhow!("block number not found"))?
+                .as_u64() as i64,
+            block_hash: block
+                .hash
+                .ok_or(anyhow!("block hash not found"))?
+                .encode_hex_with_prefix(),
+            timestamp_ms: (block.timestamp.as_u64() * 1000) as i64,
+            tx_count: receipts.len() as i32,
+            base_fee_per_gas: block.base_fee_per_gas.map(|fee| fee.to_string()),
+            min_gas_price: gas_price.as_ref().map(|p| p.min.clone()),
+            median_gas_price: gas_price.as_ref().map(|p| p.median.clone()),
+            p90_gas_price: gas_price.as_ref().map(|p| p.p90.clone()),
+            max_gas_price: gas_price.map(|p| p.max),
+            min_priority_fee: priority_fee.as_ref().map(|p| p.min.clone()),
+            median_priority_fee: priority_fee.as_ref().map(|p| p.median.clone()),
+            p90_priority_fee: priority_fee.as_ref().map(|p| p.p90.clone()),
+            max_priority_fee: priority_fee.map(|p| p.max),
+            eth_usdt_price,
+            swap_gas: swap_gas as i64,
+        })
+    }
+
+    /// Derive the swap costs and group the statistics for presentation
+    pub fn details(&self) -> Result<BlockGasDetails> {
+        let gas_price = self.gas_price();
+        let swap_cost = gas_price
+            .clone()
+            .map(|p| {
+                p.try_map(|price| {
+                    let wei = mul_decimal(price.as_str(), self.swap_gas.to_string().as_str(), 0)?;
+                    FeeAmount::new(wei.as_str(), self.eth_usdt_price.as_str())
+                })
+            })
+            .transpose()?;
+        Ok(BlockGasDetails {
+            block_number: self.block_number,
+            block_hash: self.block_hash.clone(),
+            timestamp_ms: self.timestamp_ms,
+            tx_count: self.tx_count,
+            base_fee_per_gas: self.base_fee_per_gas.clone(),
+            gas_price,
+            priority_fee: self.priority_fee(),
+            eth_usdt_price: self.eth_usdt_price.clone(),
+            swap_gas: self.swap_gas,
+            swap_cost,
+        })
+    }
+
+    fn gas_price(&self) -> Option<Percentiles<String>> {
+        Some(Percentiles {
+            min: self.min_gas_price.clone()?,
+            median: self.median_gas_price.clone()?,
+            p90: self.p90_gas_price.clone()?,
+            max: self.max_gas_price.clone()?,
+        })
+    }
+
+    fn priority_fee(&self) -> Option<Percentiles<String>> {
+        Some(Percentiles {
+            min: self.min_priority_fee.clone()?,
+            median: self.median_priority_fee.clone()?,
+            p90: self.p90_priority_fee.clone()?,
+            max: self.max_priority_fee.clone()?,
+        })
+    }
+}
@@ -0,0 +1,781 @@
+use crate::alerts::{check_swap, price_impact_bps, raise_alert};
+use crate::backfill::get_logs_adaptive;
+use crate::basis::observe_basis;
+use crate::binance_client::BinanceClient;
+use crate::db::{Checkpoint, DbRecord, FinalityUpdate, IngestionError, Reorg, Swap, TxFee};
+use crate::fee::{gas_share_of_notional, Chain};
+use crate::receipts::ReceiptFetcher;
+use crate::rpc::{connect_http, EthClient};
+use crate::util::{
+    amount_to_notional_usd, amounts_to_price, get_receipt_block, get_transaction_if_needed, now_ms,
+    receipt_swap_amounts0, try_get_tx_receipt,
+};
+use crate::{AppConfig, SwapFilter};
+use anyhow::{anyhow, Result};
+use ethers::contract::{parse_log, LogMeta};
+use ethers::prelude::{
+    Address, Block, BlockNumber, Filter, Log, Middleware, TransactionReceipt, TxHash, H256, U256,
+};
+use ethers::utils::hex::ToHexExt;
+use futures_util::future::{ready, BoxFuture, Shared};
+use futures_util::stream::FuturesOrdered;
+use futures_util::{FutureExt, StreamExt};
+use sqlx::PgPool;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+/// How new swaps and blocks are received from the node
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IngestionMode {
+    /// `eth_subscribe` over the WS endpoint
+    Ws,
+    /// `eth_getLogs` and `eth_getBlockByNumber` over the HTTP endpoint, for providers without WS
+    Polling,
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct IngestionSettings {
+    pub mode: IngestionMode,
+    /// Delay between two polls of the head in polling mode
+    pub poll_interval_ms: u64,
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct SubscriptionSettings {
+    /// Delay before reconnecting after the subscription ended, doubled after each failed attempt
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+}
+
+impl SubscriptionSettings {
+    /// Delay before the given reconnection attempt, starting at 0
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let backoff_ms = self
+            .initial_backoff_ms
+            .saturating_mul(2u64.saturating_pow(attempt));
+        Duration::from_millis(backoff_ms.min(self.max_backoff_ms))
+    }
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct ReorgSettings {
+    /// Number of blocks below the head whose hash is tracked: deeper reorgs are not detected
+    pub max_depth: u64,
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct FinalitySettings {
+    /// Number of blocks mined on top of a block for its rows to be confirmed
+    pub confirmations: u64,
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct PipelineSettings {
+    /// Number of swaps whose receipt, block and price are fetched at the same time
+    pub workers: usize,
+}
+
+/// Depth of the swap processing pipeline, shared with the web server
+pub type PipelineMonitor = Arc<RwLock<PipelineStats>>;
+
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize)]
+pub struct PipelineStats {
+    pub workers: usize,
+    /// Swaps being fetched, or fetched and waiting for the previous ones to be stored
+    pub queue_depth: usize,
+    /// Highest queue depth since the start
+    pub max_queue_depth: usize,
+    /// Swaps processed since the start, including the ones recorded as ingestion errors
+    pub processed: u64,
+}
+
+impl PipelineStats {
+    pub fn monitor(settings: &PipelineSettings) -> PipelineMonitor {
+        Arc::new(RwLock::new(PipelineStats {
+            workers: settings.workers.max(1),
+            ..Default::default()
+        }))
+    }
+}
+
+/// Everything fetched from the node and Binance to store a swap
+struct FetchedSwap {
+    tx_receipt: TransactionReceipt,
+    data: TxFee,
+    eth_price: f64,
+    timestamp_ms: i64,
+}
+
+/// A swap event, its price impact and the result of its fetch
+struct QueuedSwap {
+    log: SwapFilter,
+    meta: LogMeta,
+    impact_bps: Option<f64>,
+    fetched: Result<FetchedSwap>,
+}
+
+/// Receipts of the swaps of a block, fetched together by the first of its swaps to be fetched in the pipeline
+type BlockReceipts = Shared<BoxFuture<'static, Arc<HashMap<TxHash, TransactionReceipt>>>>;
+
+/// Output of a step of the pipeline, handled in the order the steps were queued
+enum Step {
+    Swap(Box<QueuedSwap>),
+    Checkpoint(u64),
+}
+
+/// Processes the swap events of the watched pool, in block order, and keeps track of the last fully processed block.
+/// Swaps go through a pipeline: up to `pipeline.workers` of them are fetched in parallel, and their records are stored
+/// in the order the events were received, so that the records of a block are always queued before its checkpoint.
+pub struct SwapProcessor {
+    config: AppConfig,
+    sender: Sender<DbRecord>,
+    /// Db the alerts are stored in before being delivered
+    db_connection: PgPool,
+    pub eth_client: EthClient,
+    receipt_fetcher: ReceiptFetcher,
+    binance_client: BinanceClient,
+    /// Block of the range being processed whose swaps are being queued, and the fetch of their receipts
+    block_receipts: Option<(u64, BlockReceipts)>,
+    /// Last sqrtPriceX96 seen on the pool, used to compute the price impact of the next swap. Only known
+    /// across consecutive swaps: reset when a swap cannot be decoded, on a reorg and before a catch-up.
+    last_sqrt_price: Option<U256>,
+    /// Block of the last processed swap
+    pub last_block: Option<u64>,
+    /// Last block whose swaps are all processed
+    pub checkpoint: Option<u64>,
+    /// Block, tx hash and log index of the swaps processed after the checkpoint
+    processed: HashSet<(u64, TxHash, U256)>,
+    /// Hash of the recent blocks with processed swaps, to detect the ones orphaned by a reorg
+    block_hashes: BTreeMap<u64, H256>,
+    /// Number and hash of the last new head received
+    last_head: Option<(u64, H256)>,
+    /// Swaps being fetched and checkpoints waiting for them, in order
+    pipeline: FuturesOrdered<BoxFuture<'static, Step>>,
+    pub monitor: PipelineMonitor,
+}
+
+impl SwapProcessor {
+    pub fn new(
+        config: AppConfig,
+        sender: Sender<DbRecord>,
+        db_connection: PgPool,
+        checkpoint: Option<u64>,
+    ) -> Self {
+        let eth_client = connect_http(&config.rpc_urls_http, &config.rpc).unwrap();
+        Self {
+            receipt_fetcher: ReceiptFetcher::new(eth_client.clone(), config.receipts.clone()),
+            eth_client,
+            binance_client: BinanceClient::new(config.binance_host.as_str()),
+            block_receipts: None,
+            monitor: PipelineStats::monitor(&config.pipeline),
+            config,
+            sender,
+            db_connection,
+            last_sqrt_price: None,
+            last_block: None,
+            checkpoint,
+            processed: HashSet::new(),
+            block_hashes: BTreeMap::new(),
+            last_head: None,
+            pipeline: FuturesOrdered::new(),
+        }
+    }
+
+    /// Queues a swap event in the pipeline, unless it was already processed, e.g. when the live subscription
+    /// overlaps the catch-up. Waits for the oldest swaps to be stored while `pipeline.workers` swaps are queued.
+    /// `live` events are priced with the live ticker, the others with the kline at the time of their block.
+    /// A swap that cannot be processed is recorded as an ingestion error, without stopping the processing
+    /// of the next ones. Returns whether the event was queued.
+    pub async fn handle(&mut self, log: SwapFilter, meta: LogMeta, live: bool) -> Result<bool> {
+        let block_number = meta.block_number.as_u64();
+        let key = (block_number, meta.transaction_hash, meta.log_index);
+        if self
+            .checkpoint
+            .is_some_and(|checkpoint| block_number <= checkpoint)
+            || self.processed.contains(&key)
+        {
+            info!(
+                "Skipping swap of tx hash {} in already processed block {}",
+                meta.transaction_hash.encode_hex_with_prefix(),
+                block_number
+            );
+            return Ok(false);
+        }
+        // Events arrive in block order: a swap in a later block means the previous one is fully processed
+        if let Some(last_block) = self.last_block.filter(|last| *last < block_number) {
+            self.save_checkpoint(last_block);
+        }
+        self.last_block = Some(block_number);
+        self.processed.insert(key);
+        self.block_hashes.insert(block_number, meta.block_hash);
+        let max_depth = self.config.reorg.max_depth;
+        self.block_hashes
+            .retain(|block, _| block + max_depth > block_number);
+        // Computed in event order, whether or not the swap can then be fetched and stored
+        let impact_bps = self
+            .last_sqrt_price
+            .map(|before| price_impact_bps(before, log.sqrt_price_x96));
+        self.last_sqrt_price = Some(log.sqrt_price_x96);
+        while self.queue_depth() >= self.config.pipeline.workers.max(1) {
+            self.next_step().await;
+        }
+        let block_receipts = self
+            .block_receipts
+            .as_ref()
+            .filter(|(block, _)| *block == block_number)
+            .map(|(_, receipts)| receipts.clone());
+        let fetch = fetch_swap(
+            meta.transaction_hash,
+            block_receipts,
+            live,
+            self.eth_client.clone(),
+            self.binance_client.clone(),
+            self.config.chain,
+        );
+        self.pipeline.push_back(
+            fetch
+                .map(move |fetched| {
+                    Step::Swap(Box::new(QueuedSwap {
+                        log,
+                        meta,
+                        impact_bps,
+                        fetched,
+                    }))
+                })
+                .boxed(),
+        );
+        let mut stats = self.monitor.write().unwrap();
+        stats.queue_depth += 1;
+        stats.max_queue_depth = stats.max_queue_depth.max(stats.queue_depth);
+        Ok(true)
+    }
+
+    fn queue_depth(&self) -> usize {
+        self.monitor.read().unwrap().queue_depth
+    }
+
+    /// Waits for the oldest step of the pipeline and stores its records. Never completes while the pipeline is empty,
+    /// so that it can be awaited next to the subscriptions.
+    pub async fn next_step(&mut self) {
+        let Some(step) = self.pipeline.next().await else {
+            return std::future::pending().await;
+        };
+        match step {
+            Step::Swap(swap) => {
+                let QueuedSwap {
+                    log,
+                    meta,
+                    impact_bps,
+                    fetched,
+                } = *swap;
+                if let Err(err) =
+                    fetched.and_then(|fetched| self.store(log, &meta, impact_bps, fetched))
+                {
+                    self.record_error(&meta, err);
+                }
+                let mut stats = self.monitor.write().unwrap();
+                stats.queue_depth -= 1;
+                stats.processed += 1;
+            }
+            Step::Checkpoint(block_number) => self.send_checkpoint(block_number),
+        }
+    }
+
+    /// Waits for every queued swap to be stored
+    pub async fn flush(&mut self) {
+        while !self.pipeline.is_empty() {
+            self.next_step().await;
+        }
+    }
+
+    fn record_error(&self, meta: &LogMeta, err: anyhow::Error) {
+        error!(
+            "Could not process swap of tx hash {}: {}",
+            meta.transaction_hash.encode_hex_with_prefix(),
+            err
+        );
+        let ingestion_error = IngestionError {
+            tx_hash: meta.transaction_hash.encode_hex_with_prefix(),
+            log_index: meta.log_index.as_u64() as i64,
+            block_number: meta.block_number.as_u64() as i64,
+            error: err.to_string(),
+            timestamp_ms: now_ms(),
+        };
+        if self
+            .sender
+            .send(DbRecord::IngestionError(ingestion_error.clone()))
+            .is_err()
+        {
+            error!(
+                "Could not send to queue ingestion error {:?}",
+                ingestion_error
+            );
+        }
+    }
+
+    /// Decodes a swap log. A log that cannot be decoded is recorded as an ingestion error, so that it is skipped
+    /// without stopping the processing of the next ones. The pool price after it is then unknown.
+    fn decode_log(&mut self, log: Log) -> Option<(SwapFilter, LogMeta)> {
+        let meta = LogMeta::from(&log);
+        match parse_log::<SwapFilter>(log) {
+            Ok(log) => Some((log, meta)),
+            Err(err) => {
+                self.last_sqrt_price = None;
+                self.record_error(&meta, anyhow!("could not decode swap event: {}", err));
+                None
+            }
+        }
+    }
+
+    /// Handles a log of the swap subscription. A removed log of a block whose swaps were processed
+    /// means the block was orphaned by a reorg: its swaps are rolled back and re-ingested.
+    pub async fn handle_log(&mut self, log: Log, live: bool) -> Result<bool> {
+        let meta = LogMeta::from(&log);
+        if log.removed == Some(true) {
+            let block_number = meta.block_number.as_u64();
+            if self.block_hashes.get(&block_number) == Some(&meta.block_hash) {
+                self.flush().await;
+                let head = self.eth_client.get_block_number().await?.as_u64();
+                self.reorg(block_number, meta.block_hash, None, "removed_log", head)
+                    .await?;
+            }
+            return Ok(false);
+        }
+        match self.decode_log(log) {
+            Some((log, meta)) => self.handle(log, meta, live).await,
+            None => Ok(false),
+        }
+    }
+
+    /// Handles a new head of the chain. A head that does not extend the previous one means a reorg happened,
+    /// in which case the tracked blocks are compared with the canonical chain. The stored rows are then
+    /// promoted to the finality reached at this head.
+    pub async fn on_head(&mut self, head: &Block<H256>) -> Result<()> {
+        let (Some(number), Some(hash)) = (head.number, head.hash) else {
+            return Ok(());
+        };
+        let number = number.as_u64();
+        // The same head can be polled several times
+        if self.last_head == Some((number, hash)) {
+            return Ok(());
+        }
+        // The rows of the queued swaps are rolled back or promoted once stored
+        self.flush().await;
+        let suspicious = match self.last_head {
+            Some((last_number, last_hash)) => {
+                number <= last_number
+                    || (number == last_number + 1 && head.parent_hash != last_hash)
+            }
+            None => false,
+        } || self
+            .block_hashes
+            .get(&number.saturating_sub(1))
+            .is_some_and(|parent_hash| *parent_hash != head.parent_hash);
+        self.last_head = Some((number, hash));
+        if suspicious {
+            info!(
+                "Head {} does not extend the previous one, checking for a reorg",
+                number
+            );
+            self.check_reorg(number).await?;
+        }
+        self.promote_finality(number).await;
+        Ok(())
+    }
+
+    /// Promotes the rows confirmed at `head`, and the ones of the blocks up to the `finalized` block tag
+    async fn promote_finality(&mut self, head: u64) {
+        let update = finality_at(head, &self.config.finality, &self.eth_client).await;
+        // Queued after the records of the head, so that rows are promoted once stored
+        if self
+            .sender
+            .send(DbRecord::Finality(update.clone()))
+            .is_err()
+        {
+            error!("Could not send to queue finality update {:?}", update);
+        }
+    }
+
+    /// Compares the hash of the tracked blocks up to `head` with the canonical chain,
+    /// and rolls back from the first block that was orphaned
+    async fn check_reorg(&mut self, head: u64) -> Result<()> {
+        let tracked: Vec<(u64, H256)> = self
+            .block_hashes
+            .range(..=head)
+            .map(|(block, hash)| (*block, *hash))
+            .collect();
+        for (block_number, block_hash) in tracked {
+            let canonical_hash = self
+                .eth_client
+                .get_block(block_number)
+                .await?
+                .and_then(|block| block.hash);
+            if canonical_hash != Some(block_hash) {
+                return self
+                    .reorg(
+                        block_number,
+                        block_hash,
+                        canonical_hash,
+                        "parent_hash",
+                        head,
+                    )
+                    .await;
+            }
+        }
+        Ok(())
+    }
+
+    /// Rolls back the swaps from the orphaned `block_number` onwards, and re-ingests them from the canonical chain
+    async fn reorg(
+        &mut self,
+        block_number: u64,
+        orphaned_hash: H256,
+        canonical_hash: Option<H256>,
+        detected_by: &str,
+        head: u64,
+    ) -> Result<()> {
+        warn!(
+            "Reorg detected by {} at block {} of hash {}",
+            detected_by,
+            block_number,
+            orphaned_hash.encode_hex_with_prefix()
+        );
+        // Queued after the records of the orphaned swaps, so they are stored before being rolled back
+        let reorg = Reorg {
+            pool_address: self.config.pool_address.to_lowercase(),
+            block_number: block_number as i64,
+            orphaned_block_hash: orphaned_hash.encode_hex_with_prefix(),
+            canonical_block_hash: canonical_hash.map(|hash| hash.encode_hex_with_prefix()),
+            detected_by: detected_by.to_string(),
+            swaps_rolled_back: 0,
+            timestamp_ms: now_ms(),
+        };
+        if self.sender.send(DbRecord::Reorg(reorg.clone())).is_err() {
+            error!("Could not send to queue reorg {:?}", reorg);
+        }
+        let checkpoint = block_number.saturating_sub(1);
+        self.checkpoint = Some(self.checkpoint.map_or(checkpoint, |c| c.min(checkpoint)));
+        self.last_block = None;
+        self.last_sqrt_price = None;
+        self.processed.retain(|(block, _, _)| *block < block_number);
+        self.block_hashes.retain(|block, _| *block < block_number);
+        self.catch_up(head).await
+    }
+
+    /// Processes the swaps emitted from the checkpoint to `to_block` included, e.g. while the process was down
+    /// or the subscription was reconnecting. Without checkpoint, swaps are caught up from the block of the last
+    /// processed swap, and nothing is done if no swap was processed yet, as on the first start.
+    /// The price impact of the first caught up swap is not computed, as swaps may have been missed before it.
+    pub async fn catch_up(&mut self, to_block: u64) -> Result<()> {
+        self.last_sqrt_price = None;
+        self.process_range(to_block, false).await
+    }
+
+    /// Processes the swaps emitted since the last poll up to the polled `head` included, in polling mode.
+    /// The head is handled first, as new heads received from the subscription. Polled swaps are live,
+    /// priced with the live ticker. On the first start, swaps are processed from the first polled head.
+    pub async fn poll(&mut self, head: &Block<H256>) -> Result<()> {
+        let Some(number) = head.number.map(|number| number.as_u64()) else {
+            return Ok(());
+        };
+        if self.checkpoint.is_none() && self.last_block.is_none() {
+            self.save_checkpoint(number);
+            return Ok(());
+        }
+        self.on_head(head).await?;
+        self.process_range(number, true).await
+    }
+
+    /// Processes the swaps emitted from the checkpoint to `to_block` included, see `catch_up`
+    async fn process_range(&mut self, to_block: u64, live: bool) -> Result<()> {
+        let from_block = match (self.checkpoint, self.last_block) {
+            (Some(checkpoint), _) => checkpoint + 1,
+            (None, Some(last_block)) => last_block,
+            (None, None) => return Ok(()),
+        };
+        if from_block > to_block {
+            return Ok(());
+        }
+        info!("Processing swaps from block {} to {}", from_block, to_block);
+        let filter = Filter::new()
+            .address(Address::from_str(self.config.pool_address.as_str())?)
+            .topic0(H256::from_str(self.config.swap_topic.as_str())?);
+        let logs = get_logs_adaptive(&filter, from_block, to_block, &self.eth_client).await?;
+        // The receipts of the swaps of a block are fetched in as few requests as possible, within the pipeline so
+        // that only the blocks of the queued swaps are fetched at once. The swaps whose receipt could not be
+        // fetched with their block fetch it on their own.
+        let mut block_txs: HashMap<u64, Vec<TxHash>> = HashMap::new();
+        for log in &logs {
+            if let (Some(block_number), Some(tx_hash)) = (log.block_number, log.transaction_hash) {
+                let tx_hashes = block_txs.entry(block_number.as_u64()).or_default();
+                if !tx_hashes.contains(&tx_hash) {
+                    tx_hashes.push(tx_hash);
+                }
+            }
+        }
+        for log in logs {
+            let Some((log, meta)) = self.decode_log(log) else {
+                continue;
+            };
+            let block_number = meta.block_number.as_u64();
+            if self.block_receipts.as_ref().map(|(block, _)| *block) != Some(block_number) {
+                let tx_hashes = block_txs.remove(&block_number).unwrap_or_default();
+                self.block_receipts = Some((
+                    block_number,
+                    self.fetch_block_receipts(block_number, tx_hashes),
+                ));
+            }
+            self.handle(log, meta, live).await?;
+        }
+        self.block_receipts = None;
+        self.save_checkpoint(to_block);
+        self.flush().await;
+        Ok(())
+    }
+
+    /// Fetch of the receipts of the given transactions of a block, only started once awaited
+    fn fetch_block_receipts(&self, block_number: u64, tx_hashes: Vec<TxHash>) -> BlockReceipts {
+        let receipt_fetcher = self.receipt_fetcher.clone();
+        async move {
+            match receipt_fetcher
+                .get_block_tx_receipts(block_number, &tx_hashes)
+                .await
+            {
+                Ok(receipts) => Arc::new(receipts),
+                Err(err) => {
+                    warn!(
+                        "Could not get tx receipts of block {} ahead: {}",
+                        block_number, err
+                    );
+                    Arc::new(HashMap::new())
+                }
+            }
+        }
+        .boxed()
+        .shared()
+    }
+
+    fn save_checkpoint(&mut self, block_number: u64) {
+        self.checkpoint = Some(block_number);
+        self.processed.retain(|(block, _, _)| *block > block_number);
+        // Sent after the records of the block, so it is only stored once they are
+        if self.pipeline.is_empty() {
+            self.send_checkpoint(block_number);
+        } else {
+            self.pipeline
+                .push_back(ready(Step::Checkpoint(block_number)).boxed());
+        }
+    }
+
+    fn send_checkpoint(&self, block_number: u64) {
+        let checkpoint = Checkpoint {
+            pool_address: self.config.pool_address.to_lowercase(),
+            block_number: block_number as i64,
+        };
+        if self
+            .sender
+            .send(DbRecord::Checkpoint(checkpoint.clone()))
+            .is_err()
+        {
+            error!("Could not send to queue checkpoint {:?}", checkpoint);
+        }
+    }
+
+    /// Stores the fee of the swap transaction and the swap, and observes the basis and alerts
+    fn store(
+        &self,
+        log: SwapFilter,
+        meta: &LogMeta,
+        impact_bps: Option<f64>,
+        fetched: FetchedSwap,
+    ) -> Result<()> {
+        let config = &self.config;
+        let sender = &self.sender;
+        let FetchedSwap {
+            tx_receipt,
+            data,
+            eth_price,
+            timestamp_ms,
+        } = fetched;
+        let swap_amounts0 = receipt_swap_amounts0(
+            &tx_receipt,
+            H256::from_str(config.swap_topic.as_str())?,
+            meta.address,
+        );
+
+        // The tx fee is stored once, with the first swap of the transaction on the pool
+        let first_swap = swap_amounts0
+            .first()
+            .is_none_or(|(log_index, _)| *log_index == meta.log_index);
+        if first_swap {
+            info!("Sending new data to queue: {:?}", data.clone());
+            if sender.send(data.clone().into()).is_err() {
+                error!("Could not send to queue tx fee {:?}", data.clone());
+            }
+        }
+
+        // Split the tx fee across the swaps of the transaction on the pool
+        let notionals: Vec<U256> = swap_amounts0
+            .iter()
+            .map(|(_, amount0)| amount0.unsigned_abs())
+            .collect();
+        let shares = config
+            .gas_attribution
+            .split(U256::from_dec_str(data.fee_wei.as_str())?, &notionals);
+        let fee_wei = swap_amounts0
+            .iter()
+            .zip(shares)
+            .find(|((log_index, _), _)| *log_index == meta.log_index)
+            .map(|(_, share)| share.to_string());
+
+        // Store the swap for the per-address analytics
+        let pool_price = amounts_to_price(log.amount_0, log.amount_1)?;
+        let notional_usd = amount_to_notional_usd(log.amount_0)?;
+        let (gas_usd, gas_share) = match &fee_wei {
+            Some(fee_wei) => {
+                let (gas_usd, gas_share) =
+                    gas_share_of_notional(fee_wei, data.eth_usdt_price.as_str(), notional_usd)?;
+                (Some(gas_usd), gas_share)
+            }
+            None => (None, None),
+        };
+        let swap = Swap {
+            tx_hash: data.tx_hash.clone(),
+            log_index: meta.log_index.as_u64() as i64,
+            pool_address: meta.address.encode_hex_with_prefix(),
+            block_number: meta.block_number.as_u64() as i64,
+            block_hash: Some(meta.block_hash.encode_hex_with_prefix()),
+            timestamp_ms,
+            sender: log.sender.encode_hex_with_prefix(),
+            recipient: log.recipient.encode_hex_with_prefix(),
+            tx_from: tx_receipt.from.encode_hex_with_prefix(),
+            amount0: log.amount_0.to_string(),
+            amount1: log.amount_1.to_string(),
+            price: pool_price,
+            notional_usd,
+            fee_wei,
+            gas_usd,
+            gas_share,
+        };
+        if sender.send(DbRecord::Swap(swap.clone())).is_err() {
+            error!("Could not send to queue swap {:?}", swap);
+        }
+
+        // Compare the swap price with the CEX price
+        let (point, opportunity) = observe_basis(
+            &config.basis,
+            data.tx_hash.clone(),
+            meta.log_index.as_u64() as i64,
+            meta.block_number.as_u64() as i64,
+            timestamp_ms,
+            pool_price,
+            eth_price,
+            tx_receipt.effective_gas_price.unwrap_or_default(),
+        )?;
+        if sender.send(DbRecord::Basis(point.clone())).is_err() {
+            error!("Could not send to queue basis {:?}", point);
+        }
+        if let Some(opportunity) = opportunity {
+            warn!("Arbitrage opportunity: {:?}", opportunity);
+            if sender
+                .send(DbRecord::Opportunity(opportunity.clone()))
+                .is_err()
+            {
+                error!("Could not send to queue opportunity {:?}", opportunity);
+            }
+        }
+
+        // Raise alerts for large swaps
+        if let Some(thresholds) = config.alerts.thresholds(config.pool_address.as_str()) {
+            let alerts = check_swap(
+                thresholds,
+                data.tx_hash.as_str(),
+                meta.log_index.as_u64(),
+                notional_usd,
+                impact_bps,
+            );
+            for alert in alerts {
+                warn!("Swap alert: {:?}", alert);
+                tokio::spawn(raise_alert(
+                    alert,
+                    config.alerts.webhooks.clone(),
+                    config.alerts.max_retries,
+                    sender.clone(),
+                    self.db_connection.clone(),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Blocks confirmed at `head`, and the `finalized` block tag if the node supports it
+pub async fn finality_at(
+    head: u64,
+    settings: &FinalitySettings,
+    eth_client: &EthClient,
+) -> FinalityUpdate {
+    let finalized_block = match eth_client.get_block(BlockNumber::Finalized).await {
+        Ok(block) => block.and_then(|block| block.number),
+        Err(err) => {
+            warn!("Could not get finalized block: {}", err);
+            None
+        }
+    };
+    FinalityUpdate {
+        confirmed_block: head.saturating_sub(settings.confirmations) as i64,
+        finalized_block: finalized_block.map(|number| number.as_u64() as i64),
+    }
+}
+
+/// Fetches the receipt, unless fetched with its block, block and ETH/USDT price of a swap transaction,
+/// and computes its fee
+async fn fetch_swap(
+    tx_hash: TxHash,
+    block_receipts: Option<BlockReceipts>,
+    live: bool,
+    eth_client: EthClient,
+    binance_client: BinanceClient,
+    chain: Chain,
+) -> Result<FetchedSwap> {
+    let tx_receipt = match block_receipts {
+        Some(block_receipts) => block_receipts.await.get(&tx_hash).cloned(),
+        None => None,
+    };
+    let tx_receipt = match tx_receipt {
+        Some(tx_receipt) => tx_receipt,
+        None => try_get_tx_receipt(tx_hash, &eth_client).await?,
+    };
+    let block = get_receipt_block(&tx_receipt, &eth_client).await?;
+
+    // The swap happened at the time of its block, only its price differs between live and past swaps
+    let timestamp_ms = block.timestamp.as_u64() * 1000;
+    let eth_usdt_price = if live {
+        info!("Getting ticker for ETHUSDT");
+        binance_client.get_ticker("ETHUSDT").await?.price
+    } else {
+        let ticker = binance_client.get_kline("ETHUSDT", timestamp_ms).await?;
+        ticker[0].clone().open_price
+    };
+    let eth_price = eth_usdt_price.parse::<f64>()?;
+
+    let transaction = get_transaction_if_needed(&tx_receipt, &eth_client).await?;
+    let data = TxFee::new(
+        &tx_receipt,
+        transaction.as_ref(),
+        &block,
+        eth_usdt_price,
+        chain,
+    )?;
+    Ok(FetchedSwap {
+        tx_receipt,
+        data,
+        eth_price,
+        timestamp_ms: timestamp_ms as i64,
+    })
+}
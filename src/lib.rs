@@ -1,31 +1,65 @@
+pub mod alerts;
+pub mod backfill;
+pub mod basis;
 pub mod binance_client;
+pub mod blocks;
 pub mod db;
+pub mod estimate;
+pub mod fee;
+pub mod gas;
+pub mod ingest;
+pub mod oracle;
+pub mod receipts;
+pub mod rpc;
+pub mod trace;
 pub mod util;
 
+use crate::alerts::AlertSettings;
+use crate::backfill::BackfillSettings;
+use crate::basis::BasisSettings;
 use crate::binance_client::BinanceClient;
-use crate::db::{get_tx_fee_from_db, DatabaseSettings, TxFee};
-use crate::util::{compute_gas_fee_eth, try_get_tx_receipt, tx_hash_to_price};
+use crate::db::{
+    get_address_activity_from_db, get_alert_deliveries_from_db, get_alerts_from_db,
+    get_basis_from_db, get_block_gas_from_db, get_block_header_from_db, get_block_headers_from_db,
+    get_checkpoint_from_db, get_gas_share_buckets_from_db, get_ingestion_errors_from_db,
+    get_opportunities_from_db, get_pool_priority_fees_from_db, get_pool_swap_gas_from_db,
+    get_reorgs_from_db, get_tx_fee_from_db, AddressRole, BlockGas, BlockHeader, DatabaseSettings,
+    DbRecord, Finality, FinalityUpdate, TxFee,
+};
+use crate::estimate::{EstimateSettings, PoolState, QuoteOverflow, SwapEstimate};
+use crate::fee::{Chain, FeeAmount, GasAttribution, RankingSettings};
+use crate::ingest::{
+    finality_at, FinalitySettings, IngestionMode, IngestionSettings, PipelineMonitor,
+    PipelineSettings, PipelineStats, ReorgSettings, SubscriptionSettings, SwapProcessor,
+};
+use crate::oracle::{update_gas_oracle, FeeWindow, GasOracle, GasOracleSettings, GasSuggestions};
+use crate::receipts::{ReceiptFetcher, ReceiptSettings};
+use crate::rpc::{connect_http, connect_ws, EthClient, RpcSettings};
+use crate::trace::{trace_transaction, GasBreakdown};
+use crate::util::{
+    get_receipt_block, get_transaction_if_needed, try_get_tx_receipt, tx_hash_to_price,
+};
 use actix_web::dev::Server;
 use actix_web::http::header::ContentType;
 use actix_web::{get, web, App, HttpResponse, HttpServer, Responder};
-use anyhow::Result;
-use config;
+use anyhow::{anyhow, Result};
 use config::{Config, File, FileFormat};
 use ethers::addressbook::Address;
-use ethers::contract::{abigen, Contract, LogMeta};
-use ethers::middleware::Middleware;
-use ethers::prelude::{BlockId, Http, Provider, TxHash, ValueOrArray, Ws, H256};
+use ethers::contract::abigen;
+use ethers::prelude::{
+    Block, BlockNumber, Filter, Middleware, TransactionReceipt, TxHash, H256, U256,
+};
 use ethers::utils::hex::ToHexExt;
 use futures_util::StreamExt;
 use serde::Deserialize;
 use serde_json::json;
-use sqlx;
 use sqlx::PgPool;
 use std::collections::HashMap;
 use std::str::FromStr;
 use std::sync::mpsc::Sender;
-use std::sync::Arc;
-use tracing::{error, info};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::{error, info, warn};
 
 abigen!(
     AggregatorInterface,
@@ -34,6 +68,17 @@ abigen!(
     ]"#,
 );
 
+abigen!(
+    UniswapV3Pool,
+    r#"[
+        function slot0() external view returns (uint160 sqrtPriceX96, int24 tick, uint16 observationIndex, uint16 observationCardinality, uint16 observationCardinalityNext, uint8 feeProtocol, bool unlocked)
+        function liquidity() external view returns (uint128)
+        function fee() external view returns (uint24)
+        function token0() external view returns (address)
+        function token1() external view returns (address)
+    ]"#,
+);
+
 abigen!(
     IERC20,
     r#"[
@@ -45,12 +90,30 @@ abigen!(
 #[derive(Clone, Debug, serde::Deserialize)]
 pub struct AppConfig {
     pub application_port: u16,
-    pub rpc_url_http: String,
-    pub rpc_url_ws: String,
+    pub chain: Chain,
+    /// How the fee of a transaction is split across its swaps
+    pub gas_attribution: GasAttribution,
+    /// HTTP endpoints, requests are routed to the healthiest one
+    pub rpc_urls_http: Vec<String>,
+    /// WS endpoints, subscriptions rotate over them on reconnection
+    pub rpc_urls_ws: Vec<String>,
+    pub rpc: RpcSettings,
     pub pool_address: String,
     pub swap_topic: String,
     pub binance_host: String,
     pub database: DatabaseSettings,
+    pub basis: BasisSettings,
+    pub alerts: AlertSettings,
+    pub ranking: RankingSettings,
+    pub oracle: GasOracleSettings,
+    pub estimate: EstimateSettings,
+    pub backfill: BackfillSettings,
+    pub ingestion: IngestionSettings,
+    pub subscription: SubscriptionSettings,
+    pub reorg: ReorgSettings,
+    pub finality: FinalitySettings,
+    pub pipeline: PipelineSettings,
+    pub receipts: ReceiptSettings,
 }
 
 impl AppConfig {
@@ -65,28 +128,45 @@ impl AppConfig {
 #[derive(Clone)]
 pub struct Application {
     pub config: AppConfig,
-    pub eth_client: Provider<Http>,
+    pub eth_client: EthClient,
+    pub receipt_fetcher: ReceiptFetcher,
     pub binance_client: BinanceClient,
-    pub sender: Sender<TxFee>,
+    pub sender: Sender<DbRecord>,
     pub db_connection: PgPool,
+    pub gas_oracle: GasOracle,
+    pub pipeline_monitor: PipelineMonitor,
 }
 
 impl Application {
     pub fn new(
         config: AppConfig,
-        sender: Sender<TxFee>,
+        sender: Sender<DbRecord>,
         db_connection: PgPool,
     ) -> Result<Application> {
+        let eth_client = connect_http(&config.rpc_urls_http, &config.rpc).unwrap();
         Ok(Self {
             config: config.clone(),
-            eth_client: Provider::<Http>::try_from(config.rpc_url_http.as_str()).unwrap(),
+            receipt_fetcher: ReceiptFetcher::new(eth_client.clone(), config.receipts.clone()),
+            eth_client,
             binance_client: BinanceClient::new(config.binance_host.as_str()),
             sender,
             db_connection,
+            gas_oracle: Arc::new(RwLock::new(FeeWindow::new(config.oracle.window_blocks))),
+            pipeline_monitor: PipelineStats::monitor(&config.pipeline),
         })
     }
 
-    /// Given a tx hash, tries to get the tx fee from db.
+    /// Slow, standard and fast fee suggestions from the gas oracle, priced at the live ETH/USDT price
+    pub async fn suggest_gas(&self) -> Result<GasSuggestions> {
+        let ticker = self.binance_client.get_ticker("ETHUSDT").await?;
+        self.gas_oracle
+            .read()
+            .unwrap()
+            .suggest(ticker.price, self.config.basis.swap_gas)?
+            .ok_or(anyhow!("gas oracle has not received any block yet"))
+    }
+
+    /// Given a tx hash, tries to get the tx fee from db, ranked if it was stored unranked.
     /// If the tx hash is not found, computes the tx fee on-chain, and then stores the result in db.
     pub async fn try_get_or_insert(&self, tx_hash: &TxHash) -> Result<TxFee> {
         // Try get fee from db
@@ -94,9 +174,18 @@ impl Application {
             "Try get from db tx_hash={}",
             tx_hash.encode_hex_with_prefix()
         );
-        let res = get_tx_fee_from_db(tx_hash, &self.db_connection).await;
-        if res.is_ok() {
-            return Ok(res.unwrap());
+        if let Ok(res) = get_tx_fee_from_db(tx_hash, &self.db_connection).await {
+            if !is_unranked(&res) {
+                return Ok(res);
+            }
+            return match self.eth_client.get_transaction_receipt(*tx_hash).await {
+                Ok(Some(tx_receipt)) => Ok(self.rank_stored_tx_fee(res, &tx_receipt).await),
+                Ok(None) => Ok(res),
+                Err(err) => {
+                    warn!("Could not rank fee for tx_hash={}: {}", res.tx_hash, err);
+                    Ok(res)
+                }
+            };
         }
 
         // If fee not found, get it from blockchain
@@ -113,7 +202,7 @@ impl Application {
         };
 
         // Send result to queue to be inserted in db
-        if self.sender.send(res.clone()).is_err() {
+        if self.sender.send(res.clone().into()).is_err() {
             error!("Could not send to queue tx fee {:?}", res.clone());
         }
 
@@ -130,98 +219,638 @@ impl Application {
 
         // Get transaction receipt for given transaction hash
         let tx_receipt = try_get_tx_receipt(*tx_hash, &self.eth_client).await?;
+        let heads = self.get_finality_update().await;
+        self.get_receipt_tx_fee(&tx_receipt, heads.as_ref(), true)
+            .await
+    }
 
-        // Use transaction receipt to compute the gas fee: gas_fee = gas_used * gas_price
-        let fee_eth = compute_gas_fee_eth(&tx_receipt).await?;
+    /// Finality reached at the current head, to set the finality of the fees computed on demand
+    pub async fn get_finality_update(&self) -> Option<FinalityUpdate> {
+        match self.eth_client.get_block_number().await {
+            Ok(head) => {
+                Some(finality_at(head.as_u64(), &self.config.finality, &self.eth_client).await)
+            }
+            Err(err) => {
+                warn!("Could not get head: {}", err);
+                None
+            }
+        }
+    }
 
+    /// Given a tx receipt, computes the tx fee priced with the ETH/USDT kline at the time of its block.
+    /// The fee is as final as its block at `heads`, pending if not given. Its priority fee is ranked if `rank`,
+    /// which costs the receipts of the whole block and a lookup of the stored swaps of the pool.
+    pub async fn get_receipt_tx_fee(
+        &self,
+        tx_receipt: &TransactionReceipt,
+        heads: Option<&FinalityUpdate>,
+        rank: bool,
+    ) -> Result<TxFee> {
         // Get from Binance the price of ETH/USDT at the time of the transaction (with 1 min precision)
+        let block = self.get_receipt_block(tx_receipt).await?;
+        let timestamp_ms = block.timestamp.as_u64() * 1000;
+        let ticker = self
+            .binance_client
+            .get_kline("ETHUSDT", timestamp_ms)
+            .await?;
+        let eth_usdt_price = ticker[0].clone().open_price;
+
+        // Use transaction receipt and block to compute the gas fee and its components
+        let transaction = get_transaction_if_needed(tx_receipt, &self.eth_client).await?;
+        let mut fee = TxFee::new(
+            tx_receipt,
+            transaction.as_ref(),
+            &block,
+            eth_usdt_price,
+            self.config.chain,
+        )?;
+        if let (Some(heads), Some(block_number)) = (heads, fee.block_number) {
+            fee.finality = heads.finality(block_number);
+        }
+
+        // Rank the priority fee, the fee is still returned if it cannot be ranked
+        if !rank {
+            return Ok(fee);
+        }
+        if let Err(err) = self.rank_tx_fee(&mut fee, tx_receipt).await {
+            warn!("Could not rank fee for tx_hash={}: {}", fee.tx_hash, err);
+        }
+        Ok(fee)
+    }
+
+    /// Ranks a stored fee that was not ranked when it was stored, e.g. by the watcher or a backfill,
+    /// and stores its ranking. The fee is still returned if it cannot be ranked.
+    async fn rank_stored_tx_fee(&self, mut fee: TxFee, tx_receipt: &TransactionReceipt) -> TxFee {
+        match self.rank_tx_fee(&mut fee, tx_receipt).await {
+            Ok(()) if fee.block_percentile.is_some() => {
+                if self
+                    .sender
+                    .send(DbRecord::TxFeeRanking(fee.clone()))
+                    .is_err()
+                {
+                    error!("Could not send to queue ranking of tx fee {:?}", fee);
+                }
+            }
+            Ok(()) => {}
+            Err(err) => warn!("Could not rank fee for tx_hash={}: {}", fee.tx_hash, err),
+        }
+        fee
+    }
+
+    /// Get the block of a tx receipt from the stored block headers, or on-chain if it is not stored
+    async fn get_receipt_block(&self, tx_receipt: &TransactionReceipt) -> Result<Block<H256>> {
+        if let Some(block_hash) = tx_receipt.block_hash {
+            let block_hash = block_hash.encode_hex_with_prefix();
+            if let Ok(header) = get_block_header_from_db(&block_hash, &self.db_connection).await {
+                return header.to_block();
+            }
+        }
+        get_receipt_block(tx_receipt, &self.eth_client).await
+    }
+
+    /// Rank the priority fee of a transaction against the other transactions of its block and
+    /// against the stored swaps on the same pool in the previous `ranking.lookback_blocks` blocks
+    async fn rank_tx_fee(&self, fee: &mut TxFee, tx_receipt: &TransactionReceipt) -> Result<()> {
+        let block_number = tx_receipt
+            .block_number
+            .ok_or(anyhow!("block number not found in tx receipt"))?;
+        let block_hash = tx_receipt
+            .block_hash
+            .ok_or(anyhow!("block hash not found in tx receipt"))?;
+        let block_receipts = self
+            .receipt_fetcher
+            .get_cached_block_receipts(block_number.as_u64(), block_hash)
+            .await?;
+
+        // Pool of the first swap of the transaction, if any
+        let swap_topic = H256::from_str(self.config.swap_topic.as_str())?;
+        let swap_pool = tx_receipt
+            .logs
+            .iter()
+            .find(|log| log.topics.first() == Some(&swap_topic))
+            .map(|log| log.address.encode_hex_with_prefix());
+        let mut pool_priority_fees = vec![];
+        if let Some(swap_pool) = swap_pool {
+            let to_block = block_number.as_u64() as i64 - 1;
+            let from_block = to_block - self.config.ranking.lookback_blocks as i64 + 1;
+            for priority_fee in get_pool_priority_fees_from_db(
+                swap_pool.as_str(),
+                from_block,
+                to_block,
+                fee.tx_hash.as_str(),
+                &self.db_connection,
+            )
+            .await?
+            {
+                pool_priority_fees.push(U256::from_dec_str(priority_fee.as_str())?);
+            }
+        }
+
+        fee.rank(&block_receipts, &pool_priority_fees)
+    }
+
+    /// Given a block number, tries to get the block gas statistics from db.
+    /// If the block is not found, computes the statistics on-chain, and then stores the result in db.
+    pub async fn try_get_or_insert_block_gas(&self, block_number: u64) -> Result<BlockGas> {
+        if let Ok(res) = get_block_gas_from_db(block_number as i64, &self.db_connection).await {
+            return Ok(res);
+        }
+
+        let res = self.get_block_gas(block_number).await?;
+        if self.sender.send(DbRecord::BlockGas(res.clone())).is_err() {
+            error!("Could not send to queue block gas {:?}", res);
+        }
+        Ok(res)
+    }
+
+    /// Given a block number, computes the gas price statistics of its transactions
+    pub async fn get_block_gas(&self, block_number: u64) -> Result<BlockGas> {
+        info!("Getting gas of block {}", block_number);
         let block = self
             .eth_client
-            .get_block(BlockId::Hash(tx_receipt.block_hash.unwrap()))
+            .get_block(block_number)
             .await?
-            .unwrap();
-        let timestamp_ms = block.timestamp.as_u64() * 1000;
+            .ok_or(anyhow!("block {} not found", block_number))?;
+        let receipts = self
+            .receipt_fetcher
+            .get_block_receipts(block_number)
+            .await?;
+        // Receipts from older nodes have no effective gas price: it is then derived from the transactions
+        let transactions = if receipts
+            .iter()
+            .any(|receipt| receipt.effective_gas_price.is_none())
+        {
+            self.eth_client
+                .get_block_with_txs(block_number)
+                .await?
+                .ok_or(anyhow!("block {} not found", block_number))?
+                .transactions
+        } else {
+            Vec::new()
+        };
+
+        // Get from Binance the price of ETH/USDT at the time of the block (with 1 min precision)
         let ticker = self
             .binance_client
-            .get_kline("ETHUSDT", timestamp_ms)
+            .get_kline("ETHUSDT", block.timestamp.as_u64() * 1000)
             .await?;
         let eth_usdt_price = ticker[0].clone().open_price;
-        let eth_usdt_price: f64 = eth_usdt_price.parse().unwrap();
 
-        // Compute gas fee in USDT
-        let fee_usdt = fee_eth * eth_usdt_price;
+        BlockGas::new(
+            &block,
+            &receipts,
+            &transactions,
+            eth_usdt_price,
+            self.config.basis.swap_gas,
+        )
+    }
+
+    /// Estimates the all-in cost of swapping `amount_in` of `token_in` on a pool: the output amount from the pool
+    /// state at the latest block, and the gas cost at the current base fee plus the typical priority fee
+    pub async fn estimate_swap(
+        &self,
+        pool_address: Address,
+        amount_in: U256,
+        token_in: Address,
+    ) -> Result<SwapEstimate> {
+        let block = self
+            .eth_client
+            .get_block(BlockNumber::Latest)
+            .await?
+            .ok_or(anyhow!("latest block not found"))?;
+        let block_number = block.number.ok_or(anyhow!("block number not found"))?;
+        let base_fee_per_gas = block
+            .base_fee_per_gas
+            .ok_or(anyhow!("base fee not found in block {}", block_number))?;
 
-        Ok(TxFee {
-            tx_hash: tx_receipt.transaction_hash.encode_hex_with_prefix(),
-            fee_eth,
-            fee_usdt,
+        // Read the pool state at the latest block
+        let contract = UniswapV3Pool::new(pool_address, Arc::new(self.eth_client.clone()));
+        let (sqrt_price_x96, ..) = contract.slot_0().block(block_number).call().await?;
+        let liquidity = contract.liquidity().block(block_number).call().await?;
+        let fee = contract.fee().block(block_number).call().await?;
+        let token0 = contract.token_0().block(block_number).call().await?;
+        let token1 = contract.token_1().block(block_number).call().await?;
+        let (zero_for_one, token_out) = if token_in == token0 {
+            (true, token1)
+        } else if token_in == token1 {
+            (false, token0)
+        } else {
+            return Err(anyhow!(
+                "token {} is not traded on pool {}",
+                token_in.encode_hex_with_prefix(),
+                pool_address.encode_hex_with_prefix()
+            ));
+        };
+        let state = PoolState {
+            sqrt_price_x96,
+            liquidity,
+            fee,
+        };
+        let quote = state.quote(amount_in, zero_for_one)?;
+
+        // Estimate gas from the recent swaps stored for the pool
+        let (median_gas, gas_sample_size) = get_pool_swap_gas_from_db(
+            pool_address.encode_hex_with_prefix().as_str(),
+            self.config.estimate.gas_sample_swaps,
+            &self.db_connection,
+        )
+        .await?;
+        let gas_estimate = median_gas
+            .map(|gas| gas as u64)
+            .unwrap_or(self.config.basis.swap_gas);
+
+        // Typical priority fee from the gas oracle, or from the node until the oracle has received a block
+        let oracle_priority_fee = self.gas_oracle.read().unwrap().priority_fee(1);
+        let priority_fee_per_gas = match oracle_priority_fee {
+            Some(priority_fee) => priority_fee,
+            None => {
+                self.eth_client
+                    .request::<_, U256>("eth_maxPriorityFeePerGas", ())
+                    .await?
+            }
+        };
+
+        let ticker = self.binance_client.get_ticker("ETHUSDT").await?;
+        let gas_cost = (base_fee_per_gas + priority_fee_per_gas) * gas_estimate;
+        Ok(SwapEstimate {
+            pool_address: pool_address.encode_hex_with_prefix(),
+            block_number: block_number.as_u64(),
+            token_in: token_in.encode_hex_with_prefix(),
+            token_out: token_out.encode_hex_with_prefix(),
+            amount_in: amount_in.to_string(),
+            amount_out: quote.amount_out.to_string(),
+            pool_fee: quote.fee_amount.to_string(),
+            price_impact_bps: quote.price_impact_bps(sqrt_price_x96),
+            gas_estimate,
+            gas_sample_size,
+            base_fee_per_gas: base_fee_per_gas.to_string(),
+            priority_fee_per_gas: priority_fee_per_gas.to_string(),
+            gas_cost: FeeAmount::new(gas_cost.to_string().as_str(), ticker.price.as_str())?,
+            eth_usdt_price: ticker.price,
         })
     }
 
+    /// Given a tx hash, traces the transaction and breaks down its gas used between the watched pool's swap,
+    /// the router overhead and token transfers
+    pub async fn get_gas_breakdown(&self, tx_hash: TxHash) -> Result<GasBreakdown> {
+        info!("Tracing tx hash {}", tx_hash.encode_hex_with_prefix());
+        let trace = trace_transaction(tx_hash, &self.eth_client).await?;
+        Ok(GasBreakdown::new(
+            tx_hash,
+            &trace,
+            self.config.pool_address.parse()?,
+        ))
+    }
+
     /// Given an array of tx hashes, returns a map of tx hashes to their corresponding tx fees in USDT.
-    pub async fn get_tx_fee_batch(&self, tx_hashes: Vec<String>) -> Result<HashMap<TxHash, f64>> {
+    pub async fn get_tx_fee_batch(
+        &self,
+        tx_hashes: Vec<String>,
+        min_finality: Option<Finality>,
+    ) -> Result<HashMap<TxHash, f64>> {
         let mut res: HashMap<TxHash, f64> = HashMap::new();
+        for (tx_hash, fee) in self.get_tx_fee_batch_exact(tx_hashes, min_finality).await? {
+            res.insert(tx_hash, fee.fee_usdt()?.parse()?);
+        }
+        Ok(res)
+    }
+
+    /// Given an array of tx hashes, returns a map of tx hashes to their corresponding exact tx fees.
+    /// Fees less final than `min_finality`, if given, are left out.
+    /// The receipts of the transactions whose fee is not stored are fetched in batches.
+    pub async fn get_tx_fee_batch_exact(
+        &self,
+        tx_hashes: Vec<String>,
+        min_finality: Option<Finality>,
+    ) -> Result<HashMap<TxHash, TxFee>> {
+        let mut fees: Vec<(TxHash, TxFee)> = vec![];
+        let mut unranked: Vec<(TxHash, TxFee)> = vec![];
+        let mut missing: Vec<TxHash> = vec![];
         for tx_hash_str in tx_hashes {
-            let tx_hash = TxHash::from_str(tx_hash_str.as_str());
-            if tx_hash.is_err() {
+            let Ok(tx_hash) = TxHash::from_str(tx_hash_str.as_str()) else {
                 error!("Invalid tx hash {}", tx_hash_str);
                 continue;
+            };
+            match get_tx_fee_from_db(&tx_hash, &self.db_connection).await {
+                Ok(fee) if is_unranked(&fee) => unranked.push((tx_hash, fee)),
+                Ok(fee) => fees.push((tx_hash, fee)),
+                Err(_) if !missing.contains(&tx_hash) => missing.push(tx_hash),
+                Err(_) => {}
+            }
+        }
+
+        let heads = if missing.is_empty() {
+            None
+        } else {
+            self.get_finality_update().await
+        };
+        let mut receipt_hashes = missing.clone();
+        receipt_hashes.extend(unranked.iter().map(|(tx_hash, _)| *tx_hash));
+        let mut receipts = match self.receipt_fetcher.get_receipts(&receipt_hashes).await {
+            Ok(receipts) => receipts,
+            Err(err) => {
+                warn!("Could not get tx receipts of the batch: {}", err);
+                HashMap::new()
+            }
+        };
+        for (tx_hash, fee) in unranked {
+            let fee = match receipts.get(&tx_hash) {
+                Some(tx_receipt) => self.rank_stored_tx_fee(fee, tx_receipt).await,
+                None => fee,
+            };
+            fees.push((tx_hash, fee));
+        }
+        for tx_hash in missing {
+            // Transactions without receipt in the batch are retried one by one
+            let Some(tx_receipt) = receipts.remove(&tx_hash) else {
+                if let Ok(fee) = self.try_get_or_insert(&tx_hash).await {
+                    fees.push((tx_hash, fee));
+                }
+                continue;
+            };
+            match self
+                .get_receipt_tx_fee(&tx_receipt, heads.as_ref(), true)
+                .await
+            {
+                Ok(fee) => {
+                    if self.sender.send(fee.clone().into()).is_err() {
+                        error!("Could not send to queue tx fee {:?}", fee);
+                    }
+                    fees.push((tx_hash, fee));
+                }
+                Err(err) => error!(
+                    "Could not get fee for tx_hash={}: {}",
+                    tx_hash.encode_hex_with_prefix(),
+                    err
+                ),
             }
-            let tx_hash = tx_hash.unwrap();
-            let tx_fee_res = self.try_get_or_insert(&tx_hash).await;
-            if tx_fee_res.is_ok() {
-                res.insert(tx_hash, tx_fee_res.unwrap().fee_usdt);
+        }
+
+        let mut res: HashMap<TxHash, TxFee> = HashMap::new();
+        for (tx_hash, fee) in fees {
+            if min_finality.is_some_and(|min| fee.finality < min) {
+                info!(
+                    "Leaving out {} fee of tx_hash={}",
+                    fee.finality.as_str(),
+                    tx_hash.encode_hex_with_prefix()
+                );
+                continue;
             }
+            res.insert(tx_hash, fee);
         }
         Ok(res)
     }
 }
 
-/// Listen to event logs and store in db the tx fees
-#[allow(unreachable_code)]
-pub async fn subscribe_logs(config: AppConfig, sender: Sender<TxFee>) -> Result<()> {
-    let binance_client = BinanceClient::new(config.binance_host.as_str());
-    let eth_client = Provider::<Http>::try_from(config.rpc_url_http.as_str()).unwrap();
-    let ws_client = Provider::<Ws>::connect(config.rpc_url_ws.as_str()).await?;
-    let ws_client = Arc::new(ws_client);
+/// Whether a stored fee of a mined transaction was stored without its ranking
+fn is_unranked(fee: &TxFee) -> bool {
+    fee.block_number.is_some() && fee.block_percentile.is_none()
+}
+
+/// Listen to new block headers, store them in db and keep the gas oracle up to date.
+/// New blocks are received from the WS subscription, or polled over HTTP in polling mode.
+/// Like the swap ingestion, it is restarted with exponential backoff whenever it fails or ends.
+pub async fn subscribe_blocks(config: AppConfig, sender: Sender<DbRecord>, gas_oracle: GasOracle) {
+    let mut attempt = 0;
+    loop {
+        match run_block_subscription(&config, &sender, &gas_oracle, &mut attempt).await {
+            Ok(()) => warn!("Block ingestion ended"),
+            Err(err) => error!("Block ingestion failed: {}", err),
+        }
+        let backoff = config.subscription.backoff(attempt);
+        attempt += 1;
+        info!(
+            "Restarting block ingestion in {} ms (attempt {})",
+            backoff.as_millis(),
+            attempt
+        );
+        tokio::time::sleep(backoff).await;
+    }
+}
 
-    let event = Contract::event_of_type::<SwapFilter>(ws_client)
-        .address(ValueOrArray::Array(vec![config.pool_address.parse()?]));
+/// Subscribes to the new heads, or polls them, and handles every new block until the subscription ends
+async fn run_block_subscription(
+    config: &AppConfig,
+    sender: &Sender<DbRecord>,
+    gas_oracle: &GasOracle,
+    attempt: &mut u32,
+) -> Result<()> {
+    let eth_client = connect_http(&config.rpc_urls_http, &config.rpc)?;
+    if config.ingestion.mode == IngestionMode::Polling {
+        *attempt = 0;
+        return poll_blocks(config, sender, gas_oracle, &eth_client).await;
+    }
+    let ws_client = connect_ws(&config.rpc_urls_ws, *attempt).await?;
 
-    let mut stream = event.subscribe_with_meta().await?;
+    let mut stream = ws_client.subscribe_blocks().await?;
+    *attempt = 0;
+    info!("Waiting for new blocks...");
+    while let Some(block) = stream.next().await {
+        handle_new_block(&block, config, sender, gas_oracle, &eth_client).await;
+    }
+    Ok(())
+}
+
+/// Polls the head every `ingestion.poll_interval_ms` and handles every block mined since the previous poll
+async fn poll_blocks(
+    config: &AppConfig,
+    sender: &Sender<DbRecord>,
+    gas_oracle: &GasOracle,
+    eth_client: &EthClient,
+) -> Result<()> {
+    let interval = Duration::from_millis(config.ingestion.poll_interval_ms);
+    let mut last_block: Option<u64> = None;
+    info!("Polling new blocks...");
     loop {
-        info!("Waiting for swap event...");
-        while let Some(Ok((_log, meta))) = stream.next().await {
-            let meta: LogMeta = meta;
-            let tx_receipt = try_get_tx_receipt(meta.transaction_hash, &eth_client).await?;
-            let fee_eth = compute_gas_fee_eth(&tx_receipt).await?;
-
-            info!("Getting ticker for ETHUSDT");
-            let ticker = binance_client.get_ticker("ETHUSDT").await?;
-            let eth_price = ticker.price.parse::<f64>()?;
-            let fee_usdt = fee_eth * eth_price;
-
-            let data = TxFee {
-                tx_hash: tx_receipt.transaction_hash.encode_hex_with_prefix(),
-                fee_eth,
-                fee_usdt,
-            };
-            info!("Sending new data to queue: {:?}", data.clone());
-            if sender.send(data.clone()).is_err() {
-                error!("Could not send to queue tx fee {:?}", data.clone());
+        match eth_client.get_block_number().await {
+            Ok(head) => {
+                let head = head.as_u64();
+                let from_block = last_block.map_or(head, |last_block| last_block + 1);
+                for block_number in from_block..=head {
+                    match eth_client.get_block(block_number).await {
+                        Ok(Some(block)) => {
+                            handle_new_block(&block, config, sender, gas_oracle, eth_client).await;
+                            last_block = Some(block_number);
+                        }
+                        Ok(None) => break,
+                        Err(err) => {
+                            error!("Could not get block {}: {}", block_number, err);
+                            break;
+                        }
+                    }
+                }
+            }
+            Err(err) => error!("Could not get block number: {}", err),
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// Stores the header of a new block and updates the gas oracle
+async fn handle_new_block(
+    block: &Block<H256>,
+    config: &AppConfig,
+    sender: &Sender<DbRecord>,
+    gas_oracle: &GasOracle,
+    eth_client: &EthClient,
+) {
+    let header = match BlockHeader::new(block, config.chain) {
+        Ok(header) => header,
+        Err(err) => {
+            error!("Invalid block header {:?}: {}", block, err);
+            return;
+        }
+    };
+    if sender.send(DbRecord::BlockHeader(header.clone())).is_err() {
+        error!("Could not send to queue block header {:?}", header);
+    }
+
+    if let Err(err) = update_gas_oracle(
+        gas_oracle,
+        header.block_number as u64,
+        config.oracle.window_blocks,
+        eth_client,
+    )
+    .await
+    {
+        error!(
+            "Could not update gas oracle at block {}: {}",
+            header.block_number, err
+        );
+    }
+}
+
+/// Follows the swaps of the watched pool and processes them, from the WS subscription or by polling
+/// `eth_getLogs` over HTTP depending on `ingestion.mode`. Both feed the same processor. Ingestion is supervised:
+/// whenever it fails or ends, it is restarted with exponential backoff, and the swaps emitted in between are caught up.
+/// The depth of the processing pipeline is reported to `pipeline_monitor`.
+pub async fn watch_swaps(
+    config: AppConfig,
+    sender: Sender<DbRecord>,
+    db_connection: PgPool,
+    pipeline_monitor: PipelineMonitor,
+) {
+    let mut processor = SwapProcessor::new(config.clone(), sender, db_connection.clone(), None);
+    processor.monitor = pipeline_monitor;
+    let mut attempt = 0;
+    loop {
+        let res = match config.ingestion.mode {
+            IngestionMode::Ws => {
+                run_log_subscription(&config, &mut processor, &db_connection, &mut attempt).await
             }
+            IngestionMode::Polling => {
+                run_log_polling(&config, &mut processor, &db_connection, &mut attempt).await
+            }
+        };
+        match res {
+            Ok(()) => warn!("Swap ingestion ended"),
+            Err(err) => error!("Swap ingestion failed: {}", err),
         }
+        let backoff = config.subscription.backoff(attempt);
+        attempt += 1;
+        info!(
+            "Restarting swap ingestion in {} ms (attempt {})",
+            backoff.as_millis(),
+            attempt
+        );
+        tokio::time::sleep(backoff).await;
+    }
+}
+
+/// On the first connection, resume from the checkpoint stored before the restart
+async fn load_checkpoint(
+    config: &AppConfig,
+    processor: &mut SwapProcessor,
+    db_connection: &PgPool,
+) -> Result<()> {
+    if processor.checkpoint.is_none() && processor.last_block.is_none() {
+        processor.checkpoint = get_checkpoint_from_db(config.pool_address.as_str(), db_connection)
+            .await?
+            .map(|block_number| block_number as u64);
     }
     Ok(())
 }
 
+/// Subscribes to the swaps of the watched pool, catches up from the last processed block,
+/// then processes the live swaps until the subscription ends
+async fn run_log_subscription(
+    config: &AppConfig,
+    processor: &mut SwapProcessor,
+    db_connection: &PgPool,
+    attempt: &mut u32,
+) -> Result<()> {
+    load_checkpoint(config, processor, db_connection).await?;
+
+    let ws_client = connect_ws(&config.rpc_urls_ws, *attempt).await?;
+    let filter = Filter::new()
+        .address(Address::from_str(config.pool_address.as_str())?)
+        .topic0(H256::from_str(config.swap_topic.as_str())?);
+
+    // Subscribe before catching up to the head, so that no swap is missed in between:
+    // the live swaps received meanwhile are buffered, and skipped if the catch-up already processed them.
+    // New heads are followed to detect the reorgs that do not remove any log.
+    let mut logs = ws_client.subscribe_logs(&filter).await?;
+    let mut heads = ws_client.subscribe_blocks().await?;
+    let head = processor.eth_client.get_block_number().await?.as_u64();
+    processor.catch_up(head).await?;
+    *attempt = 0;
+
+    info!("Waiting for swap events...");
+    loop {
+        tokio::select! {
+            log = logs.next() => match log {
+                Some(log) => {
+                    processor.handle_log(log, true).await?;
+                }
+                None => return Ok(()),
+            },
+            // Store the swaps whose fetch completed while waiting for the next events
+            _ = processor.next_step() => {},
+            head = heads.next() => match head {
+                Some(head) => processor.on_head(&head).await?,
+                None => return Ok(()),
+            },
+        }
+    }
+}
+
+/// Catches up from the last processed block, then polls the head every `ingestion.poll_interval_ms`
+/// and processes the swaps of the blocks mined since the previous poll, until a poll fails
+async fn run_log_polling(
+    config: &AppConfig,
+    processor: &mut SwapProcessor,
+    db_connection: &PgPool,
+    attempt: &mut u32,
+) -> Result<()> {
+    load_checkpoint(config, processor, db_connection).await?;
+    let head = processor.eth_client.get_block_number().await?.as_u64();
+    processor.catch_up(head).await?;
+    *attempt = 0;
+
+    let interval = Duration::from_millis(config.ingestion.poll_interval_ms);
+    info!("Polling swap events...");
+    loop {
+        tokio::time::sleep(interval).await;
+        let head = processor
+            .eth_client
+            .get_block(BlockNumber::Latest)
+            .await?
+            .ok_or(anyhow!("latest block not found"))?;
+        processor.poll(&head).await?;
+    }
+}
+
 pub fn run_server(
     app_config: AppConfig,
     address: String,
-    sender: Sender<TxFee>,
+    sender: Sender<DbRecord>,
     db_connection: PgPool,
+    gas_oracle: GasOracle,
+    pipeline_monitor: PipelineMonitor,
 ) -> Result<Server, std::io::Error> {
-    let app = Application::new(app_config, sender, db_connection.clone()).unwrap();
+    let mut app = Application::new(app_config, sender, db_connection.clone()).unwrap();
+    app.gas_oracle = gas_oracle;
+    app.pipeline_monitor = pipeline_monitor;
     let server = HttpServer::new(move || {
         App::new()
             .app_data(web::Data::new(app.clone()))
@@ -229,7 +858,22 @@ pub fn run_server(
                 web::scope("")
                     .service(home)
                     .service(tx_fee)
-                    .service(swap_price),
+                    .service(swap_price)
+                    .service(basis_series)
+                    .service(opportunities)
+                    .service(alert_list)
+                    .service(alert_deliveries)
+                    .service(ingestion_errors)
+                    .service(ingestion_pipeline)
+                    .service(reorgs)
+                    .service(address_activity)
+                    .service(address_leaderboard)
+                    .service(swap_gas_share)
+                    .service(block_headers)
+                    .service(block_gas)
+                    .service(gas_suggest)
+                    .service(gas_breakdown)
+                    .service(estimate_swap),
             )
             .app_data(db_connection.clone())
     })
@@ -245,25 +889,50 @@ async fn home() -> impl Responder {
         .body("Uniswap Watcher")
 }
 
+#[derive(Deserialize)]
+struct TxFeeArg {
+    /// Whether to return every component of the fee as exact decimal strings
+    exact: Option<bool>,
+    /// Only return the fees of transactions at least this final
+    min_finality: Option<Finality>,
+}
+
 #[get("/tx_fee")]
-async fn tx_fee(controller: web::Data<Application>, body: web::Bytes) -> impl Responder {
+async fn tx_fee(
+    controller: web::Data<Application>,
+    arg: web::Query<TxFeeArg>,
+    body: web::Bytes,
+) -> impl Responder {
     if body.is_empty() {
         return HttpResponse::BadRequest()
             .content_type(ContentType::plaintext())
             .body("Missing body");
     };
-    let hashes: Vec<String>;
-    match serde_json::from_slice::<Vec<String>>(body.as_ref()) {
-        Ok(deserialized) => {
-            hashes = deserialized;
-        }
+    let hashes: Vec<String> = match serde_json::from_slice::<Vec<String>>(body.as_ref()) {
+        Ok(deserialized) => deserialized,
         Err(err) => {
             return HttpResponse::BadRequest()
                 .content_type(ContentType::plaintext())
                 .body(format!("Body deserialization failed: {}", err));
         }
     };
-    match controller.get_tx_fee_batch(hashes).await {
+    if arg.exact.unwrap_or(false) {
+        let res = controller
+            .get_tx_fee_batch_exact(hashes, arg.min_finality)
+            .await
+            .and_then(|fees| {
+                fees.into_iter()
+                    .map(|(tx_hash, fee)| Ok((tx_hash, fee.details()?)))
+                    .collect::<Result<HashMap<_, _>>>()
+            });
+        return match res {
+            Ok(fees) => HttpResponse::Ok().json(fees),
+            Err(err) => HttpResponse::InternalServerError()
+                .content_type(ContentType::plaintext())
+                .body(format!("Something went wrong: {}", err)),
+        };
+    }
+    match controller.get_tx_fee_batch(hashes, arg.min_finality).await {
         Ok(fee) => {
             let res = json!(fee);
             HttpResponse::Ok().json(res)
@@ -306,3 +975,365 @@ async fn swap_price(
             .body(format!("Something went wrong: {}", err)),
     }
 }
+
+#[derive(Deserialize)]
+struct TimeRangeArg {
+    from_ms: Option<i64>,
+    to_ms: Option<i64>,
+    limit: Option<i64>,
+}
+
+#[get("/basis")]
+async fn basis_series(
+    controller: web::Data<Application>,
+    arg: web::Query<TimeRangeArg>,
+) -> impl Responder {
+    match get_basis_from_db(
+        arg.from_ms,
+        arg.to_ms,
+        arg.limit.unwrap_or(1000),
+        &controller.db_connection,
+    )
+    .await
+    {
+        Ok(series) => HttpResponse::Ok().json(series),
+        Err(err) => HttpResponse::InternalServerError()
+            .content_type(ContentType::plaintext())
+            .body(format!("Something went wrong: {}", err)),
+    }
+}
+
+#[get("/opportunities")]
+async fn opportunities(
+    controller: web::Data<Application>,
+    arg: web::Query<TimeRangeArg>,
+) -> impl Responder {
+    match get_opportunities_from_db(
+        arg.from_ms,
+        arg.to_ms,
+        arg.limit.unwrap_or(1000),
+        &controller.db_connection,
+    )
+    .await
+    {
+        Ok(events) => HttpResponse::Ok().json(events),
+        Err(err) => HttpResponse::InternalServerError()
+            .content_type(ContentType::plaintext())
+            .body(format!("Something went wrong: {}", err)),
+    }
+}
+
+#[get("/alerts")]
+async fn alert_list(
+    controller: web::Data<Application>,
+    arg: web::Query<TimeRangeArg>,
+) -> impl Responder {
+    match get_alerts_from_db(
+        arg.from_ms,
+        arg.to_ms,
+        arg.limit.unwrap_or(1000),
+        &controller.db_connection,
+    )
+    .await
+    {
+        Ok(alerts) => HttpResponse::Ok().json(alerts),
+        Err(err) => HttpResponse::InternalServerError()
+            .content_type(ContentType::plaintext())
+            .body(format!("Something went wrong: {}", err)),
+    }
+}
+
+#[get("/ingestion/errors")]
+async fn ingestion_errors(
+    controller: web::Data<Application>,
+    arg: web::Query<TimeRangeArg>,
+) -> impl Responder {
+    match get_ingestion_errors_from_db(
+        arg.from_ms,
+        arg.to_ms,
+        arg.limit.unwrap_or(1000),
+        &controller.db_connection,
+    )
+    .await
+    {
+        Ok(errors) => HttpResponse::Ok().json(errors),
+        Err(err) => HttpResponse::InternalServerError()
+            .content_type(ContentType::plaintext())
+            .body(format!("Something went wrong: {}", err)),
+    }
+}
+
+#[get("/ingestion/pipeline")]
+async fn ingestion_pipeline(controller: web::Data<Application>) -> impl Responder {
+    let stats = controller.pipeline_monitor.read().unwrap().clone();
+    HttpResponse::Ok().json(stats)
+}
+
+#[get("/reorgs")]
+async fn reorgs(
+    controller: web::Data<Application>,
+    arg: web::Query<TimeRangeArg>,
+) -> impl Responder {
+    match get_reorgs_from_db(
+        arg.from_ms,
+        arg.to_ms,
+        arg.limit.unwrap_or(1000),
+        &controller.db_connection,
+    )
+    .await
+    {
+        Ok(reorgs) => HttpResponse::Ok().json(reorgs),
+        Err(err) => HttpResponse::InternalServerError()
+            .content_type(ContentType::plaintext())
+            .body(format!("Something went wrong: {}", err)),
+    }
+}
+
+#[get("/alerts/{id}/deliveries")]
+async fn alert_deliveries(
+    controller: web::Data<Application>,
+    path: web::Path<String>,
+) -> impl Responder {
+    match get_alert_deliveries_from_db(path.as_str(), &controller.db_connection).await {
+        Ok(deliveries) => HttpResponse::Ok().json(deliveries),
+        Err(err) => HttpResponse::InternalServerError()
+            .content_type(ContentType::plaintext())
+            .body(format!("Something went wrong: {}", err)),
+    }
+}
+
+#[derive(Deserialize)]
+struct ActivityArg {
+    from_ms: Option<i64>,
+    to_ms: Option<i64>,
+    /// Only count the swaps at least this final
+    min_finality: Option<Finality>,
+}
+
+#[get("/addresses/{address}/activity")]
+async fn address_activity(
+    controller: web::Data<Application>,
+    path: web::Path<String>,
+    arg: web::Query<ActivityArg>,
+) -> impl Responder {
+    let address = match path.parse::<Address>() {
+        Ok(address) => address.encode_hex_with_prefix(),
+        Err(_) => {
+            return HttpResponse::BadRequest()
+                .content_type(ContentType::plaintext())
+                .body(format!("Invalid address {}", path))
+        }
+    };
+    let mut res = HashMap::new();
+    for role in [
+        AddressRole::Sender,
+        AddressRole::Recipient,
+        AddressRole::From,
+    ] {
+        match get_address_activity_from_db(
+            role,
+            Some(address.as_str()),
+            arg.from_ms,
+            arg.to_ms,
+            arg.min_finality,
+            1,
+            &controller.db_connection,
+        )
+        .await
+        {
+            Ok(activity) => {
+                res.insert(role, activity.into_iter().next());
+            }
+            Err(err) => {
+                return HttpResponse::InternalServerError()
+                    .content_type(ContentType::plaintext())
+                    .body(format!("Something went wrong: {}", err))
+            }
+        }
+    }
+    HttpResponse::Ok().json(res)
+}
+
+#[derive(Deserialize)]
+struct LeaderboardArg {
+    role: Option<AddressRole>,
+    from_ms: Option<i64>,
+    to_ms: Option<i64>,
+    /// Only count the swaps at least this final
+    min_finality: Option<Finality>,
+    limit: Option<i64>,
+}
+
+#[get("/addresses/leaderboard")]
+async fn address_leaderboard(
+    controller: web::Data<Application>,
+    arg: web::Query<LeaderboardArg>,
+) -> impl Responder {
+    match get_address_activity_from_db(
+        arg.role.unwrap_or(AddressRole::From),
+        None,
+        arg.from_ms,
+        arg.to_ms,
+        arg.min_finality,
+        arg.limit.unwrap_or(100),
+        &controller.db_connection,
+    )
+    .await
+    {
+        Ok(leaderboard) => HttpResponse::Ok().json(leaderboard),
+        Err(err) => HttpResponse::InternalServerError()
+            .content_type(ContentType::plaintext())
+            .body(format!("Something went wrong: {}", err)),
+    }
+}
+
+#[get("/blocks/{number}/gas")]
+async fn block_gas(controller: web::Data<Application>, path: web::Path<u64>) -> impl Responder {
+    match controller
+        .try_get_or_insert_block_gas(path.into_inner())
+        .await
+        .and_then(|stats| stats.details())
+    {
+        Ok(stats) => HttpResponse::Ok().json(stats),
+        Err(err) => HttpResponse::InternalServerError()
+            .content_type(ContentType::plaintext())
+            .body(format!("Something went wrong: {}", err)),
+    }
+}
+
+#[get("/gas/suggest")]
+async fn gas_suggest(controller: web::Data<Application>) -> impl Responder {
+    match controller.suggest_gas().await {
+        Ok(suggestions) => HttpResponse::Ok().json(suggestions),
+        Err(err) => HttpResponse::InternalServerError()
+            .content_type(ContentType::plaintext())
+            .body(format!("Something went wrong: {}", err)),
+    }
+}
+
+#[get("/tx/{hash}/gas_breakdown")]
+async fn gas_breakdown(
+    controller: web::Data<Application>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let tx_hash = match TxHash::from_str(path.as_str()) {
+        Ok(tx_hash) => tx_hash,
+        Err(err) => {
+            return HttpResponse::BadRequest()
+                .content_type(ContentType::plaintext())
+                .body(format!("Invalid tx hash: {}", err));
+        }
+    };
+    match controller.get_gas_breakdown(tx_hash).await {
+        Ok(breakdown) => HttpResponse::Ok().json(breakdown),
+        Err(err) => HttpResponse::InternalServerError()
+            .content_type(ContentType::plaintext())
+            .body(format!("Something went wrong: {}", err)),
+    }
+}
+
+#[derive(Deserialize)]
+struct EstimateSwapArg {
+    pool: String,
+    amount_in: String,
+    token_in: String,
+}
+
+#[get("/estimate_swap")]
+async fn estimate_swap(
+    controller: web::Data<Application>,
+    arg: web::Query<EstimateSwapArg>,
+) -> impl Responder {
+    let parsed = (|| -> Result<(Address, U256, Address)> {
+        Ok((
+            arg.pool.parse()?,
+            U256::from_dec_str(arg.amount_in.as_str())?,
+            arg.token_in.parse()?,
+        ))
+    })();
+    let (pool_address, amount_in, token_in) = match parsed {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            return HttpResponse::BadRequest()
+                .content_type(ContentType::plaintext())
+                .body(format!("Invalid query parameters: {}", err));
+        }
+    };
+    match controller
+        .estimate_swap(pool_address, amount_in, token_in)
+        .await
+    {
+        Ok(estimate) => HttpResponse::Ok().json(estimate),
+        Err(err) if err.downcast_ref::<QuoteOverflow>().is_some() => HttpResponse::BadRequest()
+            .content_type(ContentType::plaintext())
+            .body(format!("Invalid query parameters: {}", err)),
+        Err(err) => HttpResponse::InternalServerError()
+            .content_type(ContentType::plaintext())
+            .body(format!("Something went wrong: {}", err)),
+    }
+}
+
+#[get("/blocks")]
+async fn block_headers(
+    controller: web::Data<Application>,
+    arg: web::Query<TimeRangeArg>,
+) -> impl Responder {
+    match get_block_headers_from_db(
+        arg.from_ms,
+        arg.to_ms,
+        arg.limit.unwrap_or(1000),
+        &controller.db_connection,
+    )
+    .await
+    {
+        Ok(headers) => HttpResponse::Ok().json(headers),
+        Err(err) => HttpResponse::InternalServerError()
+            .content_type(ContentType::plaintext())
+            .body(format!("Something went wrong: {}", err)),
+    }
+}
+
+#[derive(Deserialize)]
+struct GasShareArg {
+    pool_address: Option<String>,
+    from_ms: Option<i64>,
+    to_ms: Option<i64>,
+    /// Width of the time buckets, 1 hour by default
+    bucket_ms: Option<i64>,
+    /// Only count swaps with a notional of at most this amount
+    max_notional_usd: Option<f64>,
+    /// Gas share above which swaps are counted in `above_threshold_count`, 1% by default
+    threshold: Option<f64>,
+    /// Only count the swaps at least this final
+    min_finality: Option<Finality>,
+}
+
+#[get("/swaps/gas_share")]
+async fn swap_gas_share(
+    controller: web::Data<Application>,
+    arg: web::Query<GasShareArg>,
+) -> impl Responder {
+    let bucket_ms = arg.bucket_ms.unwrap_or(3_600_000);
+    if bucket_ms <= 0 {
+        return HttpResponse::BadRequest()
+            .content_type(ContentType::plaintext())
+            .body(format!("Invalid bucket_ms {}", bucket_ms));
+    }
+    match get_gas_share_buckets_from_db(
+        arg.pool_address.as_deref(),
+        arg.from_ms,
+        arg.to_ms,
+        bucket_ms,
+        arg.max_notional_usd,
+        arg.threshold.unwrap_or(0.01),
+        arg.min_finality,
+        &controller.db_connection,
+    )
+    .await
+    {
+        Ok(buckets) => HttpResponse::Ok().json(buckets),
+        Err(err) => HttpResponse::InternalServerError()
+            .content_type(ContentType::plaintext())
+            .body(format!("Something went wrong: {}", err)),
+    }
+}
@@ -1,10 +1,13 @@
 use env_logger::Env;
 use sqlx::postgres::PgPoolOptions;
 use std::env;
-use std::sync::mpsc;
+use std::sync::{mpsc, Arc, RwLock};
 use tracing::info;
-use uniswap_watcher::db::run_queue_receiver;
-use uniswap_watcher::{run_server, subscribe_logs, AppConfig};
+use uniswap_watcher::backfill::backfill;
+use uniswap_watcher::db::{run_migrations, run_queue_receiver};
+use uniswap_watcher::ingest::PipelineStats;
+use uniswap_watcher::oracle::FeeWindow;
+use uniswap_watcher::{run_server, subscribe_blocks, watch_swaps, AppConfig, Application};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -23,18 +26,66 @@ async fn main() -> anyhow::Result<()> {
         .connect_lazy(&settings.connection_string(docker))
         .expect("Failed to create db connection");
 
+    info!("Running db migrations");
+    let mut attempt = 1;
+    while let Err(err) = run_migrations(&db_connection).await {
+        // The database may still be starting up, e.g. with docker-compose
+        if attempt >= 10 {
+            panic!("Failed to run db migrations: {}", err);
+        }
+        info!(
+            "Could not run db migrations ({}), wait 1 sec and retry",
+            err
+        );
+        attempt += 1;
+        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    }
+
     info!("Spawning queue receiver");
     let (sender, receiver) = mpsc::channel();
     tokio::spawn(run_queue_receiver(receiver, db_connection.clone()));
 
-    info!("Subscribing to logs");
-    tokio::spawn(subscribe_logs(app_config.clone(), sender.clone()));
+    // Backfill mode: `uniswap-watcher backfill <from_block> <to_block>` fills the past fees, then exits
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(String::as_str) == Some("backfill") {
+        let (Some(from_block), Some(to_block)) = (args.get(2), args.get(3)) else {
+            panic!("Usage: uniswap-watcher backfill <from_block> <to_block>");
+        };
+        info!("Backfilling blocks {} to {}", from_block, to_block);
+        let app = Application::new(app_config, sender, db_connection)?;
+        backfill(&app, from_block.parse()?, to_block.parse()?).await?;
+        return Ok(());
+    }
+
+    info!("Watching swaps");
+    let pipeline_monitor = PipelineStats::monitor(&app_config.pipeline);
+    tokio::spawn(watch_swaps(
+        app_config.clone(),
+        sender.clone(),
+        db_connection.clone(),
+        pipeline_monitor.clone(),
+    ));
+
+    info!("Subscribing to blocks");
+    let gas_oracle = Arc::new(RwLock::new(FeeWindow::new(app_config.oracle.window_blocks)));
+    tokio::spawn(subscribe_blocks(
+        app_config.clone(),
+        sender.clone(),
+        gas_oracle.clone(),
+    ));
 
     info!("Serving...");
     let address = format!("0.0.0.0:{}", app_config.application_port);
-    run_server(app_config.clone(), address, sender, db_connection)?
-        .await
-        .expect("Error running server");
+    run_server(
+        app_config.clone(),
+        address,
+        sender,
+        db_connection,
+        gas_oracle,
+        pipeline_monitor,
+    )?
+    .await
+    .expect("Error running server");
 
     Ok(())
 }
@@ -0,0 +1,165 @@
+use crate::fee::FeeAmount;
+use crate::gas::percentile;
+use crate::rpc::EthClient;
+use anyhow::Result;
+use ethers::prelude::{BlockNumber, FeeHistory, Middleware, U256};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::{Arc, RwLock};
+
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct GasOracleSettings {
+    /// Number of recent blocks the fee suggestions are computed over
+    pub window_blocks: u64,
+}
+
+/// Reward percentiles requested from `eth_feeHistory`, for the slow, standard and fast suggestions
+pub const REWARD_PERCENTILES: [f64; 3] = [10.0, 50.0, 90.0];
+
+/// Base fee and priority fee rewards of a block, as returned by `eth_feeHistory`
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockFees {
+    pub number: u64,
+    pub base_fee_per_gas: U256,
+    /// Priority fees paid at `REWARD_PERCENTILES` of the gas used in the block
+    pub rewards: Vec<U256>,
+}
+
+/// Rolling window of the fees of the most recent blocks
+#[derive(Clone, Debug, Default)]
+pub struct FeeWindow {
+    /// Oldest block first
+    pub blocks: VecDeque<BlockFees>,
+    /// Base fee of the block following the window
+    pub next_base_fee_per_gas: U256,
+    window_blocks: usize,
+}
+
+/// Fee window shared between the oracle task and the API
+pub type GasOracle = Arc<RwLock<FeeWindow>>;
+
+/// Fees to set on a transaction to be included at a given speed
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FeeSuggestion {
+    pub max_priority_fee_per_gas: String,
+    /// Twice the next base fee plus the priority fee, to stay valid if the base fee keeps rising
+    pub max_fee_per_gas: String,
+    /// Expected cost of a swap, at the next base fee plus the priority fee
+    pub swap_cost: FeeAmount,
+}
+
+/// Fee suggestions as served by the API
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct GasSuggestions {
+    /// Last block of the window the suggestions are computed over
+    pub block_number: u64,
+    pub next_base_fee_per_gas: String,
+    pub eth_usdt_price: String,
+    /// Gas units of the swap priced in `swap_cost`
+    pub swap_gas: u64,
+    pub slow: FeeSuggestion,
+    pub standard: FeeSuggestion,
+    pub fast: FeeSuggestion,
+}
+
+impl FeeWindow {
+    pub fn new(window_blocks: u64) -> Self {
+        Self {
+            window_blocks: window_blocks as usize,
+            ..Default::default()
+        }
+    }
+
+    /// Last block of the window, if any
+    pub fn last_block(&self) -> Option<u64> {
+        self.blocks.back().map(|block| block.number)
+    }
+
+    /// Append the blocks of a fee history not yet in the window, dropping the oldest ones
+    pub fn update(&mut self, history: &FeeHistory) {
+        let oldest_block = history.oldest_block.as_u64();
+        for (i, rewards) in history.reward.iter().enumerate() {
+            let number = oldest_block + i as u64;
+            if self.last_block().is_some_and(|last| number <= last) {
+                continue;
+            }
+            self.blocks.push_back(BlockFees {
+                number,
+                base_fee_per_gas: history.base_fee_per_gas.get(i).copied().unwrap_or_default(),
+                rewards: rewards.clone(),
+            });
+        }
+        // The base fee history has one more element: the base fee of the next block
+        if let Some(next_base_fee) = history.base_fee_per_gas.get(history.reward.len()) {
+            self.next_base_fee_per_gas = *next_base_fee;
+        }
+        while self.blocks.len() > self.window_blocks {
+            self.blocks.pop_front();
+        }
+    }
+
+    /// Median over the window of the rewards at the given index of `REWARD_PERCENTILES`
+    pub fn priority_fee(&self, index: usize) -> Option<U256> {
+        let mut rewards: Vec<U256> = self
+            .blocks
+            .iter()
+            .filter_map(|block| block.rewards.get(index).copied())
+            .collect();
+        rewards.sort();
+        percentile(&rewards, 50.0)
+    }
+
+    /// Slow, standard and fast fee suggestions for a swap of `swap_gas` gas units,
+    /// None until the window has received its first blocks
+    pub fn suggest(&self, eth_usdt_price: String, swap_gas: u64) -> Result<Option<GasSuggestions>> {
+        let Some(block_number) = self.last_block() else {
+            return Ok(None);
+        };
+        let suggestion = |index: usize| -> Result<FeeSuggestion> {
+            let priority_fee = self.priority_fee(index).unwrap_or_default();
+            let swap_cost = (self.next_base_fee_per_gas + priority_fee) * swap_gas;
+            Ok(FeeSuggestion {
+                max_priority_fee_per_gas: priority_fee.to_string(),
+                max_fee_per_gas: (self.next_base_fee_per_gas * U256::from(2) + priority_fee)
+                    .to_string(),
+                swap_cost: FeeAmount::new(swap_cost.to_string().as_str(), eth_usdt_price.as_str())?,
+            })
+        };
+        Ok(Some(GasSuggestions {
+            block_number,
+            next_base_fee_per_gas: self.next_base_fee_per_gas.to_string(),
+            slow: suggestion(0)?,
+            standard: suggestion(1)?,
+            fast: suggestion(2)?,
+            eth_usdt_price,
+            swap_gas,
+        }))
+    }
+}
+
+/// Given a new block number, update the fee window with `eth_feeHistory`.
+/// The whole window is filled on the first block, then only the blocks not seen yet are fetched.
+pub async fn update_gas_oracle(
+    oracle: &GasOracle,
+    block_number: u64,
+    window_blocks: u64,
+    eth_client: &EthClient,
+) -> Result<()> {
+    let last_block = oracle.read().unwrap().last_block();
+    let block_count = match last_block {
+        Some(last_block) if last_block < block_number => {
+            (block_number - last_block).min(window_blocks)
+        }
+        Some(_) => return Ok(()),
+        None => window_blocks,
+    };
+    let history = eth_client
+        .fee_history(
+            block_count,
+            BlockNumber::Number(block_number.into()),
+            &REWARD_PERCENTILES,
+        )
+        .await?;
+    oracle.write().unwrap().update(&history);
+    Ok(())
+}
@@ -0,0 +1,220 @@
+use crate::rpc::EthClient;
+use anyhow::{anyhow, Result};
+use ethers::prelude::{Middleware, ProviderError, RpcError, TransactionReceipt, TxHash, H256};
+use ethers::utils::hex::ToHexExt;
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+
+/// JSON-RPC error code of an unknown method
+const METHOD_NOT_FOUND: i64 = -32601;
+
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct ReceiptSettings {
+    /// Maximum number of `eth_getTransactionReceipt` calls sent in a single JSON-RPC batch
+    pub batch_size: usize,
+    /// Minimum number of wanted transactions in a block to fetch all its receipts with `eth_getBlockReceipts`
+    /// rather than only the wanted ones
+    pub block_receipts_min_txs: usize,
+    /// Number of blocks whose receipts are kept in memory to rank several transactions of the same block
+    pub block_cache_size: usize,
+}
+
+/// Receipts of the most recently ranked blocks, by block hash
+type BlockReceiptsCache = VecDeque<(H256, Arc<Vec<TransactionReceipt>>)>;
+
+/// Whether an `eth_getBlockReceipts` error means the node does not support the method
+fn is_unsupported(err: &ProviderError) -> bool {
+    if let Some(err) = err.as_error_response() {
+        let message = err.message.to_lowercase();
+        return err.code == METHOD_NOT_FOUND
+            || [
+                "not supported",
+                "unsupported",
+                "does not exist",
+                "not available",
+            ]
+            .iter()
+            .any(|pattern| message.contains(pattern));
+    }
+    false
+}
+
+/// Fetches transaction receipts in as few requests as possible: the receipts of a whole block with
+/// `eth_getBlockReceipts` when the node supports it, and otherwise batches of `eth_getTransactionReceipt` calls.
+/// Shared by the live watcher, the backfills and the `/tx_fee` batches.
+#[derive(Clone)]
+pub struct ReceiptFetcher {
+    eth_client: EthClient,
+    settings: ReceiptSettings,
+    /// Set once the node answered that it does not support `eth_getBlockReceipts`
+    block_receipts_unsupported: Arc<AtomicBool>,
+    block_receipts_cache: Arc<Mutex<BlockReceiptsCache>>,
+}
+
+impl ReceiptFetcher {
+    pub fn new(eth_client: EthClient, settings: ReceiptSettings) -> Self {
+        Self {
+            eth_client,
+            settings,
+            block_receipts_unsupported: Arc::new(AtomicBool::new(false)),
+            block_receipts_cache: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+
+    /// Receipts of the given transactions, with batches of `eth_getTransactionReceipt` calls.
+    /// Transactions without receipt yet, or whose call failed within its batch, are left out.
+    pub async fn get_receipts(
+        &self,
+        tx_hashes: &[TxHash],
+    ) -> Result<HashMap<TxHash, TransactionReceipt>> {
+        let mut receipts = HashMap::new();
+        for chunk in tx_hashes.chunks(self.settings.batch_size.max(1)) {
+            let params: Vec<Value> = chunk.iter().map(|tx_hash| json!([tx_hash])).collect();
+            let chunk_receipts = self
+                .eth_client
+                .as_ref()
+                .request_batch::<Option<TransactionReceipt>>("eth_getTransactionReceipt", &params)
+                .await?;
+            for (tx_hash, receipt) in chunk.iter().zip(chunk_receipts) {
+                match receipt {
+                    Ok(Some(receipt)) => {
+                        receipts.insert(receipt.transaction_hash, receipt);
+                    }
+                    Ok(None) => {}
+                    Err(err) => warn!(
+                        "Could not get tx receipt for tx hash {}: {}",
+                        tx_hash.encode_hex_with_prefix(),
+                        err
+                    ),
+                }
+            }
+        }
+        Ok(receipts)
+    }
+
+    /// Receipts of every transaction of a block, in block order
+    pub async fn get_block_receipts(&self, block_number: u64) -> Result<Vec<TransactionReceipt>> {
+        if let Some(receipts) = self.try_get_block_receipts(block_number).await? {
+            return Ok(receipts);
+        }
+        let block = self
+            .eth_client
+            .get_block(block_number)
+            .await?
+            .ok_or(anyhow!("block {} not found", block_number))?;
+        let mut receipts = self.get_receipts(&block.transactions).await?;
+        block
+            .transactions
+            .iter()
+            .map(|tx_hash| {
+                receipts.remove(tx_hash).ok_or(anyhow!(
+                    "tx receipt for tx hash {} of block {} not found",
+                    tx_hash.encode_hex_with_prefix(),
+                    block_number
+                ))
+            })
+            .collect()
+    }
+
+    /// Receipts of every transaction of a block, in block order, from the cache of the last
+    /// `receipts.block_cache_size` blocks. Keyed by block hash, so that a reorged block is fetched again.
+    pub async fn get_cached_block_receipts(
+        &self,
+        block_number: u64,
+        block_hash: H256,
+    ) -> Result<Arc<Vec<TransactionReceipt>>> {
+        let cached = self
+            .block_receipts_cache
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(hash, _)| *hash == block_hash)
+            .map(|(_, receipts)| receipts.clone());
+        if let Some(receipts) = cached {
+            return Ok(receipts);
+        }
+        let receipts = Arc::new(self.get_block_receipts(block_number).await?);
+        // Receipts of a block reorged meanwhile are not cached under the hash of the wanted block
+        if receipts
+            .iter()
+            .all(|receipt| receipt.block_hash == Some(block_hash))
+            && self.settings.block_cache_size > 0
+        {
+            let mut cache = self.block_receipts_cache.lock().unwrap();
+            if cache.len() >= self.settings.block_cache_size {
+                cache.pop_front();
+            }
+            cache.push_back((block_hash, receipts.clone()));
+        }
+        Ok(receipts)
+    }
+
+    /// Receipts of the given transactions of a block. All the receipts of the block are fetched at once when
+    /// at least `receipts.block_receipts_min_txs` of its transactions are wanted. Transactions without receipt
+    /// yet are left out.
+    pub async fn get_block_tx_receipts(
+        &self,
+        block_number: u64,
+        tx_hashes: &[TxHash],
+    ) -> Result<HashMap<TxHash, TransactionReceipt>> {
+        if tx_hashes.len() >= self.settings.block_receipts_min_txs.max(1) {
+            if let Some(receipts) = self.try_get_block_receipts(block_number).await? {
+                return Ok(receipts
+                    .into_iter()
+                    .filter(|receipt| tx_hashes.contains(&receipt.transaction_hash))
+                    .map(|receipt| (receipt.transaction_hash, receipt))
+                    .collect());
+            }
+        }
+        self.get_receipts(tx_hashes).await
+    }
+
+    /// Receipts of the given transactions, grouped by block
+    pub async fn get_receipts_by_block(
+        &self,
+        txs: &[(u64, TxHash)],
+    ) -> Result<HashMap<TxHash, TransactionReceipt>> {
+        let mut blocks: Vec<(u64, Vec<TxHash>)> = vec![];
+        for (block_number, tx_hash) in txs {
+            match blocks.last_mut() {
+                Some((last_block, tx_hashes)) if last_block == block_number => {
+                    if !tx_hashes.contains(tx_hash) {
+                        tx_hashes.push(*tx_hash);
+                    }
+                }
+                _ => blocks.push((*block_number, vec![*tx_hash])),
+            }
+        }
+        let mut receipts = HashMap::new();
+        for (block_number, tx_hashes) in blocks {
+            receipts.extend(self.get_block_tx_receipts(block_number, &tx_hashes).await?);
+        }
+        Ok(receipts)
+    }
+
+    /// Receipts of a block from `eth_getBlockReceipts`, or None if the node does not support it
+    async fn try_get_block_receipts(
+        &self,
+        block_number: u64,
+    ) -> Result<Option<Vec<TransactionReceipt>>> {
+        if self.block_receipts_unsupported.load(Ordering::Relaxed) {
+            return Ok(None);
+        }
+        match self.eth_client.get_block_receipts(block_number).await {
+            Ok(receipts) => Ok(Some(receipts)),
+            Err(err) if is_unsupported(&err) => {
+                warn!(
+                    "eth_getBlockReceipts is not supported ({}), falling back to batched eth_getTransactionReceipt",
+                    err
+                );
+                self.block_receipts_unsupported
+                    .store(true, Ordering::Relaxed);
+                Ok(None)
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+}
@@ -0,0 +1,505 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use ethers::prelude::{Http, HttpClientError, JsonRpcClient, Provider, ProviderError, Ws, U64};
+use ethers::providers::{JsonRpcError, RpcError};
+use futures_util::future::join_all;
+use reqwest::Url;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::fmt::{Debug, Display, Formatter};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// Weight of the last request in the latency and error rate moving averages
+const HEALTH_ALPHA: f64 = 0.2;
+/// Latency penalty of an endpoint failing every request
+const ERROR_PENALTY_MS: f64 = 1000.0;
+/// Methods whose result must be agreed on by `rpc.receipt_quorum` endpoints, as fees are computed from them
+const QUORUM_METHODS: [&str; 2] = ["eth_getTransactionReceipt", "eth_getBlockReceipts"];
+
+/// Ethereum client routing requests over the configured HTTP endpoints
+pub type EthClient = Provider<FailoverClient>;
+
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct RpcSettings {
+    /// Number of blocks an endpoint can lag behind the best known head before requests avoid it
+    pub max_head_lag_blocks: u64,
+    /// Delay between two checks of the head of every endpoint
+    pub head_check_interval_ms: u64,
+    /// Number of endpoints that must return the same receipts, 1 to trust the healthiest endpoint
+    pub receipt_quorum: usize,
+}
+
+impl Default for RpcSettings {
+    fn default() -> Self {
+        Self {
+            max_head_lag_blocks: 3,
+            head_check_interval_ms: 10_000,
+            receipt_quorum: 1,
+        }
+    }
+}
+
+/// Health of an endpoint, from the requests sent to it
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize)]
+pub struct EndpointHealth {
+    /// Moving average of the latency of the successful requests
+    pub latency_ms: f64,
+    /// Moving average of the share of requests that failed, from 0 to 1
+    pub error_rate: f64,
+    /// Last head returned by the endpoint
+    pub head: Option<u64>,
+    pub requests: u64,
+    pub errors: u64,
+}
+
+impl EndpointHealth {
+    /// Lower is healthier
+    fn score(&self) -> f64 {
+        self.latency_ms + ERROR_PENALTY_MS * self.error_rate
+    }
+
+    fn record(&mut self, latency: Option<Duration>) {
+        let failed = latency.is_none();
+        if let Some(latency) = latency {
+            let latency_ms = latency.as_secs_f64() * 1000.0;
+            self.latency_ms = if self.requests == self.errors {
+                latency_ms
+            } else {
+                (1.0 - HEALTH_ALPHA) * self.latency_ms + HEALTH_ALPHA * latency_ms
+            };
+        }
+        self.error_rate =
+            (1.0 - HEALTH_ALPHA) * self.error_rate + HEALTH_ALPHA * if failed { 1.0 } else { 0.0 };
+        self.requests += 1;
+        self.errors += failed as u64;
+    }
+}
+
+#[derive(Debug)]
+struct Endpoint {
+    url: String,
+    client: Http,
+    /// HTTP client shared with `client`, to send JSON-RPC batches
+    batch_client: reqwest::Client,
+    health: Mutex<EndpointHealth>,
+}
+
+/// Response to a call of a JSON-RPC batch
+#[derive(serde::Deserialize)]
+struct BatchResponse {
+    id: usize,
+    result: Option<Value>,
+    error: Option<Value>,
+}
+
+/// Result of a call of a JSON-RPC batch, once ordered. The error is kept as a JSON-RPC error object.
+#[derive(Default, serde::Deserialize, Serialize)]
+struct BatchResult {
+    result: Option<Value>,
+    error: Option<Value>,
+}
+
+impl Endpoint {
+    /// Sends the calls of `method` with each of `params` in a single JSON-RPC batch, and returns the result
+    /// or error of each call in order as an array. Only fails if the batch as a whole fails.
+    async fn request_batch(
+        &self,
+        method: &str,
+        params: &[Value],
+    ) -> Result<Value, HttpClientError> {
+        let requests: Vec<Value> = params
+            .iter()
+            .enumerate()
+            .map(|(id, params)| json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params}))
+            .collect();
+        let text = self
+            .batch_client
+            .post(self.url.as_str())
+            .json(&requests)
+            .send()
+            .await?
+            .text()
+            .await?;
+        let responses: Vec<BatchResponse> = match serde_json::from_str(&text) {
+            Ok(responses) => responses,
+            // The whole batch can be rejected with a single error response
+            Err(err) => {
+                let batch_err = serde_json::from_str::<BatchResult>(&text)
+                    .ok()
+                    .and_then(|response| response.error)
+                    .and_then(|error| serde_json::from_value::<JsonRpcError>(error).ok());
+                return Err(match batch_err {
+                    Some(batch_err) => HttpClientError::JsonRpcError(batch_err),
+                    None => HttpClientError::SerdeJson { err, text },
+                });
+            }
+        };
+        let mut results: Vec<BatchResult> = params.iter().map(|_| BatchResult::default()).collect();
+        for response in responses {
+            if let Some(result) = results.get_mut(response.id) {
+                result.result = response.result;
+                result.error = response.error;
+            }
+        }
+        serde_json::to_value(results).map_err(|err| HttpClientError::SerdeJson {
+            err,
+            text: String::new(),
+        })
+    }
+}
+
+/// JSON-RPC transport over several HTTP endpoints. Every request is sent to the healthiest endpoint that does not lag
+/// behind the best known head, and fails over to the next one on transport errors. JSON-RPC error responses are
+/// returned as is, since the endpoint answered.
+#[derive(Clone, Debug)]
+pub struct FailoverClient {
+    endpoints: Arc<Vec<Endpoint>>,
+    settings: RpcSettings,
+    last_head_check: Arc<Mutex<Option<Instant>>>,
+}
+
+#[derive(Debug)]
+pub enum FailoverError {
+    Client(HttpClientError),
+    SerdeJson(serde_json::Error),
+    /// Not enough endpoints returned the same result
+    NoQuorum {
+        method: String,
+        required: usize,
+        agreeing: usize,
+    },
+}
+
+impl Display for FailoverError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FailoverError::Client(err) => write!(f, "{}", err),
+            FailoverError::SerdeJson(err) => write!(f, "{}", err),
+            FailoverError::NoQuorum {
+                method,
+                required,
+                agreeing,
+            } => write!(
+                f,
+                "no quorum on {}: {} endpoints agreeing out of {} required",
+                method, agreeing, required
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FailoverError {}
+
+impl RpcError for FailoverError {
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        match self {
+            FailoverError::Client(err) => err.as_error_response(),
+            _ => None,
+        }
+    }
+
+    fn as_serde_error(&self) -> Option<&serde_json::Error> {
+        match self {
+            FailoverError::Client(err) => err.as_serde_error(),
+            FailoverError::SerdeJson(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<FailoverError> for ProviderError {
+    fn from(err: FailoverError) -> Self {
+        match err {
+            FailoverError::Client(err) => err.into(),
+            err => ProviderError::JsonRpcClientError(Box::new(err)),
+        }
+    }
+}
+
+impl FailoverClient {
+    pub fn new(urls: &[String], settings: RpcSettings) -> Result<Self> {
+        if urls.is_empty() {
+            return Err(anyhow!("no RPC endpoint configured"));
+        }
+        let endpoints = urls
+            .iter()
+            .map(|url| {
+                let batch_client = reqwest::Client::new();
+                Ok(Endpoint {
+                    url: url.clone(),
+                    client: Http::new_with_client(Url::parse(url)?, batch_client.clone()),
+                    batch_client,
+                    health: Mutex::new(EndpointHealth::default()),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            endpoints: Arc::new(endpoints),
+            settings,
+            last_head_check: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    /// Health of every endpoint, by URL
+    pub fn health(&self) -> Vec<(String, EndpointHealth)> {
+        self.endpoints
+            .iter()
+            .map(|endpoint| {
+                (
+                    endpoint.url.clone(),
+                    endpoint.health.lock().unwrap().clone(),
+                )
+            })
+            .collect()
+    }
+
+    /// Indices of the endpoints, healthiest first. Endpoints lagging behind the best known head come last.
+    fn ranked(&self) -> Vec<usize> {
+        let health: Vec<EndpointHealth> = self
+            .endpoints
+            .iter()
+            .map(|endpoint| endpoint.health.lock().unwrap().clone())
+            .collect();
+        let best_head = health.iter().filter_map(|health| health.head).max();
+        let lagging = |health: &EndpointHealth| match (health.head, best_head) {
+            (Some(head), Some(best_head)) => head + self.settings.max_head_lag_blocks < best_head,
+            _ => false,
+        };
+        let mut ranked: Vec<usize> = (0..health.len()).collect();
+        ranked.sort_by(|a, b| {
+            lagging(&health[*a])
+                .cmp(&lagging(&health[*b]))
+                .then(health[*a].score().total_cmp(&health[*b].score()))
+        });
+        ranked
+    }
+
+    /// Sends a request to an endpoint and records its health
+    async fn send(
+        &self,
+        index: usize,
+        method: &str,
+        params: &Value,
+    ) -> Result<Value, HttpClientError> {
+        let endpoint = &self.endpoints[index];
+        let start = Instant::now();
+        let res = if params.is_null() {
+            endpoint.client.request::<_, Value>(method, ()).await
+        } else {
+            endpoint.client.request::<_, Value>(method, params).await
+        };
+        self.record(index, method, start, &res);
+        res
+    }
+
+    /// Sends a JSON-RPC batch to an endpoint and records its health
+    async fn send_batch(
+        &self,
+        index: usize,
+        method: &str,
+        params: &[Value],
+    ) -> Result<Value, HttpClientError> {
+        let start = Instant::now();
+        let res = self.endpoints[index].request_batch(method, params).await;
+        self.record(index, method, start, &res);
+        res
+    }
+
+    fn record(
+        &self,
+        index: usize,
+        method: &str,
+        start: Instant,
+        res: &Result<Value, HttpClientError>,
+    ) {
+        let mut health = self.endpoints[index].health.lock().unwrap();
+        match res {
+            // The endpoint answered, even if with an error
+            Ok(_) | Err(HttpClientError::JsonRpcError(_)) => health.record(Some(start.elapsed())),
+            Err(_) => health.record(None),
+        }
+        if let (Ok(value), "eth_blockNumber") = (res, method) {
+            if let Ok(head) = serde_json::from_value::<U64>(value.clone()) {
+                health.head = Some(head.as_u64());
+            }
+        }
+    }
+
+    /// Checks the head of every endpoint if the last check is older than `rpc.head_check_interval_ms`
+    async fn check_heads(&self) {
+        if self.endpoints.len() < 2 {
+            return;
+        }
+        {
+            let mut last_head_check = self.last_head_check.lock().unwrap();
+            let interval = Duration::from_millis(self.settings.head_check_interval_ms);
+            if last_head_check.is_some_and(|last| last.elapsed() < interval) {
+                return;
+            }
+            *last_head_check = Some(Instant::now());
+        }
+        let checks = (0..self.endpoints.len())
+            .map(|index| self.send(index, "eth_blockNumber", &Value::Null));
+        join_all(checks).await;
+    }
+
+    /// Sends the request to the ranked endpoints until `quorum` of them return the same result
+    async fn request_quorum<F, Fut>(
+        &self,
+        method: &str,
+        quorum: usize,
+        send: F,
+    ) -> Result<Value, FailoverError>
+    where
+        F: Fn(usize) -> Fut,
+        Fut: Future<Output = Result<Value, HttpClientError>>,
+    {
+        let mut results: Vec<(Value, usize)> = vec![];
+        for index in self.ranked() {
+            let value = match send(index).await {
+                Ok(value) => value,
+                Err(err) => {
+                    warn!(
+                        "{} failed on {}: {}",
+                        method, self.endpoints[index].url, err
+                    );
+                    continue;
+                }
+            };
+            let agreeing = match results.iter_mut().find(|(result, _)| *result == value) {
+                Some((_, count)) => {
+                    *count += 1;
+                    *count
+                }
+                None => {
+                    results.push((value.clone(), 1));
+                    1
+                }
+            };
+            if agreeing >= quorum {
+                return Ok(value);
+            }
+        }
+        Err(FailoverError::NoQuorum {
+            method: method.to_string(),
+            required: quorum,
+            agreeing: results.iter().map(|(_, count)| *count).max().unwrap_or(0),
+        })
+    }
+
+    /// Sends the request to the ranked endpoints until one answers
+    async fn request_failover<F, Fut>(&self, method: &str, send: F) -> Result<Value, FailoverError>
+    where
+        F: Fn(usize) -> Fut,
+        Fut: Future<Output = Result<Value, HttpClientError>>,
+    {
+        let ranked = self.ranked();
+        let mut last_err = None;
+        for (attempt, index) in ranked.iter().enumerate() {
+            match send(*index).await {
+                Ok(value) => return Ok(value),
+                Err(err @ HttpClientError::JsonRpcError(_)) => {
+                    return Err(FailoverError::Client(err))
+                }
+                Err(err) => {
+                    if attempt + 1 < ranked.len() {
+                        warn!(
+                            "{} failed on {}, failing over: {}",
+                            method, self.endpoints[*index].url, err
+                        );
+                    }
+                    last_err = Some(err);
+                }
+            }
+        }
+        Err(FailoverError::Client(
+            last_err.expect("at least one endpoint"),
+        ))
+    }
+
+    /// Sends the calls of `method` with each of `params` in a single JSON-RPC batch, routed like a single request:
+    /// the batch only fails over to the next endpoint on transport errors. The result of each call is returned
+    /// in the order of `params`, so that a failed call does not fail the others.
+    pub async fn request_batch<R: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: &[Value],
+    ) -> Result<Vec<Result<R, FailoverError>>, FailoverError> {
+        if params.is_empty() {
+            return Ok(vec![]);
+        }
+        self.check_heads().await;
+        let send = |index| self.send_batch(index, method, params);
+        let quorum = self.settings.receipt_quorum;
+        let value = if quorum > 1 && QUORUM_METHODS.contains(&method) {
+            self.request_quorum(method, quorum, send).await?
+        } else {
+            self.request_failover(method, send).await?
+        };
+        let results: Vec<BatchResult> =
+            serde_json::from_value(value).map_err(FailoverError::SerdeJson)?;
+        Ok(results
+            .into_iter()
+            .map(|result| match result.error {
+                Some(err) => {
+                    let err: JsonRpcError =
+                        serde_json::from_value(err).map_err(FailoverError::SerdeJson)?;
+                    Err(FailoverError::Client(HttpClientError::JsonRpcError(err)))
+                }
+                None => serde_json::from_value(result.result.unwrap_or_default())
+                    .map_err(FailoverError::SerdeJson),
+            })
+            .collect())
+    }
+}
+
+#[async_trait]
+impl JsonRpcClient for FailoverClient {
+    type Error = FailoverError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        let params = serde_json::to_value(params).map_err(FailoverError::SerdeJson)?;
+        self.check_heads().await;
+        let send = |index| self.send(index, method, &params);
+        let quorum = self.settings.receipt_quorum;
+        let value = if quorum > 1 && QUORUM_METHODS.contains(&method) {
+            self.request_quorum(method, quorum, send).await?
+        } else {
+            self.request_failover(method, send).await?
+        };
+        serde_json::from_value(value).map_err(FailoverError::SerdeJson)
+    }
+}
+
+/// Client over the HTTP endpoints of `urls`
+pub fn connect_http(urls: &[String], settings: &RpcSettings) -> Result<EthClient> {
+    Ok(Provider::new(FailoverClient::new(urls, settings.clone())?))
+}
+
+/// Connects to the first WS endpoint of `urls` that accepts the connection, starting from the `attempt`-th one,
+/// so that reconnections rotate over the endpoints
+pub async fn connect_ws(urls: &[String], attempt: u32) -> Result<Provider<Ws>> {
+    let mut last_err = anyhow!("no WS endpoint configured");
+    for i in 0..urls.len() {
+        let url = &urls[(attempt as usize + i) % urls.len()];
+        match Provider::<Ws>::connect(url.as_str()).await {
+            Ok(ws_client) => {
+                info!("Connected to {}", url);
+                return Ok(ws_client);
+            }
+            Err(err) => {
+                warn!("Could not connect to {}: {}", url, err);
+                last_err = err.into();
+            }
+        }
+    }
+    Err(last_err)
+}
@@ -0,0 +1,150 @@
+use crate::rpc::EthClient;
+use anyhow::Result;
+use ethers::prelude::{Address, Bytes, TxHash, U256};
+use ethers::utils::hex::ToHexExt;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// Selector of `uniswapV3SwapCallback(int256,int256,bytes)`, called back by the pool on the router
+const SWAP_CALLBACK_SELECTOR: [u8; 4] = [0xfa, 0x46, 0x1e, 0x33];
+/// Selectors of ERC20 `transfer(address,uint256)` and `transferFrom(address,address,uint256)`
+const TRANSFER_SELECTORS: [[u8; 4]; 2] = [[0xa9, 0x05, 0x9c, 0xbb], [0x23, 0xb8, 0x72, 0xdd]];
+
+/// Call frame as returned by `debug_traceTransaction` with the `callTracer`
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallFrame {
+    #[serde(rename = "type")]
+    pub typ: String,
+    pub from: Address,
+    pub to: Option<Address>,
+    pub gas_used: U256,
+    pub input: Bytes,
+    #[serde(default)]
+    pub calls: Vec<CallFrame>,
+}
+
+/// What the gas of a call frame is spent on
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GasCategory {
+    /// The watched pool's `swap` call, including the calls made by the pool other than token transfers
+    PoolSwap,
+    /// The router or any other contract, including the swap callback
+    RouterOverhead,
+    /// ERC20 `transfer` and `transferFrom` calls, including the calls they make, e.g. to a proxy implementation
+    TokenTransfer,
+}
+
+/// Gas of a call frame, flattened in call order
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FrameGas {
+    pub depth: usize,
+    /// Call type, e.g. `CALL` or `DELEGATECALL`
+    pub call_type: String,
+    pub from: String,
+    pub to: Option<String>,
+    /// First 4 bytes of the input, if any
+    pub selector: Option<String>,
+    /// Gas used by the frame, including its sub-calls
+    pub gas_used: u64,
+    /// Gas used by the frame itself, excluding its sub-calls
+    pub self_gas_used: u64,
+    pub category: GasCategory,
+}
+
+/// Gas used by a transaction, broken down by what it was spent on
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct GasBreakdown {
+    pub tx_hash: String,
+    pub gas_used: u64,
+    pub pool_swap_gas: u64,
+    pub router_overhead_gas: u64,
+    pub token_transfer_gas: u64,
+    pub frames: Vec<FrameGas>,
+}
+
+impl CallFrame {
+    fn selector(&self) -> Option<[u8; 4]> {
+        self.input.get(..4).map(|s| [s[0], s[1], s[2], s[3]])
+    }
+
+    /// Category of the frame: frames that are not a token transfer, a call to the pool
+    /// or a swap callback are in the category of their parent
+    fn category(&self, pool_address: Address, parent: GasCategory) -> GasCategory {
+        let selector = self.selector();
+        if parent == GasCategory::TokenTransfer
+            || selector.is_some_and(|s| TRANSFER_SELECTORS.contains(&s))
+        {
+            GasCategory::TokenTransfer
+        } else if selector == Some(SWAP_CALLBACK_SELECTOR) {
+            GasCategory::RouterOverhead
+        } else if self.to == Some(pool_address) {
+            GasCategory::PoolSwap
+        } else {
+            parent
+        }
+    }
+
+    fn flatten(
+        &self,
+        depth: usize,
+        pool_address: Address,
+        parent: GasCategory,
+        frames: &mut Vec<FrameGas>,
+    ) {
+        let category = self.category(pool_address, parent);
+        let calls_gas_used = self
+            .calls
+            .iter()
+            .fold(U256::zero(), |acc, call| acc + call.gas_used);
+        frames.push(FrameGas {
+            depth,
+            call_type: self.typ.clone(),
+            from: self.from.encode_hex_with_prefix(),
+            to: self.to.map(|to| to.encode_hex_with_prefix()),
+            selector: self.selector().map(|s| s.encode_hex_with_prefix()),
+            gas_used: self.gas_used.as_u64(),
+            self_gas_used: self.gas_used.saturating_sub(calls_gas_used).as_u64(),
+            category,
+        });
+        for call in self.calls.iter() {
+            call.flatten(depth + 1, pool_address, category, frames);
+        }
+    }
+}
+
+impl GasBreakdown {
+    /// Given the call trace of a transaction, attributes the gas used by every call frame
+    /// to the watched pool's swap, the router overhead or token transfers
+    pub fn new(tx_hash: TxHash, trace: &CallFrame, pool_address: Address) -> Self {
+        let mut frames = vec![];
+        trace.flatten(0, pool_address, GasCategory::RouterOverhead, &mut frames);
+        let category_gas = |category: GasCategory| {
+            frames
+                .iter()
+                .filter(|frame| frame.category == category)
+                .map(|frame| frame.self_gas_used)
+                .sum()
+        };
+        Self {
+            tx_hash: tx_hash.encode_hex_with_prefix(),
+            gas_used: trace.gas_used.as_u64(),
+            pool_swap_gas: category_gas(GasCategory::PoolSwap),
+            router_overhead_gas: category_gas(GasCategory::RouterOverhead),
+            token_transfer_gas: category_gas(GasCategory::TokenTransfer),
+            frames,
+        }
+    }
+}
+
+/// Get the call trace of a transaction with `debug_traceTransaction` and the `callTracer`
+pub async fn trace_transaction(tx_hash: TxHash, eth_client: &EthClient) -> Result<CallFrame> {
+    let trace = eth_client
+        .request(
+            "debug_traceTransaction",
+            (tx_hash, json!({"tracer": "callTracer"})),
+        )
+        .await?;
+    Ok(trace)
+}
@@ -1,20 +1,22 @@
+use crate::rpc::EthClient;
 use anyhow::{anyhow, Error, Result};
 use ethers::abi::Address;
 use ethers::middleware::Middleware;
-use ethers::prelude::{Http, Provider, TransactionReceipt, H256, I256};
+use ethers::prelude::{Block, BlockId, Transaction, TransactionReceipt, H256, I256, U256};
 use ethers::types::{Bytes, TxHash};
 use ethers::utils::format_units;
 use ethers::utils::hex::ToHexExt;
-use num_bigint::BigInt;
+use num_bigint::{BigInt, Sign};
 use num_traits::ToPrimitive;
 use std::ops::BitXor;
 use std::time;
+use std::time::{SystemTime, UNIX_EPOCH};
 use tracing::info;
 
 /// Get transaction receipt for given transaction hash
 pub async fn try_get_tx_receipt(
     tx_hash: TxHash,
-    eth_client: &Provider<Http>,
+    eth_client: &EthClient,
 ) -> Result<TransactionReceipt> {
     // The tx receipt may not be found immediately after receiving the event log,
     // so a retry logic is used to try fetch the receipt every second for 5 seconds
@@ -43,17 +45,109 @@ pub async fn try_get_tx_receipt(
     }
 }
 
-/// Given a tx receipt, computes the gas fee in ETH
-pub async fn compute_gas_fee_eth(tx: &TransactionReceipt) -> Result<f64, Error> {
-    let gas_price = tx
-        .effective_gas_price
-        .ok_or(anyhow!("effective gas price not found in tx receipt"))?;
+/// Given a tx receipt, get the block containing the transaction
+pub async fn get_receipt_block(
+    tx: &TransactionReceipt,
+    eth_client: &EthClient,
+) -> Result<Block<H256>> {
+    let block_hash = tx
+        .block_hash
+        .ok_or(anyhow!("block hash not found in tx receipt"))?;
+    eth_client
+        .get_block(BlockId::Hash(block_hash))
+        .await?
+        .ok_or(anyhow!(
+            "block {} not found",
+            block_hash.encode_hex_with_prefix()
+        ))
+}
+
+/// Given a tx receipt without effective gas price, as returned by older nodes and for pre-London blocks,
+/// get the transaction to derive the gas price from. Returns None if the receipt has the effective gas price.
+pub async fn get_transaction_if_needed(
+    tx: &TransactionReceipt,
+    eth_client: &EthClient,
+) -> Result<Option<Transaction>> {
+    if tx.effective_gas_price.is_some() {
+        return Ok(None);
+    }
+    info!(
+        "Effective gas price not found in receipt of tx hash {}, getting transaction",
+        tx.transaction_hash.encode_hex_with_prefix()
+    );
+    let transaction = eth_client
+        .get_transaction(tx.transaction_hash)
+        .await?
+        .ok_or(anyhow!(
+            "transaction {} not found",
+            tx.transaction_hash.encode_hex_with_prefix()
+        ))?;
+    Ok(Some(transaction))
+}
+
+/// Given a tx receipt and the base fee of its block, computes the part of the gas fee burned, in wei.
+/// Blocks before the London fork have no base fee, so nothing is burned.
+pub fn compute_burned_fee_wei(
+    tx: &TransactionReceipt,
+    base_fee_per_gas: Option<U256>,
+) -> Result<U256, Error> {
     let gas = tx
         .gas_used
         .ok_or(anyhow!("gas used not found in tx receipt"))?;
-    let gas_eth = gas_price * gas;
-    let gas_eth_str = format_units(gas_eth, "ether")?;
-    Ok(gas_eth_str.parse()?)
+    Ok(base_fee_per_gas.unwrap_or_default() * gas)
+}
+
+/// Get a numeric field of the tx receipt not known by ethers, e.g. fields added by recent forks or by L2s
+pub fn get_receipt_field(tx: &TransactionReceipt, key: &str) -> Result<Option<U256>> {
+    tx.other
+        .get_deserialized::<U256>(key)
+        .transpose()
+        .map_err(|err| anyhow!("invalid {} in tx receipt: {}", key, err))
+}
+
+/// Given the receipt of a blob transaction (EIP-4844), computes the blob gas fee in wei:
+/// blob_fee = blob_gas_used * blob_gas_price. Returns None for transactions without blobs.
+pub fn compute_blob_fee_wei(tx: &TransactionReceipt) -> Result<Option<U256>> {
+    let blob_gas_used = get_receipt_field(tx, "blobGasUsed")?;
+    let blob_gas_price = get_receipt_field(tx, "blobGasPrice")?;
+    Ok(blob_gas_used
+        .zip(blob_gas_price)
+        .map(|(blob_gas_used, blob_gas_price)| blob_gas_used * blob_gas_price))
+}
+
+/// Parse a decimal string into an integer mantissa and its number of decimals,
+/// e.g. "3406.82" into (340682, 2)
+pub fn parse_decimal(s: &str) -> Result<(BigInt, u32)> {
+    let (int_part, frac_part) = s.split_once('.').unwrap_or((s, ""));
+    let mantissa = BigInt::parse_bytes(format!("{}{}", int_part, frac_part).as_bytes(), 10)
+        .ok_or(anyhow!("invalid decimal {}", s))?;
+    Ok((mantissa, frac_part.len() as u32))
+}
+
+/// Format an integer mantissa with the given number of decimals, without trailing zeros
+pub fn format_decimal(mantissa: &BigInt, decimals: u32) -> String {
+    let sign = if mantissa.sign() == Sign::Minus {
+        "-"
+    } else {
+        ""
+    };
+    let digits = mantissa.magnitude().to_string();
+    let decimals = decimals as usize;
+    let digits = format!("{:0>width$}", digits, width = decimals + 1);
+    let (int_part, frac_part) = digits.split_at(digits.len() - decimals);
+    let frac_part = frac_part.trim_end_matches('0');
+    if frac_part.is_empty() {
+        format!("{}{}", sign, int_part)
+    } else {
+        format!("{}{}.{}", sign, int_part, frac_part)
+    }
+}
+
+/// Exact product of two decimal strings, divided by 10^shift
+pub fn mul_decimal(a: &str, b: &str, shift: u32) -> Result<String> {
+    let (a, a_decimals) = parse_decimal(a)?;
+    let (b, b_decimals) = parse_decimal(b)?;
+    Ok(format_decimal(&(a * b), a_decimals + b_decimals + shift))
 }
 
 /// Convert a hex string into a BigInt
@@ -86,20 +180,29 @@ pub fn log_data_to_price(data: Bytes) -> Result<f64> {
     let amount1 = amount1
         .to_isize()
         .ok_or(anyhow!("error converting bigint {:?}", amount1))?;
-    let amount0 = I256::from(amount0);
-    let amount1 = I256::from(amount1);
+    amounts_to_price(I256::from(amount0), I256::from(amount1))
+}
+
+/// Given amount0 and amount1 of a swap on the WETH-USDC-500 pool, compute the price as amount_usdc/amount_weth
+pub fn amounts_to_price(amount0: I256, amount1: I256) -> Result<f64> {
     // Note: this function is specific for the pool 0x88e6A0c2dDD26FEEb64F039a2c41296FcB3f5640 on mainnet
     let amount_usdc = format_units(amount0, 6)?.parse::<f64>()?;
     let amount_weth = format_units(amount1, 18)?.parse::<f64>()?;
     Ok((amount_usdc / amount_weth).abs())
 }
 
+/// Given amount0 of a swap on the WETH-USDC-500 pool, compute the notional in USD as the USDC amount
+pub fn amount_to_notional_usd(amount0: I256) -> Result<f64> {
+    // Note: this function is specific for the pool 0x88e6A0c2dDD26FEEb64F039a2c41296FcB3f5640 on mainnet
+    Ok(format_units(amount0, 6)?.parse::<f64>()?.abs())
+}
+
 /// Given a tx hash, return the swap price if a swap event is found for the given topic and address
 pub async fn tx_hash_to_price(
     swap_topic: H256,
     pool_address: Address,
     tx_hash: TxHash,
-    eth_client: &Provider<Http>,
+    eth_client: &EthClient,
 ) -> Result<f64> {
     let tx_receipt = try_get_tx_receipt(tx_hash, eth_client).await?;
     let logs: Vec<_> = tx_receipt
@@ -115,3 +218,29 @@ pub async fn tx_hash_to_price(
     }
     log_data_to_price(logs[0].clone().data)
 }
+
+/// Given a tx receipt, return the log index and amount0 of every swap event for the given topic and address
+pub fn receipt_swap_amounts0(
+    tx_receipt: &TransactionReceipt,
+    swap_topic: H256,
+    pool_address: Address,
+) -> Vec<(U256, I256)> {
+    tx_receipt
+        .logs
+        .iter()
+        .filter(|log| log.topics.first() == Some(&swap_topic) && log.address == pool_address)
+        .filter(|log| log.data.len() >= 32)
+        .map(|log| {
+            let amount0 = I256::from_raw(U256::from_big_endian(&log.data[..32]));
+            (log.log_index.unwrap_or_default(), amount0)
+        })
+        .collect()
+}
+
+/// Current unix time in milliseconds
+pub fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system time before unix epoch")
+        .as_millis() as i64
+}
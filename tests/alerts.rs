@@ -0,0 +1,235 @@
+mod common;
+
+use actix_web::{post, web, App, HttpResponse, HttpServer, Responder};
+use common::{decode_swap_log, swap_log, Fixtures, MockServer};
+use ethers::prelude::U256;
+use sqlx::postgres::PgPoolOptions;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+use uniswap_watcher::alerts::{check_swap, deliver_alert, price_impact_bps, PoolAlertThreshold};
+use uniswap_watcher::db::{run_migrations, DbRecord};
+use uniswap_watcher::ingest::SwapProcessor;
+use uniswap_watcher::AppConfig;
+
+const LEGACY_TX_HASH: &str = "0x5c504ed432cb51138bcf09aa5e8a410dd4a1e204ef84bfed1be16dfba1b22060";
+const LEGACY_BLOCK_HASH: &str =
+    "0x4e3a3754410177e6937ef1f84bba68ea139e8d1a2258c5f85db9f1cd715a1bdd";
+
+fn thresholds() -> PoolAlertThreshold {
+    PoolAlertThreshold {
+        pool_address: "0x88e6A0c2dDD26FEEb64F039a2c41296FcB3f5640".into(),
+        min_notional_usd: 1000000.0,
+        max_price_impact_bps: 10.0,
+    }
+}
+
+#[test]
+fn price_impact() {
+    let before = U256::from(1000000u64);
+    let after = U256::from(1001000u64);
+    assert!((price_impact_bps(before, after) - 20.01).abs() < 1e-6);
+    assert_eq!(price_impact_bps(before, before), 0.0);
+}
+
+#[test]
+fn alerts_for_passed_thresholds() {
+    let alerts = check_swap(&thresholds(), "0x01", 7, 2000000.0, Some(15.0));
+    let kinds: Vec<_> = alerts.iter().map(|alert| alert.kind.as_str()).collect();
+    assert_eq!(kinds, vec!["notional", "price_impact"]);
+    assert_eq!(alerts[0].id, "0x01-7-notional");
+
+    let alerts = check_swap(&thresholds(), "0x01", 7, 1000.0, None);
+    assert!(alerts.is_empty());
+}
+
+#[post("/hook")]
+async fn flaky_hook(calls: web::Data<Arc<AtomicUsize>>) -> impl Responder {
+    // Fail the first call, accept the following ones
+    if calls.fetch_add(1, Ordering::SeqCst) == 0 {
+        HttpResponse::InternalServerError().finish()
+    } else {
+        HttpResponse::Ok().finish()
+    }
+}
+
+/// Starts a webhook failing its first call, and returns its port and the number of calls it received
+fn start_hook() -> (u16, Arc<AtomicUsize>) {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let data = calls.clone();
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(web::Data::new(data.clone()))
+            .service(flaky_hook)
+    })
+    .bind("127.0.0.1:0")
+    .unwrap();
+    let port = server.addrs()[0].port();
+    tokio::spawn(server.run());
+    (port, calls)
+}
+
+#[tokio::test]
+async fn webhook_delivery_retries() {
+    let (port, calls) = start_hook();
+
+    let alert = check_swap(&thresholds(), "0x01", 7, 2000000.0, None).remove(0);
+    let (sender, receiver) = mpsc::channel();
+    deliver_alert(
+        alert,
+        vec![format!("http://127.0.0.1:{}/hook", port)],
+        3,
+        sender,
+    )
+    .await;
+
+    let deliveries: Vec<_> = receiver
+        .try_iter()
+        .map(|record| match record {
+            DbRecord::AlertDelivery(delivery) => delivery,
+            other => panic!("unexpected record {:?}", other),
+        })
+        .collect();
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+    assert_eq!(deliveries.len(), 2);
+    assert_eq!(deliveries[0].status_code, Some(500));
+    assert!(!deliveries[0].delivered);
+    assert_eq!(deliveries[1].attempt, 2);
+    assert!(deliveries[1].delivered);
+}
+
+#[tokio::test]
+async fn alert_delivered_once() {
+    let fixtures = Fixtures::default()
+        .with_file(
+            "eth_getTransactionReceipt",
+            LEGACY_TX_HASH,
+            "legacy_tx_receipt.json",
+        )
+        .with_file("eth_getTransactionByHash", LEGACY_TX_HASH, "legacy_tx.json")
+        .with_file(
+            "eth_getBlockByHash",
+            LEGACY_BLOCK_HASH,
+            "legacy_tx_block.json",
+        );
+    let server = MockServer::start(fixtures);
+    let (port, calls) = start_hook();
+    let mut config = AppConfig::new().unwrap();
+    config.rpc_urls_http = vec![server.url.clone()];
+    config.binance_host = server.url.clone();
+    config.alerts.webhooks = vec![format!("http://127.0.0.1:{}/hook", port)];
+    config.alerts.max_retries = 1;
+    config.alerts.pools = vec![PoolAlertThreshold {
+        pool_address: config.pool_address.clone(),
+        min_notional_usd: 1000.0,
+        max_price_impact_bps: 10.0,
+    }];
+
+    let db_connection = PgPoolOptions::new()
+        .connect_timeout(Duration::from_secs(2))
+        .connect(&config.database.connection_string(false))
+        .await
+        .expect("Failed to create db connection");
+    run_migrations(&db_connection)
+        .await
+        .expect("Failed to run db migrations");
+    _ = sqlx::query("DELETE FROM alerts WHERE tx_hash = $1")
+        .bind(LEGACY_TX_HASH)
+        .execute(&db_connection)
+        .await;
+
+    // The same swap is ingested again, as after a reorg or a restart
+    let log = swap_log(&config, LEGACY_TX_HASH, LEGACY_BLOCK_HASH, 12487760);
+    for _ in 0..2 {
+        let (sender, _) = mpsc::channel();
+        let mut processor = SwapProcessor::new(config.clone(), sender, db_connection.clone(), None);
+        let (log, meta) = decode_swap_log(log.clone());
+        assert!(processor.handle(log, meta, false).await.unwrap());
+        processor.flush().await;
+        // Let the alert be raised
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+
+    // The alert is stored and delivered once: the first call fails, and is not retried
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+    let (alerts,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM alerts WHERE tx_hash = $1")
+        .bind(LEGACY_TX_HASH)
+        .fetch_one(&db_connection)
+        .await
+        .unwrap();
+    assert_eq!(alerts, 1);
+}
+
+#[tokio::test]
+async fn price_impact_after_failed_swap() {
+    let fixtures = Fixtures::default()
+        .with_file(
+            "eth_getTransactionReceipt",
+            LEGACY_TX_HASH,
+            "legacy_tx_receipt.json",
+        )
+        .with_file("eth_getTransactionByHash", LEGACY_TX_HASH, "legacy_tx.json")
+        .with_file(
+            "eth_getBlockByHash",
+            LEGACY_BLOCK_HASH,
+            "legacy_tx_block.json",
+        );
+    let server = MockServer::start(fixtures);
+    let mut config = AppConfig::new().unwrap();
+    config.rpc_urls_http = vec![server.url.clone()];
+    config.binance_host = server.url.clone();
+    config.alerts.webhooks = vec![];
+    config.alerts.pools = vec![PoolAlertThreshold {
+        pool_address: config.pool_address.clone(),
+        min_notional_usd: f64::MAX,
+        max_price_impact_bps: 10.0,
+    }];
+
+    let db_connection = PgPoolOptions::new()
+        .connect_timeout(Duration::from_secs(2))
+        .connect(&config.database.connection_string(false))
+        .await
+        .expect("Failed to create db connection");
+    run_migrations(&db_connection)
+        .await
+        .expect("Failed to run db migrations");
+    _ = sqlx::query("DELETE FROM alerts WHERE tx_hash = $1")
+        .bind(LEGACY_TX_HASH)
+        .execute(&db_connection)
+        .await;
+
+    // The second swap moves the price but cannot be fetched, the third one moves it back
+    let failed_tx_hash = format!("0x{:064x}", 0xdead);
+    let swaps = [
+        (LEGACY_TX_HASH, 0, 1),
+        (failed_tx_hash.as_str(), 1, 2),
+        (LEGACY_TX_HASH, 2, 1),
+    ];
+    let (sender, receiver) = mpsc::channel();
+    let mut processor = SwapProcessor::new(config.clone(), sender, db_connection.clone(), None);
+    for (tx_hash, log_index, price_factor) in swaps {
+        let log = swap_log(&config, tx_hash, LEGACY_BLOCK_HASH, 12487760);
+        let (mut log, mut meta) = decode_swap_log(log);
+        log.sqrt_price_x96 *= price_factor;
+        meta.log_index = U256::from(log_index);
+        assert!(processor.handle(log, meta, false).await.unwrap());
+    }
+    processor.flush().await;
+    // Let the alerts be raised
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let failed = receiver
+        .try_iter()
+        .filter(|record| matches!(record, DbRecord::IngestionError(_)))
+        .count();
+    assert_eq!(failed, 1);
+    // The impact of the third swap is computed from the price after the failed one
+    let (ids,): (Vec<String>,) = sqlx::query_as(
+        "SELECT COALESCE(ARRAY_AGG(id), '{}') FROM alerts WHERE tx_hash = $1 AND kind = 'price_impact'",
+    )
+    .bind(LEGACY_TX_HASH)
+    .fetch_one(&db_connection)
+    .await
+    .unwrap();
+    assert_eq!(ids, vec![format!("{}-2-price_impact", LEGACY_TX_HASH)]);
+}
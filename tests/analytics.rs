@@ -0,0 +1,239 @@
+use sqlx::postgres::PgPoolOptions;
+use sqlx::PgPool;
+use uniswap_watcher::db::{
+    get_address_activity_from_db, get_gas_share_buckets_from_db, insert_swap, insert_tx_fee,
+    run_migrations, AddressRole, DatabaseSettings, Swap, TxFee,
+};
+use uniswap_watcher::fee::gas_share_of_notional;
+
+const TRADER: &str = "0x00000000000000000000000000000000000000aa";
+const ROUTER: &str = "0x00000000000000000000000000000000000000bb";
+
+pub async fn get_db_connection() -> PgPool {
+    let settings = DatabaseSettings {
+        username: "postgres".into(),
+        password: "password".into(),
+        port: 5432,
+        host: "127.0.0.1".into(),
+        database_name: "postgres_db".into(),
+    };
+    let pool = PgPoolOptions::new()
+        .connect_timeout(std::time::Duration::from_secs(2))
+        .connect(&settings.connection_string(false))
+        .await
+        .expect("Failed to create db connection");
+    run_migrations(&pool)
+        .await
+        .expect("Failed to run db migrations");
+    _ = sqlx::query(
+        "DELETE FROM fees WHERE tx_hash IN (SELECT tx_hash FROM swaps WHERE tx_from = $1)",
+    )
+    .bind(TRADER)
+    .execute(&pool)
+    .await;
+    _ = sqlx::query("DELETE FROM swaps WHERE tx_from = $1")
+        .bind(TRADER)
+        .execute(&pool)
+        .await;
+    pool
+}
+
+fn swap(tx_hash: &str, log_index: i64, amount0: &str, amount1: &str, notional_usd: f64) -> Swap {
+    Swap {
+        tx_hash: tx_hash.into(),
+        log_index,
+        pool_address: "0x88e6a0c2ddd26feeb64f039a2c41296fcb3f5640".into(),
+        block_number: 1,
+        block_hash: None,
+        timestamp_ms: 1_000,
+        sender: ROUTER.into(),
+        recipient: TRADER.into(),
+        tx_from: TRADER.into(),
+        amount0: amount0.into(),
+        amount1: amount1.into(),
+        price: 3000.0,
+        notional_usd,
+        fee_wei: None,
+        gas_usd: None,
+        gas_share: None,
+    }
+}
+
+#[tokio::test]
+async fn address_activity() {
+    let db_connection = get_db_connection().await;
+    let tx1 = "0x00000000000000000000000000000000000000000000000000000000000000a1";
+    let tx2 = "0x00000000000000000000000000000000000000000000000000000000000000a2";
+    // Two swaps in the same transaction and one in another: gas must be counted once per tx
+    for data in [
+        swap(tx1, 1, "3000000000", "-1000000000000000000", 3000.0),
+        swap(tx1, 2, "3000000000", "-1000000000000000000", 3000.0),
+        swap(tx2, 1, "-6000000000", "2000000000000000000", 6000.0),
+    ] {
+        insert_swap(&data, &db_connection).await.unwrap();
+    }
+    // A swap processed again, e.g. after a restart, is not stored twice
+    insert_swap(
+        &swap(tx1, 1, "3000000000", "-1000000000000000000", 3000.0),
+        &db_connection,
+    )
+    .await
+    .unwrap();
+    // 0.005 ETH = 10 USDT and 0.0025 ETH = 5 USDT at 2000 USDT
+    for (tx_hash, fee_wei) in [(tx1, "5000000000000000"), (tx2, "2500000000000000")] {
+        _ = insert_tx_fee(
+            &TxFee {
+                tx_hash: tx_hash.into(),
+                fee_wei: fee_wei.into(),
+                eth_usdt_price: "2000".into(),
+                ..Default::default()
+            },
+            &db_connection,
+        )
+        .await;
+    }
+
+    let activity = get_address_activity_from_db(
+        AddressRole::From,
+        Some(TRADER),
+        None,
+        None,
+        None,
+        1,
+        &db_connection,
+    )
+    .await
+    .unwrap();
+    assert_eq!(activity.len(), 1);
+    let activity = &activity[0];
+    assert_eq!(activity.swap_count, 3);
+    assert_eq!(activity.volume_usd, 12000.0);
+    assert_eq!(activity.avg_size_usd, 4000.0);
+    assert_eq!(activity.gas_paid_usdt, 15.0);
+    assert_eq!(activity.net_amount0, "0");
+    assert_eq!(activity.net_amount1, "0");
+
+    let leaderboard = get_address_activity_from_db(
+        AddressRole::Sender,
+        None,
+        Some(0),
+        Some(2_000),
+        None,
+        10,
+        &db_connection,
+    )
+    .await
+    .unwrap();
+    assert!(leaderboard
+        .iter()
+        .any(|activity| activity.address == ROUTER));
+}
+
+#[tokio::test]
+async fn attributed_swap_fees() {
+    let db_connection = get_db_connection().await;
+    let tx = "0x00000000000000000000000000000000000000000000000000000000000000a3";
+    let recipients = [
+        "0x00000000000000000000000000000000000000c1",
+        "0x00000000000000000000000000000000000000c2",
+    ];
+    _ = sqlx::query("DELETE FROM swaps WHERE tx_hash = $1")
+        .bind(tx)
+        .execute(&db_connection)
+        .await;
+    // 0.004 ETH = 8 USDT split in proportion to notional between the two swaps of the tx
+    for (log_index, (recipient, fee_wei)) in recipients
+        .iter()
+        .zip(["3000000000000000", "1000000000000000"])
+        .enumerate()
+    {
+        let mut data = swap(tx, log_index as i64, "0", "0", 0.0);
+        data.recipient = recipient.to_string();
+        data.tx_from = "0x00000000000000000000000000000000000000ac".into();
+        data.fee_wei = Some(fee_wei.into());
+        insert_swap(&data, &db_connection).await.unwrap();
+    }
+    _ = insert_tx_fee(
+        &TxFee {
+            tx_hash: tx.into(),
+            fee_wei: "4000000000000000".into(),
+            eth_usdt_price: "2000".into(),
+            ..Default::default()
+        },
+        &db_connection,
+    )
+    .await;
+
+    for (recipient, gas_paid_usdt) in recipients.iter().zip([6.0, 2.0]) {
+        let activity = get_address_activity_from_db(
+            AddressRole::Recipient,
+            Some(recipient),
+            None,
+            None,
+            None,
+            1,
+            &db_connection,
+        )
+        .await
+        .unwrap();
+        assert_eq!(activity[0].gas_paid_usdt, gas_paid_usdt);
+    }
+}
+
+#[test]
+fn gas_share() {
+    // 0.005 ETH = 10 USDT at 2000 USDT, on a swap of 500 USD
+    let (gas_usd, gas_share) = gas_share_of_notional("5000000000000000", "2000", 500.0).unwrap();
+    assert_eq!(gas_usd, 10.0);
+    assert_eq!(gas_share, Some(0.02));
+    assert_eq!(gas_share_of_notional("1", "2000", 0.0).unwrap().1, None);
+}
+
+#[tokio::test]
+async fn gas_share_distribution() {
+    let db_connection = get_db_connection().await;
+    let pool_address = "0x00000000000000000000000000000000000000dd";
+    _ = sqlx::query("DELETE FROM swaps WHERE pool_address = $1")
+        .bind(pool_address)
+        .execute(&db_connection)
+        .await;
+    // Two swaps in the first hour, two in the second one, of which one above 1000 USD
+    for (i, (timestamp_ms, notional_usd, gas_usd)) in [
+        (1_000, 500.0, 10.0),
+        (2_000, 800.0, 4.0),
+        (3_601_000, 5000.0, 100.0),
+        (3_602_000, 200.0, 1.0),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let mut data = swap(pool_address, i as i64, "0", "0", notional_usd);
+        data.pool_address = pool_address.into();
+        data.tx_from = pool_address.into();
+        data.timestamp_ms = timestamp_ms;
+        data.gas_usd = Some(gas_usd);
+        data.gas_share = Some(gas_usd / notional_usd);
+        insert_swap(&data, &db_connection).await.unwrap();
+    }
+
+    let buckets = get_gas_share_buckets_from_db(
+        Some(pool_address),
+        None,
+        None,
+        3_600_000,
+        Some(1000.0),
+        0.01,
+        None,
+        &db_connection,
+    )
+    .await
+    .unwrap();
+    assert_eq!(buckets.len(), 2);
+    assert_eq!(buckets[0].bucket_start_ms, 3_600_000);
+    assert_eq!(buckets[0].swap_count, 1);
+    assert_eq!(buckets[0].above_threshold_count, 0);
+    assert_eq!(buckets[1].bucket_start_ms, 0);
+    assert_eq!(buckets[1].swap_count, 2);
+    assert_eq!(buckets[1].above_threshold_count, 1);
+    assert!((buckets[1].median_gas_share - 0.0125).abs() < 1e-12);
+}
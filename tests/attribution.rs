@@ -0,0 +1,59 @@
+use ethers::types::{Bytes, Log, TransactionReceipt, H256, I256, U256};
+use std::str::FromStr;
+use uniswap_watcher::fee::GasAttribution;
+use uniswap_watcher::util::receipt_swap_amounts0;
+
+#[test]
+fn split_equally() {
+    let notionals = [U256::from(1), U256::from(5), U256::from(0)];
+    let shares = GasAttribution::Equal.split(U256::from(100), &notionals);
+    // Rounding dust goes to the last swap
+    assert_eq!(shares, vec![U256::from(33), U256::from(33), U256::from(34)]);
+}
+
+#[test]
+fn split_by_notional() {
+    let notionals = [U256::from(1000), U256::from(3000)];
+    let shares = GasAttribution::Notional.split(U256::from(101), &notionals);
+    assert_eq!(shares, vec![U256::from(25), U256::from(76)]);
+
+    // Without notional the fee is split equally
+    let shares = GasAttribution::Notional.split(U256::from(10), &[U256::zero(), U256::zero()]);
+    assert_eq!(shares, vec![U256::from(5), U256::from(5)]);
+}
+
+#[test]
+fn swap_amounts_from_receipt() {
+    let swap_topic =
+        H256::from_str("0xc42079f94a6350d7e6235f29174924f928cc2ac818eb64fed8004e115fbcca67")
+            .unwrap();
+    let pool = "0x88e6A0c2dDD26FEEb64F039a2c41296FcB3f5640"
+        .parse()
+        .unwrap();
+    let log = |log_index: u64, amount0: I256, address| {
+        let mut data = [0u8; 64];
+        amount0.into_raw().to_big_endian(&mut data[..32]);
+        Log {
+            address,
+            topics: vec![swap_topic],
+            data: Bytes::from(data.to_vec()),
+            log_index: Some(U256::from(log_index)),
+            ..Default::default()
+        }
+    };
+    let receipt = TransactionReceipt {
+        logs: vec![
+            log(3, I256::from(-2_000_000), pool),
+            log(4, I256::from(1_000_000), Default::default()),
+            log(7, I256::from(5_000_000), pool),
+        ],
+        ..Default::default()
+    };
+    assert_eq!(
+        receipt_swap_amounts0(&receipt, swap_topic, pool),
+        vec![
+            (U256::from(3), I256::from(-2_000_000)),
+            (U256::from(7), I256::from(5_000_000)),
+        ]
+    );
+}
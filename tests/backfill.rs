@@ -0,0 +1,185 @@
+mod common;
+
+use common::{Fixtures, MockServer};
+use ethers::types::H256;
+use serde_json::{json, Value};
+use sqlx::postgres::PgPoolOptions;
+use sqlx::PgPool;
+use std::str::FromStr;
+use std::sync::mpsc;
+use uniswap_watcher::backfill::backfill;
+use uniswap_watcher::db::{get_backfill_progress_from_db, get_tx_fee_from_db, run_migrations};
+use uniswap_watcher::{AppConfig, Application};
+
+const LEGACY_TX_HASH: &str = "0x5c504ed432cb51138bcf09aa5e8a410dd4a1e204ef84bfed1be16dfba1b22060";
+const LEGACY_BLOCK_HASH: &str =
+    "0x4e3a3754410177e6937ef1f84bba68ea139e8d1a2258c5f85db9f1cd715a1bdd";
+const BLOB_TX_HASH: &str = "0xbb1e0f1c1accfedcc1dddfcc48d7d0bf893263a77e74c3d26c63adf729e89e69";
+const BLOB_BLOCK_HASH: &str = "0xd3d17a9c8eb69157f59feb3c42cc46126d390efae0c8525f7377115d5d476235";
+/// Tx whose block cannot be fetched, so that its fee cannot be computed
+const BROKEN_TX_HASH: &str = "0x00000000000000000000000000000000000000000000000000000000000dead4";
+const BROKEN_BLOCK_HASH: &str =
+    "0x00000000000000000000000000000000000000000000000000000000000b10c4";
+const FROM_BLOCK: u64 = 100;
+const TO_BLOCK: u64 = 107;
+
+/// Swap log of the given transaction on the watched pool
+fn swap_log(config: &AppConfig, tx_hash: &str, block_hash: &str, block_number: u64) -> Value {
+    json!({
+        "address": config.pool_address,
+        "topics": [config.swap_topic],
+        "data": "0x",
+        "blockHash": block_hash,
+        "blockNumber": format!("{:#x}", block_number),
+        "transactionHash": tx_hash,
+        "transactionIndex": "0x0",
+        "logIndex": "0x0",
+        "removed": false
+    })
+}
+
+/// Receipts, transactions and blocks of the legacy and blob transactions
+fn tx_fixtures() -> Fixtures {
+    Fixtures::default()
+        .with_file(
+            "eth_getTransactionReceipt",
+            LEGACY_TX_HASH,
+            "legacy_tx_receipt.json",
+        )
+        .with_file("eth_getTransactionByHash", LEGACY_TX_HASH, "legacy_tx.json")
+        .with_file(
+            "eth_getBlockByHash",
+            LEGACY_BLOCK_HASH,
+            "legacy_tx_block.json",
+        )
+        .with_file(
+            "eth_getTransactionReceipt",
+            BLOB_TX_HASH,
+            "blob_tx_receipt.json",
+        )
+        .with_file("eth_getBlockByHash", BLOB_BLOCK_HASH, "blob_tx_block.json")
+        .with(
+            "eth_getTransactionReceipt",
+            BROKEN_TX_HASH,
+            broken_receipt(),
+        )
+}
+
+/// Receipt of the legacy transaction, moved to `BROKEN_TX_HASH` in a block without fixture
+fn broken_receipt() -> Value {
+    let path = format!(
+        "{}/tests/fixtures/legacy_tx_receipt.json",
+        env!("CARGO_MANIFEST_DIR")
+    );
+    let mut receipt: Value = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    receipt["transactionHash"] = json!(BROKEN_TX_HASH);
+    receipt["blockHash"] = json!(BROKEN_BLOCK_HASH);
+    receipt["blockNumber"] = json!("0x6a");
+    receipt
+}
+
+async fn start_app(fixtures: Fixtures, db_connection: PgPool) -> (Application, MockServer) {
+    let server = MockServer::start(fixtures);
+    let mut config = AppConfig::new().unwrap();
+    config.rpc_urls_http = vec![server.url.clone()];
+    config.binance_host = server.url.clone();
+    config.backfill.chunk_blocks = 4;
+    config.backfill.concurrency = 2;
+    let (sender, _) = mpsc::channel();
+    (
+        Application::new(config, sender, db_connection).unwrap(),
+        server,
+    )
+}
+
+#[tokio::test]
+async fn backfill_resumes_and_splits_ranges() {
+    let config = AppConfig::new().unwrap();
+    let db_connection = PgPoolOptions::new()
+        .connect_timeout(std::time::Duration::from_secs(2))
+        .connect(&config.database.connection_string(false))
+        .await
+        .expect("Failed to create db connection");
+    run_migrations(&db_connection)
+        .await
+        .expect("Failed to run db migrations");
+    for tx_hash in [LEGACY_TX_HASH, BLOB_TX_HASH, BROKEN_TX_HASH] {
+        _ = sqlx::query("DELETE FROM fees WHERE tx_hash = $1")
+            .bind(tx_hash)
+            .execute(&db_connection)
+            .await;
+    }
+    _ = sqlx::query("DELETE FROM ingestion_errors WHERE tx_hash = $1")
+        .bind(BROKEN_TX_HASH)
+        .execute(&db_connection)
+        .await;
+    _ = sqlx::query("DELETE FROM backfill_progress WHERE from_block = $1 AND to_block = $2")
+        .bind(FROM_BLOCK as i64)
+        .bind(TO_BLOCK as i64)
+        .execute(&db_connection)
+        .await;
+    let progress = || {
+        get_backfill_progress_from_db(
+            config.pool_address.as_str(),
+            FROM_BLOCK as i64,
+            TO_BLOCK as i64,
+            &db_connection,
+        )
+    };
+
+    // The first range has too many results and is split in two, the second range fails
+    let fixtures = tx_fixtures()
+        .with_logs_error(100, 103, "query returned more than 10000 results")
+        .with_logs(
+            100,
+            101,
+            json!([swap_log(&config, LEGACY_TX_HASH, LEGACY_BLOCK_HASH, 101)]),
+        )
+        .with_logs(102, 103, json!([]))
+        .with_logs_error(104, 107, "internal error");
+    let (app, _server) = start_app(fixtures, db_connection.clone()).await;
+    assert!(backfill(&app, FROM_BLOCK, TO_BLOCK).await.is_err());
+    assert_eq!(progress().await.unwrap(), Some(104));
+    let fee = get_tx_fee_from_db(&H256::from_str(LEGACY_TX_HASH).unwrap(), &db_connection)
+        .await
+        .unwrap();
+    assert_eq!(fee.fee_wei, "1050000000000000");
+
+    // The backfill resumes from the second range: the first one is not requested again.
+    // The tx whose fee cannot be computed is recorded as an ingestion error, and skipped.
+    let fixtures = tx_fixtures().with_logs(
+        104,
+        107,
+        json!([
+            swap_log(&config, BLOB_TX_HASH, BLOB_BLOCK_HASH, 105),
+            swap_log(&config, BLOB_TX_HASH, BLOB_BLOCK_HASH, 105),
+            swap_log(&config, BROKEN_TX_HASH, BROKEN_BLOCK_HASH, 106)
+        ]),
+    );
+    let (app, server) = start_app(fixtures, db_connection.clone()).await;
+    backfill(&app, FROM_BLOCK, TO_BLOCK).await.unwrap();
+    assert_eq!(progress().await.unwrap(), Some(108));
+    // Backfilled fees are not ranked: the receipts of their whole block are not fetched
+    assert_eq!(server.requests("eth_getBlockReceipts"), 0);
+    assert_eq!(server.requests("eth_getBlockByNumber"), 0);
+    let fee = get_tx_fee_from_db(&H256::from_str(BLOB_TX_HASH).unwrap(), &db_connection)
+        .await
+        .unwrap();
+    assert_eq!(fee.fee_wei, "472144000000000");
+    assert!(
+        get_tx_fee_from_db(&H256::from_str(BROKEN_TX_HASH).unwrap(), &db_connection)
+            .await
+            .is_err()
+    );
+    let (errors,): (i64,) = sqlx::query_as(
+        "SELECT COUNT(*) FROM ingestion_errors WHERE tx_hash = $1 AND block_number = 106",
+    )
+    .bind(BROKEN_TX_HASH)
+    .fetch_one(&db_connection)
+    .await
+    .unwrap();
+    assert_eq!(errors, 1);
+
+    // Nothing left to do
+    backfill(&app, FROM_BLOCK, TO_BLOCK).await.unwrap();
+}
@@ -0,0 +1,109 @@
+use anyhow::Result;
+use ethers::prelude::U256;
+use sqlx::postgres::PgPoolOptions;
+use uniswap_watcher::basis::{compute_basis_bps, observe_basis, BasisSettings};
+use uniswap_watcher::db::{insert_basis, insert_opportunity, run_migrations, DatabaseSettings};
+
+fn settings() -> BasisSettings {
+    BasisSettings {
+        fee_tier_bps: 5.0,
+        swap_gas: 150000,
+        trade_size_usd: 100000.0,
+    }
+}
+
+#[test]
+fn basis_bps() {
+    assert_eq!(compute_basis_bps(3010.0, 3000.0), 33.333333333333336);
+    assert_eq!(compute_basis_bps(2990.0, 3000.0), -33.333333333333336);
+}
+
+#[test]
+fn opportunity_above_threshold() -> Result<()> {
+    // Gas cost: 150000 * 20 gwei = 0.003 ETH = 9 USD, i.e. 0.9 bps on a 100k trade
+    let gas_price = U256::from(20_000_000_000u64);
+    let (point, opportunity) = observe_basis(
+        &settings(),
+        "0x01".into(),
+        0,
+        1,
+        0,
+        3010.0,
+        3000.0,
+        gas_price,
+    )?;
+    assert_eq!(point.basis_bps, 33.333333333333336);
+    let opportunity = opportunity.unwrap();
+    assert_eq!(opportunity.gas_cost_usd, 9.0);
+    assert!((opportunity.threshold_bps - 5.9).abs() < 1e-9);
+    Ok(())
+}
+
+#[test]
+fn no_opportunity_below_threshold() -> Result<()> {
+    let gas_price = U256::from(20_000_000_000u64);
+    let (_, opportunity) = observe_basis(
+        &settings(),
+        "0x01".into(),
+        0,
+        1,
+        0,
+        3001.0,
+        3000.0,
+        gas_price,
+    )?;
+    assert!(opportunity.is_none());
+    Ok(())
+}
+
+#[tokio::test]
+async fn basis_stored_once_per_swap() -> Result<()> {
+    let db_settings = DatabaseSettings {
+        username: "postgres".into(),
+        password: "password".into(),
+        port: 5432,
+        host: "127.0.0.1".into(),
+        database_name: "postgres_db".into(),
+    };
+    let pool = PgPoolOptions::new()
+        .connect_timeout(std::time::Duration::from_secs(2))
+        .connect(&db_settings.connection_string(false))
+        .await?;
+    run_migrations(&pool).await?;
+    let tx_hash = "0x00000000000000000000000000000000000000000000000000000000000000b1";
+    for table in ["basis", "opportunities"] {
+        sqlx::query(&format!("DELETE FROM {} WHERE tx_hash = $1", table))
+            .bind(tx_hash)
+            .execute(&pool)
+            .await?;
+    }
+
+    let gas_price = U256::from(20_000_000_000u64);
+    // The same swap processed twice, e.g. after a restart, and another swap of the same tx
+    for log_index in [0, 0, 1] {
+        let (point, opportunity) = observe_basis(
+            &settings(),
+            tx_hash.into(),
+            log_index,
+            1,
+            0,
+            3010.0,
+            3000.0,
+            gas_price,
+        )?;
+        insert_basis(&point, &pool).await?;
+        insert_opportunity(&opportunity.unwrap(), &pool).await?;
+    }
+
+    for table in ["basis", "opportunities"] {
+        let (count,): (i64,) = sqlx::query_as(&format!(
+            "SELECT COUNT(*) FROM {} WHERE tx_hash = $1",
+            table
+        ))
+        .bind(tx_hash)
+        .fetch_one(&pool)
+        .await?;
+        assert_eq!(count, 2);
+    }
+    Ok(())
+}
@@ -0,0 +1,43 @@
+mod common;
+
+use common::{Fixtures, MockServer};
+use ethers::types::H256;
+use sqlx::postgres::PgPoolOptions;
+use std::str::FromStr;
+use std::sync::mpsc;
+use uniswap_watcher::{AppConfig, Application};
+
+const TX_HASH: &str = "0xbb1e0f1c1accfedcc1dddfcc48d7d0bf893263a77e74c3d26c63adf729e89e69";
+const BLOCK_HASH: &str = "0xd3d17a9c8eb69157f59feb3c42cc46126d390efae0c8525f7377115d5d476235";
+
+#[tokio::test]
+async fn blob_tx_fee() {
+    let fixtures = Fixtures::default()
+        .with_file("eth_getTransactionReceipt", TX_HASH, "blob_tx_receipt.json")
+        .with_file("eth_getBlockByHash", BLOCK_HASH, "blob_tx_block.json");
+    let server = MockServer::start(fixtures);
+
+    let mut config = AppConfig::new().unwrap();
+    config.rpc_urls_http = vec![server.url.clone()];
+    config.binance_host = server.url.clone();
+    let (sender, _) = mpsc::channel();
+    let db_connection = PgPoolOptions::new()
+        .connect_lazy(&config.database.connection_string(false))
+        .unwrap();
+    let app = Application::new(config, sender, db_connection).unwrap();
+
+    let fee = app
+        .get_tx_fee(&H256::from_str(TX_HASH).unwrap())
+        .await
+        .unwrap()
+        .details()
+        .unwrap();
+    // 21000 gas at 10 gwei, of which 8 gwei of base fee, plus 2 blobs at 1 gwei per blob gas
+    assert_eq!(fee.blob_gas_used.unwrap(), "262144");
+    assert_eq!(fee.blob_gas_price.unwrap(), "1000000000");
+    assert_eq!(fee.blob.unwrap().eth, "0.000262144");
+    assert_eq!(fee.burned.unwrap().eth, "0.000168");
+    assert_eq!(fee.priority.unwrap().eth, "0.000042");
+    assert_eq!(fee.fee.eth, "0.000472144");
+    assert_eq!(fee.fee.usdt, "0.944288");
+}
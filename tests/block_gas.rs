@@ -0,0 +1,110 @@
+mod common;
+
+use common::{Fixtures, MockServer};
+use ethers::types::{Block, Transaction, TransactionReceipt, H256, U256};
+use serde_json::{json, Value};
+use sqlx::postgres::PgPoolOptions;
+use std::sync::mpsc;
+use uniswap_watcher::db::{get_block_gas_from_db, insert_block_gas, run_migrations, BlockGas};
+use uniswap_watcher::gas::percentile;
+use uniswap_watcher::{AppConfig, Application};
+
+const BLOCK_NUMBER: u64 = 19531264;
+
+#[test]
+fn nearest_rank_percentile() {
+    let values: Vec<U256> = (1..=10u64).map(U256::from).collect();
+    assert_eq!(percentile(&values, 50.0), Some(U256::from(5)));
+    assert_eq!(percentile(&values, 90.0), Some(U256::from(9)));
+    assert_eq!(percentile(&values, 0.0), Some(U256::from(1)));
+    assert_eq!(percentile(&[], 50.0), None);
+}
+
+#[tokio::test]
+async fn block_gas_stats() {
+    let fixtures = Fixtures::default()
+        .with_file("eth_getBlockByNumber", "0x12a0600", "gas_block.json")
+        .with_file(
+            "eth_getBlockReceipts",
+            "0x12a0600",
+            "gas_block_receipts.json",
+        );
+    let server = MockServer::start(fixtures);
+
+    let mut config = AppConfig::new().unwrap();
+    config.rpc_urls_http = vec![server.url.clone()];
+    config.binance_host = server.url.clone();
+    config.basis.swap_gas = 150000;
+    let (sender, _) = mpsc::channel();
+    let db_connection = PgPoolOptions::new()
+        .connect_lazy(&config.database.connection_string(false))
+        .unwrap();
+    let app = Application::new(config, sender, db_connection.clone()).unwrap();
+
+    let stats = app.get_block_gas(BLOCK_NUMBER).await.unwrap();
+    let details = stats.details().unwrap();
+    // Gas prices of 10, 12, 15, 20 and 50 gwei over a base fee of 8 gwei
+    assert_eq!(details.tx_count, 5);
+    assert_eq!(details.base_fee_per_gas.as_deref(), Some("8000000000"));
+    let gas_price = details.gas_price.unwrap();
+    assert_eq!(gas_price.min, "10000000000");
+    assert_eq!(gas_price.median, "15000000000");
+    assert_eq!(gas_price.p90, "50000000000");
+    let priority_fee = details.priority_fee.unwrap();
+    assert_eq!(priority_fee.min, "2000000000");
+    assert_eq!(priority_fee.max, "42000000000");
+    // 150000 gas at 15 gwei and 2000 USDT per ETH
+    let swap_cost = details.swap_cost.unwrap();
+    assert_eq!(swap_cost.median.eth, "0.00225");
+    assert_eq!(swap_cost.median.usdt, "4.5");
+
+    // Cached statistics are returned as computed
+    run_migrations(&db_connection)
+        .await
+        .expect("Failed to run db migrations");
+    _ = sqlx::query("DELETE FROM block_gas WHERE block_number = $1")
+        .bind(BLOCK_NUMBER as i64)
+        .execute(&db_connection)
+        .await;
+    insert_block_gas(&stats, &db_connection).await.unwrap();
+    let cached = get_block_gas_from_db(BLOCK_NUMBER as i64, &db_connection)
+        .await
+        .unwrap();
+    assert_eq!(cached, stats);
+}
+
+fn fixture(file: &str) -> Value {
+    let path = format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), file);
+    serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+}
+
+#[test]
+fn gas_price_derived_from_transaction() {
+    let block: Block<H256> = serde_json::from_value(fixture("gas_block.json")).unwrap();
+    let mut receipts = fixture("gas_block_receipts.json");
+    // The receipt of the 50 gwei transaction comes from a node without effective gas price
+    let receipt = receipts[4].as_object_mut().unwrap();
+    receipt.remove("effectiveGasPrice");
+    let tx_hash = receipt["transactionHash"].clone();
+    let receipts: Vec<TransactionReceipt> = serde_json::from_value(receipts).unwrap();
+    let mut transaction = fixture("legacy_tx.json");
+    let fields = transaction.as_object_mut().unwrap();
+    fields.remove("gasPrice");
+    fields.insert("hash".into(), tx_hash);
+    fields.insert("type".into(), json!("0x2"));
+    fields.insert("chainId".into(), json!("0x1"));
+    fields.insert("accessList".into(), json!([]));
+    // Base fee of 8 gwei plus 42 gwei of priority fee, below the max fee
+    fields.insert("maxFeePerGas".into(), json!("0xdf8475800"));
+    fields.insert("maxPriorityFeePerGas".into(), json!("0x9c7652400"));
+    let transaction: Transaction = serde_json::from_value(transaction).unwrap();
+
+    let stats = BlockGas::new(&block, &receipts, &[transaction], "2000".into(), 150000).unwrap();
+    assert_eq!(stats.tx_count, 5);
+    assert_eq!(stats.max_gas_price.as_deref(), Some("50000000000"));
+    assert_eq!(stats.max_priority_fee.as_deref(), Some("42000000000"));
+    assert_eq!(stats.median_gas_price.as_deref(), Some("15000000000"));
+
+    // The price cannot be left out without skewing the statistics
+    assert!(BlockGas::new(&block, &receipts, &[], "2000".into(), 150000).is_err());
+}
@@ -0,0 +1,117 @@
+mod common;
+
+use common::{Fixtures, MockServer};
+use ethers::types::{Block, H256, U256};
+use sqlx::postgres::PgPoolOptions;
+use std::str::FromStr;
+use std::sync::mpsc;
+use uniswap_watcher::blocks::blob_base_fee;
+use uniswap_watcher::db::{
+    get_block_header_from_db, get_block_headers_from_db, insert_block_header, run_migrations,
+    BlockHeader,
+};
+use uniswap_watcher::fee::Chain;
+use uniswap_watcher::{AppConfig, Application};
+
+const TX_HASH: &str = "0xbb1e0f1c1accfedcc1dddfcc48d7d0bf893263a77e74c3d26c63adf729e89e69";
+const BLOCK_HASH: &str = "0xd3d17a9c8eb69157f59feb3c42cc46126d390efae0c8525f7377115d5d476235";
+
+fn blob_block() -> Block<H256> {
+    let path = format!(
+        "{}/tests/fixtures/blob_tx_block.json",
+        env!("CARGO_MANIFEST_DIR")
+    );
+    serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+}
+
+/// Timestamps of blocks after Cancun, Prague and the second blob parameter only fork
+const CANCUN_TIMESTAMP: u64 = 1711000000;
+const PRAGUE_TIMESTAMP: u64 = 1750000000;
+const BPO2_TIMESTAMP: u64 = 1770000000;
+
+#[test]
+fn blob_base_fee_from_excess_blob_gas() {
+    assert_eq!(
+        blob_base_fee(Chain::Ethereum, U256::zero(), CANCUN_TIMESTAMP),
+        Some(U256::one())
+    );
+    // 60 blobs of excess
+    assert_eq!(
+        blob_base_fee(Chain::Ethereum, U256::from(393216 * 60), CANCUN_TIMESTAMP),
+        Some(U256::from(1172))
+    );
+    assert_eq!(
+        blob_base_fee(Chain::Ethereum, U256::from(100_000_000), CANCUN_TIMESTAMP),
+        Some(U256::from(10203769476395u64))
+    );
+
+    // The update fraction is raised with the blob target
+    assert_eq!(
+        blob_base_fee(Chain::Ethereum, U256::from(393216 * 60), PRAGUE_TIMESTAMP),
+        Some(U256::from(111))
+    );
+    assert_eq!(
+        blob_base_fee(Chain::Ethereum, U256::from(100_000_000), PRAGUE_TIMESTAMP),
+        Some(U256::from(470442149))
+    );
+    assert_eq!(
+        blob_base_fee(Chain::Ethereum, U256::from(100_000_000), BPO2_TIMESTAMP),
+        Some(U256::from(5209))
+    );
+
+    // L2s have no blob base fee schedule
+    assert_eq!(
+        blob_base_fee(Chain::Optimism, U256::from(100_000_000), BPO2_TIMESTAMP),
+        None
+    );
+}
+
+#[tokio::test]
+async fn store_and_use_block_headers() {
+    let header = BlockHeader::new(&blob_block(), Chain::Ethereum).unwrap();
+    assert_eq!(header.block_number, 19531250);
+    assert_eq!(header.base_fee_per_gas.as_deref(), Some("8000000000"));
+    assert_eq!(header.blob_base_fee.as_deref(), Some("1"));
+    // The mainnet forks do not apply to L2s
+    let l2_header = BlockHeader::new(&blob_block(), Chain::Base).unwrap();
+    assert_eq!(l2_header.blob_base_fee, None);
+
+    let mut config = AppConfig::new().unwrap();
+    let db_connection = PgPoolOptions::new()
+        .connect_timeout(std::time::Duration::from_secs(2))
+        .connect(&config.database.connection_string(false))
+        .await
+        .expect("Failed to create db connection");
+    run_migrations(&db_connection)
+        .await
+        .expect("Failed to run db migrations");
+    insert_block_header(&header, &db_connection).await.unwrap();
+    let stored = get_block_header_from_db(BLOCK_HASH, &db_connection)
+        .await
+        .unwrap();
+    assert_eq!(stored, header);
+    let headers = get_block_headers_from_db(
+        Some(header.timestamp_ms),
+        Some(header.timestamp_ms),
+        10,
+        &db_connection,
+    )
+    .await
+    .unwrap();
+    assert!(headers.contains(&header));
+
+    // The fee is computed from the stored header, without getting the block on-chain
+    let fixtures =
+        Fixtures::default().with_file("eth_getTransactionReceipt", TX_HASH, "blob_tx_receipt.json");
+    let server = MockServer::start(fixtures);
+    config.rpc_urls_http = vec![server.url.clone()];
+    config.binance_host = server.url.clone();
+    let (sender, _) = mpsc::channel();
+    let app = Application::new(config, sender, db_connection).unwrap();
+    let fee = app
+        .get_tx_fee(&H256::from_str(TX_HASH).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(fee.base_fee_per_gas.as_deref(), Some("8000000000"));
+    assert_eq!(fee.fee_eth().unwrap(), "0.000472144");
+}
@@ -0,0 +1,137 @@
+mod common;
+
+use common::{decode_swap_log, lazy_db_connection, swap_log, Fixtures, MockServer};
+use serde_json::json;
+use sqlx::postgres::PgPoolOptions;
+use std::sync::mpsc;
+use uniswap_watcher::db::{
+    get_checkpoint_from_db, run_migrations, upsert_checkpoint, Checkpoint, DbRecord,
+};
+use uniswap_watcher::ingest::SwapProcessor;
+use uniswap_watcher::AppConfig;
+
+const LEGACY_TX_HASH: &str = "0x5c504ed432cb51138bcf09aa5e8a410dd4a1e204ef84bfed1be16dfba1b22060";
+const LEGACY_BLOCK_HASH: &str =
+    "0x4e3a3754410177e6937ef1f84bba68ea139e8d1a2258c5f85db9f1cd715a1bdd";
+const BLOB_TX_HASH: &str = "0xbb1e0f1c1accfedcc1dddfcc48d7d0bf893263a77e74c3d26c63adf729e89e69";
+const BLOB_BLOCK_HASH: &str = "0xd3d17a9c8eb69157f59feb3c42cc46126d390efae0c8525f7377115d5d476235";
+const UNDECODABLE_TX_HASH: &str =
+    "0x00000000000000000000000000000000000000000000000000000000000dead5";
+
+#[tokio::test]
+async fn catch_up_from_checkpoint() {
+    let mut config = AppConfig::new().unwrap();
+    let mut undecodable_log = swap_log(&config, UNDECODABLE_TX_HASH, BLOB_BLOCK_HASH, 106);
+    undecodable_log["data"] = json!("0x");
+    let fixtures = Fixtures::default()
+        .with_file(
+            "eth_getTransactionReceipt",
+            LEGACY_TX_HASH,
+            "legacy_tx_receipt.json",
+        )
+        .with_file("eth_getTransactionByHash", LEGACY_TX_HASH, "legacy_tx.json")
+        .with_file(
+            "eth_getBlockByHash",
+            LEGACY_BLOCK_HASH,
+            "legacy_tx_block.json",
+        )
+        .with_file(
+            "eth_getTransactionReceipt",
+            BLOB_TX_HASH,
+            "blob_tx_receipt.json",
+        )
+        .with_file("eth_getBlockByHash", BLOB_BLOCK_HASH, "blob_tx_block.json")
+        .with_logs(
+            105,
+            107,
+            json!([
+                swap_log(&config, BLOB_TX_HASH, BLOB_BLOCK_HASH, 105),
+                undecodable_log,
+                swap_log(&config, LEGACY_TX_HASH, LEGACY_BLOCK_HASH, 107)
+            ]),
+        );
+    let server = MockServer::start(fixtures);
+    config.rpc_urls_http = vec![server.url.clone()];
+    config.binance_host = server.url.clone();
+
+    // Without checkpoint, as on the first start, there is nothing to catch up
+    let (sender, receiver) = mpsc::channel();
+    let mut processor =
+        SwapProcessor::new(config.clone(), sender, lazy_db_connection(&config), None);
+    processor.catch_up(107).await.unwrap();
+    assert!(receiver.try_recv().is_err());
+
+    // Swaps emitted while the process was down are processed from the block after the checkpoint
+    let (sender, receiver) = mpsc::channel();
+    let mut processor = SwapProcessor::new(
+        config.clone(),
+        sender,
+        lazy_db_connection(&config),
+        Some(104),
+    );
+    processor.catch_up(107).await.unwrap();
+    let records: Vec<DbRecord> = receiver.try_iter().collect();
+    let swaps: Vec<_> = records
+        .iter()
+        .filter_map(|record| match record {
+            DbRecord::Swap(swap) => Some((swap.block_number, swap.timestamp_ms)),
+            _ => None,
+        })
+        .collect();
+    // Caught up swaps have the time of their block
+    assert_eq!(swaps, vec![(105, 1709294499000), (107, 1623456160000)]);
+    // The undecodable log is recorded and skipped
+    let errors: Vec<_> = records
+        .iter()
+        .filter_map(|record| match record {
+            DbRecord::IngestionError(error) => Some((error.tx_hash.as_str(), error.block_number)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(errors, vec![(UNDECODABLE_TX_HASH, 106)]);
+    let checkpoints: Vec<i64> = records
+        .iter()
+        .filter_map(|record| match record {
+            DbRecord::Checkpoint(checkpoint) => Some(checkpoint.block_number),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(checkpoints, vec![105, 107]);
+    // The checkpoint of a block is queued after its records
+    assert!(matches!(records.last(), Some(DbRecord::Checkpoint(_))));
+    assert_eq!(processor.checkpoint, Some(107));
+
+    // Live swaps of the caught up blocks are skipped
+    let (log, meta) = decode_swap_log(swap_log(&config, LEGACY_TX_HASH, LEGACY_BLOCK_HASH, 107));
+    assert!(!processor.handle(log, meta, true).await.unwrap());
+    assert!(receiver.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn store_checkpoint() {
+    let config = AppConfig::new().unwrap();
+    let db_connection = PgPoolOptions::new()
+        .connect_timeout(std::time::Duration::from_secs(2))
+        .connect(&config.database.connection_string(false))
+        .await
+        .expect("Failed to create db connection");
+    run_migrations(&db_connection)
+        .await
+        .expect("Failed to run db migrations");
+    let pool_address = "0x00000000000000000000000000000000000000c7";
+    for block_number in [100, 101] {
+        upsert_checkpoint(
+            &Checkpoint {
+                pool_address: pool_address.into(),
+                block_number,
+            },
+            &db_connection,
+        )
+        .await
+        .unwrap();
+    }
+    let checkpoint = get_checkpoint_from_db(pool_address, &db_connection)
+        .await
+        .unwrap();
+    assert_eq!(checkpoint, Some(101));
+}
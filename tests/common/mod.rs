@@ -0,0 +1,227 @@
+#![allow(dead_code)]
+
+use actix_web::{get, post, web, App, HttpResponse, HttpServer, Responder};
+use ethers::contract::{parse_log, LogMeta};
+use ethers::types::{Log, I256, U256};
+use serde_json::{json, Value};
+use sqlx::postgres::PgPoolOptions;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use uniswap_watcher::{AppConfig, SwapFilter};
+
+/// Local server answering JSON-RPC requests and Binance klines with recorded fixtures,
+/// as no live node is available in tests
+pub struct MockServer {
+    pub url: String,
+    /// Number of JSON-RPC requests received by method, counting every request of a batch
+    requests: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+/// Fixtures answered by the mock server, keyed by JSON-RPC method and first param
+#[derive(Clone, Default)]
+pub struct Fixtures {
+    responses: HashMap<String, Value>,
+    errors: HashMap<String, String>,
+}
+
+impl Fixtures {
+    /// Answer `method` called with `param` as first param with the given result
+    pub fn with(mut self, method: &str, param: &str, result: Value) -> Self {
+        self.responses
+            .insert(format!("{}:{}", method, param.to_lowercase()), result);
+        self
+    }
+
+    /// Answer `method` called with `param` as first param with the content of `tests/fixtures/{file}`
+    pub fn with_file(self, method: &str, param: &str, file: &str) -> Self {
+        let path = format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), file);
+        let content = std::fs::read_to_string(&path).expect("Failed to read fixture");
+        let result = serde_json::from_str(&content).expect("Failed to parse fixture");
+        self.with(method, param, result)
+    }
+
+    /// Answer `method` called with `param` as first param with a JSON-RPC error with the given message
+    pub fn with_error(mut self, method: &str, param: &str, message: &str) -> Self {
+        self.errors.insert(
+            format!("{}:{}", method, param.to_lowercase()),
+            message.to_string(),
+        );
+        self
+    }
+
+    /// Answer `eth_getLogs` from `from_block` to `to_block` with the given logs, whatever the addresses and topics
+    pub fn with_logs(self, from_block: u64, to_block: u64, logs: Value) -> Self {
+        self.with("eth_getLogs", &logs_range(from_block, to_block), logs)
+    }
+
+    /// Answer `eth_getLogs` from `from_block` to `to_block` with a JSON-RPC error with the given message
+    pub fn with_logs_error(self, from_block: u64, to_block: u64, message: &str) -> Self {
+        self.with_error("eth_getLogs", &logs_range(from_block, to_block), message)
+    }
+
+    /// Answer `eth_call` to the contract `to` of the function with the given 4 bytes `selector`
+    /// with the ABI-encoded `result`, whatever the arguments and block
+    pub fn with_call(self, to: &str, selector: &str, result: &str) -> Self {
+        self.with("eth_call", &format!("{}:{}", to, selector), json!(result))
+    }
+
+    fn answer(&self, request: &Value) -> Value {
+        let method = request["method"].as_str().unwrap_or_default();
+        let param = match &request["params"][0] {
+            Value::String(param) => param.to_lowercase(),
+            Value::Object(call) if method == "eth_call" => {
+                let field = |key: &str| call.get(key).and_then(Value::as_str);
+                let to = field("to").unwrap_or_default();
+                let data = field("data").or(field("input"));
+                let selector = data.unwrap_or_default().get(..10).unwrap_or_default();
+                format!("{}:{}", to, selector).to_lowercase()
+            }
+            Value::Object(filter) if method == "eth_getLogs" => {
+                let field = |key: &str| filter.get(key).and_then(Value::as_str);
+                format!(
+                    "{}:{}",
+                    field("fromBlock").unwrap_or_default(),
+                    field("toBlock").unwrap_or_default()
+                )
+            }
+            other => other.to_string(),
+        };
+        let key = format!("{}:{}", method, param);
+        if let Some(message) = self.errors.get(&key) {
+            return json!({
+                "jsonrpc": "2.0",
+                "id": request["id"],
+                "error": {"code": -32005, "message": message}
+            });
+        }
+        match self.responses.get(&key) {
+            Some(result) => json!({"jsonrpc": "2.0", "id": request["id"], "result": result}),
+            None => json!({
+                "jsonrpc": "2.0",
+                "id": request["id"],
+                "error": {"code": -32601, "message": format!("no fixture for {} {}", method, param)}
+            }),
+        }
+    }
+}
+
+fn logs_range(from_block: u64, to_block: u64) -> String {
+    format!("{:#x}:{:#x}", from_block, to_block)
+}
+
+#[post("/")]
+async fn rpc(
+    fixtures: web::Data<Fixtures>,
+    requests: web::Data<Arc<Mutex<HashMap<String, usize>>>>,
+    body: web::Json<Value>,
+) -> impl Responder {
+    let answer = |request: &Value| {
+        let method = request["method"].as_str().unwrap_or_default().to_string();
+        *requests.lock().unwrap().entry(method).or_default() += 1;
+        fixtures.answer(request)
+    };
+    match body.into_inner() {
+        Value::Array(batch) => {
+            HttpResponse::Ok().json(batch.iter().map(answer).collect::<Vec<_>>())
+        }
+        request => HttpResponse::Ok().json(answer(&request)),
+    }
+}
+
+#[get("/api/v3/klines")]
+async fn klines() -> impl Responder {
+    HttpResponse::Ok().json(json!([[
+        1709314800000u64,
+        "2000.00000000",
+        "2001.00000000",
+        "1999.00000000",
+        "2000.50000000",
+        "100.00000000",
+        1709314859999u64,
+        "200000.00000000",
+        100,
+        "50.00000000",
+        "100000.00000000",
+        "0"
+    ]]))
+}
+
+#[get("/api/v3/ticker/price")]
+async fn ticker() -> impl Responder {
+    HttpResponse::Ok().json(json!({"symbol": "ETHUSDT", "price": "2000.00000000"}))
+}
+
+impl MockServer {
+    /// Start the mock server on a random local port.
+    /// Binance prices are always 2000 USDT per ETH.
+    pub fn start(fixtures: Fixtures) -> Self {
+        let requests = Arc::new(Mutex::new(HashMap::new()));
+        let data = requests.clone();
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(web::Data::new(fixtures.clone()))
+                .app_data(web::Data::new(data.clone()))
+                .app_data(web::JsonConfig::default().limit(1 << 24))
+                .service(rpc)
+                .service(klines)
+                .service(ticker)
+        })
+        .bind("127.0.0.1:0")
+        .expect("Failed to bind mock server");
+        let url = format!("http://127.0.0.1:{}", server.addrs()[0].port());
+        tokio::spawn(server.run());
+        Self { url, requests }
+    }
+
+    /// Number of JSON-RPC requests of the given method received so far
+    pub fn requests(&self, method: &str) -> usize {
+        self.requests
+            .lock()
+            .unwrap()
+            .get(method)
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+/// Swap log on the watched pool of 2000 USDC for 1 WETH
+pub fn swap_log(config: &AppConfig, tx_hash: &str, block_hash: &str, block_number: u64) -> Value {
+    let word = |value: I256| format!("{:064x}", value.into_raw());
+    let data = format!(
+        "0x{}{}{}{}{}",
+        word(I256::from(-2_000_000_000i64)),
+        word(I256::from_raw(U256::exp10(18))),
+        word(I256::from_raw(
+            U256::from_dec_str("1771595571142957102961017161607260").unwrap()
+        )),
+        word(I256::from_raw(U256::exp10(19))),
+        word(I256::from(200000))
+    );
+    let address_topic = format!("0x{:0>64}", "e592427a0aece92de3edfe1f382a2eca0fa9bfd0");
+    json!({
+        "address": config.pool_address,
+        "topics": [config.swap_topic, address_topic, address_topic],
+        "data": data,
+        "blockHash": block_hash,
+        "blockNumber": format!("{:#x}", block_number),
+        "transactionHash": tx_hash,
+        "transactionIndex": "0x0",
+        "logIndex": "0x0",
+        "removed": false
+    })
+}
+
+/// Db connection that is only opened when first used
+pub fn lazy_db_connection(config: &AppConfig) -> PgPool {
+    PgPoolOptions::new()
+        .connect_lazy(&config.database.connection_string(false))
+        .unwrap()
+}
+
+/// Swap event and its metadata, as received from the subscription
+pub fn decode_swap_log(log: Value) -> (SwapFilter, LogMeta) {
+    let log: Log = serde_json::from_value(log).unwrap();
+    let meta = LogMeta::from(&log);
+    (parse_log::<SwapFilter>(log).unwrap(), meta)
+}
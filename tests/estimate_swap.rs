@@ -0,0 +1,213 @@
+mod common;
+
+use common::{Fixtures, MockServer};
+use ethers::types::{Address, U256};
+use sqlx::postgres::PgPoolOptions;
+use std::str::FromStr;
+use std::sync::mpsc;
+use uniswap_watcher::db::{insert_swap, insert_tx_fee, run_migrations, Swap, TxFee};
+use uniswap_watcher::estimate::{PoolState, QuoteOverflow};
+use uniswap_watcher::{AppConfig, Application};
+
+const POOL: &str = "0x000000000000000000000000000000000000e571";
+const USDC: &str = "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48";
+const WETH: &str = "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2";
+/// sqrtPriceX96 of 2000 USDC per WETH
+const SQRT_PRICE_X96: &str = "1771595571142957102961017161607260";
+
+fn pool_state() -> PoolState {
+    PoolState {
+        sqrt_price_x96: U256::from_dec_str(SQRT_PRICE_X96).unwrap(),
+        liquidity: 10u128.pow(19),
+        fee: 500,
+    }
+}
+
+/// ABI-encoded 32 bytes word
+fn word(value: U256) -> String {
+    format!("{:064x}", value)
+}
+
+fn address_word(address: &str) -> String {
+    format!("{:0>64}", &address[2..])
+}
+
+#[test]
+fn quote_within_tick_range() {
+    let state = pool_state();
+    // 2000 USDC in, less the 0.05% fee
+    let quote = state.quote(U256::from(2_000_000_000u64), true).unwrap();
+    assert_eq!(quote.amount_out, U256::from(999495532355032847u64));
+    assert_eq!(quote.fee_amount, U256::from(1_000_000));
+    assert!(quote.sqrt_price_x96_after < state.sqrt_price_x96);
+
+    // 1 WETH in
+    let quote = state.quote(U256::exp10(18), false).unwrap();
+    assert_eq!(quote.amount_out, U256::from(1998991064u64));
+    assert_eq!(quote.fee_amount, U256::from(500_000_000_000_000u64));
+    assert!((quote.price_impact_bps(state.sqrt_price_x96) - 0.0894).abs() < 1e-4);
+
+    let empty = PoolState {
+        liquidity: 0,
+        ..pool_state()
+    };
+    assert!(empty.quote(U256::one(), true).is_err());
+}
+
+#[test]
+fn quote_overflow() {
+    // All the liquidity in range is drained by token0, while token1 pushes the price out of range
+    let state = pool_state();
+    let quote = state.quote(U256::MAX, true).unwrap();
+    assert!(quote.amount_out < U256::exp10(24));
+    let err = state.quote(U256::MAX, false).unwrap_err();
+    assert!(err.downcast_ref::<QuoteOverflow>().is_some());
+
+    let state = PoolState {
+        sqrt_price_x96: U256::MAX,
+        liquidity: u128::MAX,
+        ..pool_state()
+    };
+    for zero_for_one in [true, false] {
+        let err = state.quote(U256::MAX, zero_for_one).unwrap_err();
+        assert!(err.downcast_ref::<QuoteOverflow>().is_some());
+    }
+}
+
+/// Swap on `POOL` whose transaction used the given gas
+async fn store_swap(tx_hash: &str, block_number: i64, gas_used: u64, db_connection: &sqlx::PgPool) {
+    _ = sqlx::query("DELETE FROM fees WHERE tx_hash = $1")
+        .bind(tx_hash)
+        .execute(db_connection)
+        .await;
+    _ = sqlx::query("DELETE FROM swaps WHERE tx_hash = $1")
+        .bind(tx_hash)
+        .execute(db_connection)
+        .await;
+    insert_tx_fee(
+        &TxFee {
+            tx_hash: tx_hash.into(),
+            gas_used: Some(gas_used.to_string()),
+            fee_wei: "0".into(),
+            eth_usdt_price: "2000".into(),
+            ..Default::default()
+        },
+        db_connection,
+    )
+    .await
+    .unwrap();
+    insert_swap(
+        &Swap {
+            tx_hash: tx_hash.into(),
+            log_index: 0,
+            pool_address: POOL.into(),
+            block_number,
+            block_hash: None,
+            timestamp_ms: 0,
+            sender: POOL.into(),
+            recipient: POOL.into(),
+            tx_from: POOL.into(),
+            amount0: "0".into(),
+            amount1: "0".into(),
+            price: 0.0,
+            notional_usd: 0.0,
+            fee_wei: None,
+            gas_usd: None,
+            gas_share: None,
+        },
+        db_connection,
+    )
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn estimate_swap_cost() {
+    let mut config = AppConfig::new().unwrap();
+    let db_connection = PgPoolOptions::new()
+        .connect_timeout(std::time::Duration::from_secs(2))
+        .connect(&config.database.connection_string(false))
+        .await
+        .expect("Failed to create db connection");
+    run_migrations(&db_connection)
+        .await
+        .expect("Failed to run db migrations");
+    // The oldest swap is out of the sample
+    config.estimate.gas_sample_swaps = 3;
+    store_swap(
+        "0xe571000000000000000000000000000000000000000000000000000000000001",
+        100,
+        500000,
+        &db_connection,
+    )
+    .await;
+    store_swap(
+        "0xe571000000000000000000000000000000000000000000000000000000000002",
+        101,
+        120000,
+        &db_connection,
+    )
+    .await;
+    store_swap(
+        "0xe571000000000000000000000000000000000000000000000000000000000003",
+        102,
+        130000,
+        &db_connection,
+    )
+    .await;
+    store_swap(
+        "0xe571000000000000000000000000000000000000000000000000000000000004",
+        103,
+        110000,
+        &db_connection,
+    )
+    .await;
+
+    // Latest block with a base fee of 8 gwei, and a priority fee of 2 gwei from the node
+    let slot0 = format!(
+        "0x{}{}",
+        word(U256::from_dec_str(SQRT_PRICE_X96).unwrap()),
+        "0".repeat(64 * 6)
+    );
+    let fixtures = Fixtures::default()
+        .with_file("eth_getBlockByNumber", "latest", "gas_block.json")
+        .with("eth_maxPriorityFeePerGas", "null", "0x77359400".into())
+        .with_call(POOL, "0x3850c7bd", &slot0)
+        .with_call(POOL, "0x1a686502", &format!("0x{}", word(U256::exp10(19))))
+        .with_call(POOL, "0xddca3f43", &format!("0x{}", word(U256::from(500))))
+        .with_call(POOL, "0x0dfe1681", &format!("0x{}", address_word(USDC)))
+        .with_call(POOL, "0xd21220a7", &format!("0x{}", address_word(WETH)));
+    let server = MockServer::start(fixtures);
+    config.rpc_urls_http = vec![server.url.clone()];
+    config.binance_host = server.url.clone();
+    let (sender, _) = mpsc::channel();
+    let app = Application::new(config, sender, db_connection).unwrap();
+
+    let pool = Address::from_str(POOL).unwrap();
+    let estimate = app
+        .estimate_swap(
+            pool,
+            U256::from(2_000_000_000u64),
+            Address::from_str(USDC).unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(estimate.block_number, 19531264);
+    assert_eq!(estimate.token_out, WETH);
+    assert_eq!(estimate.amount_out, "999495532355032847");
+    assert_eq!(estimate.pool_fee, "1000000");
+    // Median of the 3 most recent swaps
+    assert_eq!(estimate.gas_estimate, 120000);
+    assert_eq!(estimate.gas_sample_size, 3);
+    assert_eq!(estimate.base_fee_per_gas, "8000000000");
+    assert_eq!(estimate.priority_fee_per_gas, "2000000000");
+    // 120000 gas at 10 gwei and 2000 USDT per ETH
+    assert_eq!(estimate.gas_cost.eth, "0.0012");
+    assert_eq!(estimate.gas_cost.usdt, "2.4");
+
+    // A token not traded on the pool
+    assert!(app
+        .estimate_swap(pool, U256::one(), Address::zero())
+        .await
+        .is_err());
+}
@@ -5,6 +5,7 @@ use ethers::providers::{Http, Provider};
 use ethers::types::Address;
 use std::str::FromStr;
 use std::sync::Arc;
+use uniswap_watcher::rpc::{connect_http, RpcSettings};
 use uniswap_watcher::util::tx_hash_to_price;
 use uniswap_watcher::IERC20;
 
@@ -46,7 +47,7 @@ async fn erc20() -> Result<()> {
 
 #[tokio::test]
 async fn decode_price() -> Result<()> {
-    let eth_client = Provider::<Http>::try_from("https://eth.drpc.org").unwrap();
+    let eth_client = connect_http(&[RPC_URL_HTTP.to_string()], &RpcSettings::default()).unwrap();
     let swap_topic = H256::from_str(SWAP_TOPIC).unwrap();
     let pool_address = POOL_ADDRESS.parse::<Address>().unwrap();
     let tx_hash =
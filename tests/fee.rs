@@ -1,14 +1,13 @@
-use ethers::{
-    providers::{Http, Middleware, Provider},
-    types::H256,
-};
+use ethers::prelude::{Block, TransactionReceipt, U256};
+use ethers::types::H256;
 use sqlx::postgres::PgPoolOptions;
-use sqlx::{Connection, Executor, PgConnection, PgPool};
+use sqlx::PgPool;
 use std::collections::HashMap;
 use std::str::FromStr;
 use std::sync::mpsc;
-use uniswap_watcher::db::{get_tx_fee_from_db, DatabaseSettings, TxFee};
-use uniswap_watcher::util::compute_gas_fee_eth;
+use uniswap_watcher::db::{get_tx_fee_from_db, run_migrations, DatabaseSettings, TxFee};
+use uniswap_watcher::fee::Chain;
+use uniswap_watcher::util::mul_decimal;
 use uniswap_watcher::{AppConfig, Application};
 
 pub async fn get_db_connection() -> PgPool {
@@ -19,54 +18,28 @@ pub async fn get_db_connection() -> PgPool {
         host: "127.0.0.1".into(),
         database_name: "postgres_db".into(),
     };
-    let mut connection = PgConnection::connect(&settings.connection_string(false))
+    let pool = PgPoolOptions::new()
+        .connect_timeout(std::time::Duration::from_secs(2))
+        .connect(&settings.connection_string(false))
         .await
-        .expect("Failed to connect to Postgres");
-    _ = connection
-        .execute(
-            r#"
-        CREATE TABLE fees
-        (
-            tx_hash   TEXT             NOT NULL,
-            PRIMARY KEY (tx_hash),
-            fee_eth   DOUBLE PRECISION NOT NULL,
-            fee_usdt  DOUBLE PRECISION NOT NULL
-        );
-        "#,
-        )
-        .await;
+        .expect("Failed to create db connection");
+    run_migrations(&pool)
+        .await
+        .expect("Failed to run db migrations");
     _ = sqlx::query(
         r#"
-        INSERT INTO fees (tx_hash, fee_eth, fee_usdt)
-        VALUES ($1, $2, $3)
+        INSERT INTO fees (tx_hash, fee_wei, eth_usdt_price)
+        VALUES ($1, $2::NUMERIC, $3::NUMERIC)
+        ON CONFLICT (tx_hash) DO UPDATE
+        SET fee_wei = excluded.fee_wei, eth_usdt_price = excluded.eth_usdt_price
         "#,
     )
     .bind("0xf3a23cc9af86832d33e87d717a6490fb75f594220abc88485084516256bae331")
-    .bind(0.11)
-    .bind(50.3)
-    .execute(&mut connection)
+    .bind("110000000000000000")
+    .bind("457.3")
+    .execute(&pool)
     .await;
-    PgPoolOptions::new()
-        .connect_timeout(std::time::Duration::from_secs(2))
-        .connect(&settings.connection_string(false))
-        .await
-        .expect("Failed to create db connection")
-}
-
-#[tokio::test]
-async fn gas_fee_eth() {
-    let client = Provider::<Http>::try_from("https://eth.drpc.org").unwrap();
-    let tx_hash =
-        H256::from_str("0xe55abfa818e6237b794a41a99482ef7108ed7d6c89867ed9b443011c93d2fb77")
-            .unwrap();
-    let tx = client
-        .get_transaction_receipt(tx_hash)
-        .await
-        .unwrap()
-        .unwrap();
-
-    let gas_fee = compute_gas_fee_eth(&tx).await.unwrap();
-    assert_eq!(gas_fee, 0.0181623707852374);
+    pool
 }
 
 #[tokio::test]
@@ -81,10 +54,13 @@ async fn get_tx_fee_single() {
     .unwrap();
     let expected = TxFee {
         tx_hash: "0xf3a23cc9af86832d33e87d717a6490fb75f594220abc88485084516256bae331".to_string(),
-        fee_eth: 0.11,
-        fee_usdt: 50.3,
+        fee_wei: "110000000000000000".to_string(),
+        eth_usdt_price: "457.3".to_string(),
+        ..Default::default()
     };
     assert_eq!(actual, expected);
+    assert_eq!(actual.fee_eth().unwrap(), "0.11");
+    assert_eq!(actual.fee_usdt().unwrap(), "50.303");
 }
 
 #[tokio::test]
@@ -98,17 +74,100 @@ async fn get_tx_fee_batch() {
     let db_connection = get_db_connection().await;
     let app = Application::new(config, sender, db_connection).unwrap();
     let actual = app
-        .get_tx_fee_batch(vec![
-            hash1.to_string(),
-            hash2.to_string(),
-            hash3.to_string(),
-        ])
+        .get_tx_fee_batch(
+            vec![hash1.to_string(), hash2.to_string(), hash3.to_string()],
+            None,
+        )
         .await
         .unwrap();
+    // Fees are now computed exactly and only then rounded to f64,
+    // so they can differ in the last digit from a product of f64 values
     let expected = HashMap::from([
         (H256::from_str(hash1).unwrap(), 61.875928038562485),
         (H256::from_str(hash2).unwrap(), 64.23295474697701),
         (H256::from_str(hash3).unwrap(), 409.30215911746706),
     ]);
-    assert_eq!(actual, expected);
+    assert_eq!(actual.len(), expected.len());
+    for (tx_hash, fee) in expected {
+        assert!((actual[&tx_hash] - fee).abs() / fee < 1e-12);
+    }
+}
+
+#[tokio::test]
+async fn get_tx_fee_exact() {
+    let hash = "0xe55abfa818e6237b794a41a99482ef7108ed7d6c89867ed9b443011c93d2fb77";
+
+    let config = AppConfig::new().unwrap();
+    let (sender, _) = mpsc::channel();
+    let db_connection = get_db_connection().await;
+    let app = Application::new(config, sender, db_connection).unwrap();
+    let actual = app
+        .get_tx_fee(&H256::from_str(hash).unwrap())
+        .await
+        .unwrap()
+        .details()
+        .unwrap();
+    assert_eq!(actual.gas_used.unwrap(), "338200");
+    assert_eq!(actual.effective_gas_price.unwrap(), "53703047857");
+    assert_eq!(actual.fee.wei, "18162370785237400");
+    assert_eq!(actual.fee.eth, "0.0181623707852374");
+    assert_eq!(actual.fee.usdt, "61.875928038562479068");
+    let burned = actual.burned.unwrap();
+    let priority = actual.priority.unwrap();
+    assert_eq!(
+        U256::from_dec_str(&burned.wei).unwrap() + U256::from_dec_str(&priority.wei).unwrap(),
+        U256::from(18162370785237400u64)
+    );
+}
+
+#[test]
+fn fee_burned_and_priority() {
+    let receipt = TransactionReceipt {
+        gas_used: Some(U256::from(100000)),
+        effective_gas_price: Some(U256::from(30_000_000_000u64)),
+        ..Default::default()
+    };
+    let block = Block::<H256> {
+        base_fee_per_gas: Some(U256::from(25_000_000_000u64)),
+        ..Default::default()
+    };
+    let fee = TxFee::new(&receipt, None, &block, "2000".into(), Chain::Ethereum)
+        .unwrap()
+        .details()
+        .unwrap();
+    assert_eq!(fee.fee.eth, "0.003");
+    assert_eq!(fee.base_fee_per_gas.unwrap(), "25000000000");
+    let burned = fee.burned.unwrap();
+    assert_eq!(burned.eth, "0.0025");
+    assert_eq!(burned.usdt, "5");
+    let priority = fee.priority.unwrap();
+    assert_eq!(priority.eth, "0.0005");
+    assert_eq!(priority.usdt, "1");
+
+    // Before the London fork there is no base fee: the whole fee goes to the miner
+    let fee = TxFee::new(
+        &receipt,
+        None,
+        &Block::default(),
+        "2000".into(),
+        Chain::Ethereum,
+    )
+    .unwrap();
+    assert_eq!(fee.burned_fee_wei.unwrap(), "0");
+    assert_eq!(fee.priority_fee_wei.unwrap(), fee.fee_wei);
+}
+
+#[test]
+fn exact_decimals() {
+    assert_eq!(
+        mul_decimal("18162370785237400", "1", 18).unwrap(),
+        "0.0181623707852374"
+    );
+    assert_eq!(
+        mul_decimal("18162370785237400", "3406.82000000", 18).unwrap(),
+        "61.875928038562479068"
+    );
+    assert_eq!(mul_decimal("-5", "2", 1).unwrap(), "-1");
+    assert_eq!(mul_decimal("0", "3000", 18).unwrap(), "0");
+    assert!(mul_decimal("0x12", "1", 0).is_err());
 }
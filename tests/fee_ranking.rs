@@ -0,0 +1,224 @@
+mod common;
+
+use common::{Fixtures, MockServer};
+use ethers::types::H256;
+use serde_json::{json, Value};
+use sqlx::postgres::PgPoolOptions;
+use std::str::FromStr;
+use std::sync::mpsc;
+use tokio::sync::{Mutex, MutexGuard};
+use uniswap_watcher::db::{
+    get_tx_fee_from_db, insert_record, insert_swap, insert_tx_fee, run_migrations, DbRecord, Swap,
+    TxFee,
+};
+use uniswap_watcher::{AppConfig, Application};
+
+const TX_HASH: &str = "0x0000000000000000000000000000000000000000000000000000000000a11ce3";
+const BLOCK_HASH: &str = "0x9b3f1c0e6a0f4f3ad5c2e1b7f8a9d0c1b2e3f4a5b6c7d8e9f0a1b2c3d4e5f6a7";
+const BLOCK_NUMBER: i64 = 19531264;
+const POOL: &str = "0x00000000000000000000000000000000000000cc";
+
+/// Held by each test while it uses the swaps stored on `POOL`
+static DB_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// Receipt of the 20 gwei transaction of the block fixture, with a swap on `POOL`
+fn swap_receipt(swap_topic: &str) -> Value {
+    let path = format!(
+        "{}/tests/fixtures/gas_block_receipts.json",
+        env!("CARGO_MANIFEST_DIR")
+    );
+    let receipts: Value = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    let mut receipt = receipts[3].clone();
+    receipt["logs"] = json!([{
+        "address": POOL,
+        "topics": [swap_topic],
+        "data": "0x",
+        "blockHash": BLOCK_HASH,
+        "blockNumber": receipt["blockNumber"],
+        "transactionHash": TX_HASH,
+        "transactionIndex": receipt["transactionIndex"],
+        "logIndex": "0x0",
+        "removed": false
+    }]);
+    receipt
+}
+
+/// Swap on `POOL` in a previous block, whose transaction paid the given gas price over a base fee of 8 gwei
+async fn store_previous_swap(
+    tx_hash: &str,
+    block_number: i64,
+    gas_price: &str,
+    db_connection: &sqlx::PgPool,
+) {
+    _ = sqlx::query("DELETE FROM fees WHERE tx_hash = $1")
+        .bind(tx_hash)
+        .execute(db_connection)
+        .await;
+    _ = sqlx::query("DELETE FROM swaps WHERE tx_hash = $1")
+        .bind(tx_hash)
+        .execute(db_connection)
+        .await;
+    insert_tx_fee(
+        &TxFee {
+            tx_hash: tx_hash.into(),
+            effective_gas_price: Some(gas_price.into()),
+            base_fee_per_gas: Some("8000000000".into()),
+            fee_wei: "0".into(),
+            eth_usdt_price: "2000".into(),
+            ..Default::default()
+        },
+        db_connection,
+    )
+    .await
+    .unwrap();
+    insert_swap(
+        &Swap {
+            tx_hash: tx_hash.into(),
+            log_index: 0,
+            pool_address: POOL.into(),
+            block_number,
+            block_hash: None,
+            timestamp_ms: 0,
+            sender: POOL.into(),
+            recipient: POOL.into(),
+            tx_from: POOL.into(),
+            amount0: "0".into(),
+            amount1: "0".into(),
+            price: 0.0,
+            notional_usd: 0.0,
+            fee_wei: None,
+            gas_usd: None,
+            gas_share: None,
+        },
+        db_connection,
+    )
+    .await
+    .unwrap();
+}
+
+/// Node serving the transaction and its block, and db with the previous swaps on `POOL`
+async fn setup() -> (MutexGuard<'static, ()>, MockServer, AppConfig, sqlx::PgPool) {
+    let lock = DB_LOCK.lock().await;
+    let mut config = AppConfig::new().unwrap();
+    let fixtures = Fixtures::default()
+        .with(
+            "eth_getTransactionReceipt",
+            TX_HASH,
+            swap_receipt(config.swap_topic.as_str()),
+        )
+        .with_file("eth_getBlockByHash", BLOCK_HASH, "gas_block.json")
+        .with_file(
+            "eth_getBlockReceipts",
+            "0x12a0600",
+            "gas_block_receipts.json",
+        );
+    let server = MockServer::start(fixtures);
+    config.rpc_urls_http = vec![server.url.clone()];
+    config.binance_host = server.url.clone();
+    config.ranking.lookback_blocks = 100;
+
+    let db_connection = PgPoolOptions::new()
+        .connect_timeout(std::time::Duration::from_secs(2))
+        .connect(&config.database.connection_string(false))
+        .await
+        .expect("Failed to create db connection");
+    run_migrations(&db_connection)
+        .await
+        .expect("Failed to run db migrations");
+    // Priority fees of 1, 10 and 20 gwei, the first one out of the lookback window
+    let previous = [
+        (
+            "0x00000000000000000000000000000000000000000000000000000000000000c1",
+            200,
+            "9000000000",
+        ),
+        (
+            "0x00000000000000000000000000000000000000000000000000000000000000c2",
+            2,
+            "18000000000",
+        ),
+        (
+            "0x00000000000000000000000000000000000000000000000000000000000000c3",
+            1,
+            "28000000000",
+        ),
+    ];
+    for (tx_hash, blocks_before, gas_price) in previous {
+        store_previous_swap(
+            tx_hash,
+            BLOCK_NUMBER - blocks_before,
+            gas_price,
+            &db_connection,
+        )
+        .await;
+    }
+    (lock, server, config, db_connection)
+}
+
+/// Ranking expected for `TX_HASH`
+fn assert_ranking(fee: &TxFee) {
+    let ranking = fee.details().unwrap().ranking.unwrap();
+    // 12 gwei of priority fee against 2, 4, 7 and 42 gwei in the block
+    assert_eq!(ranking.priority_fee_per_gas, "12000000000");
+    assert_eq!(ranking.block_percentile, 75.0);
+    assert_eq!(ranking.block_median_priority_fee, "4000000000");
+    // 21000 gas at 8 gwei above the median, at 2000 USDT per ETH
+    assert_eq!(ranking.overpayment.eth, "0.000168");
+    assert_eq!(ranking.overpayment.usdt, "0.336");
+    assert_eq!(ranking.pool_sample_size, 2);
+    assert_eq!(ranking.pool_percentile, Some(50.0));
+}
+
+#[tokio::test]
+async fn rank_priority_fee() {
+    let (_lock, _server, config, db_connection) = setup().await;
+    let (sender, _) = mpsc::channel();
+    let app = Application::new(config, sender, db_connection).unwrap();
+    let fee = app
+        .get_tx_fee(&H256::from_str(TX_HASH).unwrap())
+        .await
+        .unwrap();
+    assert_ranking(&fee);
+}
+
+#[tokio::test]
+async fn rank_stored_fee() {
+    let (_lock, _server, config, db_connection) = setup().await;
+    let tx_hash = H256::from_str(TX_HASH).unwrap();
+    // Fee stored unranked, as by the watcher or a backfill
+    let stored = TxFee {
+        tx_hash: TX_HASH.into(),
+        fee_wei: "252000000000000".into(),
+        eth_usdt_price: "2000".into(),
+        effective_gas_price: Some("20000000000".into()),
+        base_fee_per_gas: Some("8000000000".into()),
+        gas_used: Some("21000".into()),
+        block_number: Some(BLOCK_NUMBER),
+        ..Default::default()
+    };
+    _ = sqlx::query("DELETE FROM fees WHERE tx_hash = $1")
+        .bind(TX_HASH)
+        .execute(&db_connection)
+        .await;
+    insert_tx_fee(&stored, &db_connection).await.unwrap();
+
+    let (sender, receiver) = mpsc::channel();
+    let app = Application::new(config, sender, db_connection.clone()).unwrap();
+    let fees = app
+        .get_tx_fee_batch_exact(vec![TX_HASH.into()], None)
+        .await
+        .unwrap();
+    assert_ranking(&fees[&tx_hash]);
+
+    // The ranking is stored, and the fee is not ranked again on the next lookup
+    let records: Vec<DbRecord> = receiver.try_iter().collect();
+    assert_eq!(records.len(), 1);
+    assert!(matches!(records[0], DbRecord::TxFeeRanking(_)));
+    insert_record(&records[0], &db_connection).await.unwrap();
+    let fee = get_tx_fee_from_db(&tx_hash, &db_connection).await.unwrap();
+    assert_eq!(fee.block_percentile, Some(75.0));
+    assert_eq!(fee.effective_gas_price, stored.effective_gas_price);
+    let fee = app.try_get_or_insert(&tx_hash).await.unwrap();
+    assert_ranking(&fee);
+    assert!(receiver.try_recv().is_err());
+}
@@ -0,0 +1,214 @@
+mod common;
+
+use common::{lazy_db_connection, Fixtures, MockServer};
+use ethers::types::{Block, H256, U64};
+use serde_json::json;
+use sqlx::postgres::PgPoolOptions;
+use sqlx::PgPool;
+use std::str::FromStr;
+use std::sync::mpsc;
+use uniswap_watcher::db::{
+    get_address_activity_from_db, get_tx_fee_from_db, insert_swap, insert_tx_fee, promote_finality,
+    run_migrations, AddressRole, DbRecord, Finality, FinalityUpdate, Swap, TxFee,
+};
+use uniswap_watcher::ingest::SwapProcessor;
+use uniswap_watcher::{AppConfig, Application};
+
+/// Number of the block of `gas_block.json`, answered as the `finalized` block
+const FINALIZED_BLOCK: i64 = 19531264;
+
+#[tokio::test]
+async fn promote_on_new_head() {
+    let mut config = AppConfig::new().unwrap();
+    let fixtures =
+        Fixtures::default().with_file("eth_getBlockByNumber", "finalized", "gas_block.json");
+    let server = MockServer::start(fixtures);
+    config.rpc_urls_http = vec![server.url.clone()];
+    let (sender, receiver) = mpsc::channel();
+    let mut processor =
+        SwapProcessor::new(config.clone(), sender, lazy_db_connection(&config), None);
+
+    let head = Block {
+        number: Some(U64::from(19531340)),
+        hash: Some(H256::from_low_u64_be(19531340)),
+        ..Default::default()
+    };
+    processor.on_head(&head).await.unwrap();
+    match receiver.try_recv() {
+        Ok(DbRecord::Finality(update)) => assert_eq!(
+            update,
+            FinalityUpdate {
+                confirmed_block: 19531340 - config.finality.confirmations as i64,
+                finalized_block: Some(FINALIZED_BLOCK),
+            }
+        ),
+        record => panic!("expected a finality update, got {:?}", record),
+    }
+    assert!(receiver.try_recv().is_err());
+}
+
+async fn store_swap(tx_hash: &str, tx_from: &str, block_number: i64, pool: &PgPool) {
+    _ = sqlx::query("DELETE FROM fees WHERE tx_hash = $1")
+        .bind(tx_hash)
+        .execute(pool)
+        .await;
+    _ = sqlx::query("DELETE FROM swaps WHERE tx_hash = $1")
+        .bind(tx_hash)
+        .execute(pool)
+        .await;
+    insert_tx_fee(
+        &TxFee {
+            tx_hash: tx_hash.into(),
+            fee_wei: "0".into(),
+            eth_usdt_price: "2000".into(),
+            block_number: Some(block_number),
+            ..Default::default()
+        },
+        pool,
+    )
+    .await
+    .unwrap();
+    insert_swap(
+        &Swap {
+            tx_hash: tx_hash.into(),
+            log_index: 0,
+            pool_address: tx_from.into(),
+            block_number,
+            block_hash: None,
+            timestamp_ms: 0,
+            sender: tx_from.into(),
+            recipient: tx_from.into(),
+            tx_from: tx_from.into(),
+            amount0: "0".into(),
+            amount1: "0".into(),
+            price: 0.0,
+            notional_usd: 1000.0,
+            fee_wei: None,
+            gas_usd: None,
+            gas_share: None,
+        },
+        pool,
+    )
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn store_finality() {
+    let config = AppConfig::new().unwrap();
+    let db_connection = PgPoolOptions::new()
+        .connect_timeout(std::time::Duration::from_secs(2))
+        .connect(&config.database.connection_string(false))
+        .await
+        .expect("Failed to create db connection");
+    run_migrations(&db_connection)
+        .await
+        .expect("Failed to run db migrations");
+    // Blocks far in the future, so that no other test promotes them
+    let tx_from = "0x00000000000000000000000000000000000000f1";
+    let blocks = [
+        (
+            "0x0000000000000000000000000000000000000000000000000000000000f10001",
+            900_000_001,
+        ),
+        (
+            "0x0000000000000000000000000000000000000000000000000000000000f10002",
+            900_000_002,
+        ),
+        (
+            "0x0000000000000000000000000000000000000000000000000000000000f10003",
+            900_000_003,
+        ),
+    ];
+    for (tx_hash, block_number) in blocks {
+        store_swap(tx_hash, tx_from, block_number, &db_connection).await;
+    }
+
+    promote_finality(
+        &FinalityUpdate {
+            confirmed_block: 900_000_002,
+            finalized_block: Some(900_000_001),
+        },
+        &db_connection,
+    )
+    .await
+    .unwrap();
+
+    let mut finality = vec![];
+    for (tx_hash, _) in blocks {
+        let fee = get_tx_fee_from_db(&H256::from_str(tx_hash).unwrap(), &db_connection)
+            .await
+            .unwrap();
+        finality.push(fee.finality);
+    }
+    assert_eq!(
+        finality,
+        vec![Finality::Finalized, Finality::Confirmed, Finality::Pending]
+    );
+
+    for (min_finality, swap_count) in [
+        (None, 3),
+        (Some(Finality::Confirmed), 2),
+        (Some(Finality::Finalized), 1),
+    ] {
+        let activity = get_address_activity_from_db(
+            AddressRole::From,
+            Some(tx_from),
+            None,
+            None,
+            min_finality,
+            1,
+            &db_connection,
+        )
+        .await
+        .unwrap();
+        assert_eq!(activity[0].swap_count, swap_count);
+    }
+}
+
+#[tokio::test]
+async fn compute_finalized_fee() {
+    let tx_hash = "0x5c504ed432cb51138bcf09aa5e8a410dd4a1e204ef84bfed1be16dfba1b22060";
+    let block_hash = "0x4e3a3754410177e6937ef1f84bba68ea139e8d1a2258c5f85db9f1cd715a1bdd";
+    // The tx is in block 12487760, long before the `finalized` block
+    let fixtures = Fixtures::default()
+        .with_file(
+            "eth_getTransactionReceipt",
+            tx_hash,
+            "legacy_tx_receipt.json",
+        )
+        .with_file("eth_getTransactionByHash", tx_hash, "legacy_tx.json")
+        .with_file("eth_getBlockByHash", block_hash, "legacy_tx_block.json")
+        .with("eth_blockNumber", "null", json!("0x12a064c"))
+        .with_file("eth_getBlockByNumber", "finalized", "gas_block.json");
+    let server = MockServer::start(fixtures);
+    let mut config = AppConfig::new().unwrap();
+    config.rpc_urls_http = vec![server.url.clone()];
+    config.binance_host = server.url.clone();
+    let db_connection = PgPoolOptions::new()
+        .connect_timeout(std::time::Duration::from_secs(2))
+        .connect(&config.database.connection_string(false))
+        .await
+        .expect("Failed to create db connection");
+    run_migrations(&db_connection)
+        .await
+        .expect("Failed to run db migrations");
+    _ = sqlx::query("DELETE FROM fees WHERE tx_hash = $1")
+        .bind(tx_hash)
+        .execute(&db_connection)
+        .await;
+    let (sender, receiver) = mpsc::channel();
+    let app = Application::new(config, sender, db_connection).unwrap();
+
+    // The fee is not stored yet: it is computed, and already finalized
+    let fees = app
+        .get_tx_fee_batch_exact(vec![tx_hash.to_string()], Some(Finality::Finalized))
+        .await
+        .unwrap();
+    let fee = &fees[&H256::from_str(tx_hash).unwrap()];
+    assert_eq!(fee.finality, Finality::Finalized);
+    match receiver.try_recv() {
+        Ok(DbRecord::TxFee(stored)) => assert_eq!(stored.finality, Finality::Finalized),
+        record => panic!("expected a tx fee, got {:?}", record),
+    }
+}
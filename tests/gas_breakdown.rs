@@ -0,0 +1,53 @@
+mod common;
+
+use common::{Fixtures, MockServer};
+use ethers::types::H256;
+use sqlx::postgres::PgPoolOptions;
+use std::str::FromStr;
+use std::sync::mpsc;
+use uniswap_watcher::trace::GasCategory;
+use uniswap_watcher::{AppConfig, Application};
+
+const TX_HASH: &str = "0x4c5a0c9ab9c4d3f5d2b2f06d2a5e3b8b6b7f0e2c1d9a8b7c6d5e4f3a2b1c0d9e";
+
+#[tokio::test]
+async fn gas_breakdown_from_trace() {
+    let fixtures =
+        Fixtures::default().with_file("debug_traceTransaction", TX_HASH, "swap_trace.json");
+    let server = MockServer::start(fixtures);
+    let mut config = AppConfig::new().unwrap();
+    config.rpc_urls_http = vec![server.url.clone()];
+    let (sender, _) = mpsc::channel();
+    let db_connection = PgPoolOptions::new()
+        .connect_lazy(&config.database.connection_string(false))
+        .unwrap();
+    let app = Application::new(config, sender, db_connection).unwrap();
+    let breakdown = app
+        .get_gas_breakdown(H256::from_str(TX_HASH).unwrap())
+        .await
+        .unwrap();
+
+    assert_eq!(breakdown.gas_used, 120000);
+    // Pool swap frame and its balance checks
+    assert_eq!(breakdown.pool_swap_gas, 46000);
+    // Router entry point and swap callback
+    assert_eq!(breakdown.router_overhead_gas, 45000);
+    // WETH transfer out, USDC transferFrom in and its delegate call to the proxy implementation
+    assert_eq!(breakdown.token_transfer_gas, 29000);
+    assert_eq!(
+        breakdown.pool_swap_gas + breakdown.router_overhead_gas + breakdown.token_transfer_gas,
+        breakdown.gas_used
+    );
+
+    assert_eq!(breakdown.frames.len(), 8);
+    let delegate_call = &breakdown.frames[6];
+    assert_eq!(delegate_call.depth, 4);
+    assert_eq!(delegate_call.call_type, "DELEGATECALL");
+    assert_eq!(delegate_call.category, GasCategory::TokenTransfer);
+    let callback = &breakdown.frames[4];
+    assert_eq!(callback.selector.as_deref(), Some("0xfa461e33"));
+    assert_eq!(callback.self_gas_used, 10000);
+    assert_eq!(callback.category, GasCategory::RouterOverhead);
+
+    assert!(app.get_gas_breakdown(H256::zero()).await.is_err());
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use common::{Fixtures, MockServer};
+use ethers::types::{FeeHistory, U256};
+use sqlx::postgres::PgPoolOptions;
+use std::sync::mpsc;
+use uniswap_watcher::oracle::FeeWindow;
+use uniswap_watcher::{AppConfig, Application};
+
+const GWEI: u64 = 1_000_000_000;
+
+/// Fee history of `count` blocks from `oldest_block`, with rewards growing with the block number
+fn history(oldest_block: u64, count: u64) -> FeeHistory {
+    FeeHistory {
+        base_fee_per_gas: (0..=count).map(|i| U256::from((10 + i) * GWEI)).collect(),
+        gas_used_ratio: vec![0.5; count as usize],
+        oldest_block: U256::from(oldest_block),
+        reward: (0..count)
+            .map(|i| {
+                let block = oldest_block + i;
+                vec![
+                    U256::from(block),
+                    U256::from(2 * block),
+                    U256::from(3 * block),
+                ]
+            })
+            .collect(),
+    }
+}
+
+#[test]
+fn rolling_window() {
+    let mut window = FeeWindow::new(3);
+    window.update(&history(100, 3));
+    // Blocks already in the window are not added twice
+    window.update(&history(101, 4));
+    let numbers: Vec<u64> = window.blocks.iter().map(|block| block.number).collect();
+    assert_eq!(numbers, vec![102, 103, 104]);
+    assert_eq!(window.next_base_fee_per_gas, U256::from(14 * GWEI));
+    assert_eq!(window.priority_fee(1), Some(U256::from(2 * 103)));
+}
+
+#[tokio::test]
+async fn gas_suggestions() {
+    let server = MockServer::start(Fixtures::default());
+    let mut config = AppConfig::new().unwrap();
+    config.binance_host = server.url.clone();
+    config.basis.swap_gas = 150000;
+    let (sender, _) = mpsc::channel();
+    let db_connection = PgPoolOptions::new()
+        .connect_lazy(&config.database.connection_string(false))
+        .unwrap();
+    let app = Application::new(config, sender, db_connection).unwrap();
+    assert!(app.suggest_gas().await.is_err());
+
+    // Next base fee of 20 gwei, median rewards of 1, 2 and 3 gwei over the window
+    app.gas_oracle.write().unwrap().update(&FeeHistory {
+        base_fee_per_gas: vec![U256::from(18 * GWEI), U256::from(20 * GWEI)],
+        gas_used_ratio: vec![0.9],
+        oldest_block: U256::from(100),
+        reward: vec![vec![
+            U256::from(GWEI),
+            U256::from(2 * GWEI),
+            U256::from(3 * GWEI),
+        ]],
+    });
+    let suggestions = app.suggest_gas().await.unwrap();
+    assert_eq!(suggestions.block_number, 100);
+    assert_eq!(suggestions.next_base_fee_per_gas, "20000000000");
+    assert_eq!(suggestions.slow.max_priority_fee_per_gas, "1000000000");
+    assert_eq!(suggestions.fast.max_fee_per_gas, "43000000000");
+    // 150000 gas at 22 gwei and 2000 USDT per ETH
+    assert_eq!(suggestions.standard.swap_cost.eth, "0.0033");
+    assert_eq!(suggestions.standard.swap_cost.usdt, "6.6");
+}
@@ -0,0 +1,55 @@
+use ethers::prelude::{Block, TransactionReceipt, H256, U256};
+use uniswap_watcher::db::TxFee;
+use uniswap_watcher::fee::Chain;
+
+fn receipt(file: &str) -> TransactionReceipt {
+    let path = format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), file);
+    serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+}
+
+fn block(base_fee_per_gas: u64) -> Block<H256> {
+    Block {
+        base_fee_per_gas: Some(U256::from(base_fee_per_gas)),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn op_stack_l1_fee() {
+    let receipt = receipt("op_stack_tx_receipt.json");
+    for chain in [Chain::Optimism, Chain::Base] {
+        let fee = TxFee::new(&receipt, None, &block(1000), "2000".into(), chain)
+            .unwrap()
+            .details()
+            .unwrap();
+        // 187500 gas at 0.001 gwei, plus 0.0001 ETH of L1 data fee
+        assert_eq!(fee.l1_gas_used.as_deref(), Some("1600"));
+        assert_eq!(fee.l1.unwrap().eth, "0.0001");
+        assert_eq!(fee.fee.eth, "0.0001001875");
+        assert_eq!(fee.fee.usdt, "0.200375");
+    }
+
+    // The L1 fee is ignored when watching Ethereum
+    let fee = TxFee::new(&receipt, None, &block(1000), "2000".into(), Chain::Ethereum).unwrap();
+    assert_eq!(fee.l1_fee_wei, None);
+    assert_eq!(fee.fee_eth().unwrap(), "0.0000001875");
+}
+
+#[test]
+fn arbitrum_l1_component() {
+    let receipt = receipt("arbitrum_tx_receipt.json");
+    let fee = TxFee::new(
+        &receipt,
+        None,
+        &block(100_000_000),
+        "2000".into(),
+        Chain::Arbitrum,
+    )
+    .unwrap()
+    .details()
+    .unwrap();
+    // The L1 component is part of the 500000 gas used, so it does not add to the total fee
+    assert_eq!(fee.l1_gas_used.as_deref(), Some("100000"));
+    assert_eq!(fee.l1.unwrap().eth, "0.00001");
+    assert_eq!(fee.fee.eth, "0.00005");
+}
@@ -0,0 +1,99 @@
+mod common;
+
+use common::{Fixtures, MockServer};
+use ethers::types::{Block, Transaction, TransactionReceipt, H256, U256, U64};
+use sqlx::postgres::PgPoolOptions;
+use std::str::FromStr;
+use std::sync::mpsc;
+use uniswap_watcher::db::TxFee;
+use uniswap_watcher::fee::{resolve_gas_price, Chain, GasPriceSource};
+use uniswap_watcher::{AppConfig, Application};
+
+const TX_HASH: &str = "0x5c504ed432cb51138bcf09aa5e8a410dd4a1e204ef84bfed1be16dfba1b22060";
+const BLOCK_HASH: &str = "0x4e3a3754410177e6937ef1f84bba68ea139e8d1a2258c5f85db9f1cd715a1bdd";
+
+#[tokio::test]
+async fn pre_london_tx_fee() {
+    // The receipt has no effective gas price: the gas price of the transaction is used
+    let fixtures = Fixtures::default()
+        .with_file(
+            "eth_getTransactionReceipt",
+            TX_HASH,
+            "legacy_tx_receipt.json",
+        )
+        .with_file("eth_getTransactionByHash", TX_HASH, "legacy_tx.json")
+        .with_file("eth_getBlockByHash", BLOCK_HASH, "legacy_tx_block.json");
+    let server = MockServer::start(fixtures);
+
+    let mut config = AppConfig::new().unwrap();
+    config.rpc_urls_http = vec![server.url.clone()];
+    config.binance_host = server.url.clone();
+    let (sender, _) = mpsc::channel();
+    let db_connection = PgPoolOptions::new()
+        .connect_lazy(&config.database.connection_string(false))
+        .unwrap();
+    let app = Application::new(config, sender, db_connection).unwrap();
+
+    let fee = app
+        .get_tx_fee(&H256::from_str(TX_HASH).unwrap())
+        .await
+        .unwrap()
+        .details()
+        .unwrap();
+    // 21000 gas at 50 gwei, all of it paid to the miner
+    assert_eq!(fee.gas_price_source.as_deref(), Some("legacy_gas_price"));
+    assert_eq!(fee.effective_gas_price.unwrap(), "50000000000");
+    assert_eq!(fee.base_fee_per_gas, None);
+    assert_eq!(fee.burned.unwrap().eth, "0");
+    assert_eq!(fee.priority.unwrap().eth, "0.00105");
+    assert_eq!(fee.fee.eth, "0.00105");
+    assert_eq!(fee.fee.usdt, "2.1");
+}
+
+#[test]
+fn dynamic_fee_without_effective_gas_price() {
+    let receipt = TransactionReceipt {
+        gas_used: Some(U256::from(100000)),
+        ..Default::default()
+    };
+    let transaction = Transaction {
+        transaction_type: Some(U64::from(2)),
+        max_fee_per_gas: Some(U256::from(40_000_000_000u64)),
+        max_priority_fee_per_gas: Some(U256::from(2_000_000_000u64)),
+        ..Default::default()
+    };
+    let block = Block::<H256> {
+        base_fee_per_gas: Some(U256::from(25_000_000_000u64)),
+        ..Default::default()
+    };
+
+    // The priority fee is paid in full below the max fee
+    let (gas_price, source) =
+        resolve_gas_price(&receipt, Some(&transaction), block.base_fee_per_gas).unwrap();
+    assert_eq!(gas_price, U256::from(27_000_000_000u64));
+    assert_eq!(source, GasPriceSource::DynamicFee);
+
+    // ... and capped by the max fee above it
+    let (gas_price, _) = resolve_gas_price(
+        &receipt,
+        Some(&transaction),
+        Some(U256::from(39_000_000_000u64)),
+    )
+    .unwrap();
+    assert_eq!(gas_price, U256::from(40_000_000_000u64));
+
+    let fee = TxFee::new(
+        &receipt,
+        Some(&transaction),
+        &block,
+        "2000".into(),
+        Chain::Ethereum,
+    )
+    .unwrap();
+    assert_eq!(fee.fee_eth().unwrap(), "0.0027");
+    assert_eq!(fee.burned_fee_wei.unwrap(), "2500000000000000");
+    assert_eq!(fee.priority_fee_wei.unwrap(), "200000000000000");
+
+    // Without the transaction the gas price cannot be determined
+    assert!(TxFee::new(&receipt, None, &block, "2000".into(), Chain::Ethereum).is_err());
+}
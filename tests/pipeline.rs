@@ -0,0 +1,194 @@
+mod common;
+
+use common::{decode_swap_log, lazy_db_connection, swap_log, Fixtures, MockServer};
+use serde_json::{json, Value};
+use std::sync::mpsc;
+use std::time::Duration;
+use uniswap_watcher::db::DbRecord;
+use uniswap_watcher::ingest::{PipelineStats, SwapProcessor};
+use uniswap_watcher::AppConfig;
+
+const LEGACY_TX_HASH: &str = "0x5c504ed432cb51138bcf09aa5e8a410dd4a1e204ef84bfed1be16dfba1b22060";
+const LEGACY_BLOCK_HASH: &str =
+    "0x4e3a3754410177e6937ef1f84bba68ea139e8d1a2258c5f85db9f1cd715a1bdd";
+const BLOB_TX_HASH: &str = "0xbb1e0f1c1accfedcc1dddfcc48d7d0bf893263a77e74c3d26c63adf729e89e69";
+const BLOB_BLOCK_HASH: &str = "0xd3d17a9c8eb69157f59feb3c42cc46126d390efae0c8525f7377115d5d476235";
+/// Tx whose receipt is never found, so that its fetch is retried for several seconds
+const PENDING_TX_HASH: &str = "0x00000000000000000000000000000000000000000000000000000000000dead2";
+const PENDING_BLOCK_HASH: &str =
+    "0x00000000000000000000000000000000000000000000000000000000000b10c2";
+
+/// Receipt of the legacy tx with the given swap logs
+fn legacy_receipt(logs: Vec<Value>) -> Value {
+    let path = format!(
+        "{}/tests/fixtures/legacy_tx_receipt.json",
+        env!("CARGO_MANIFEST_DIR")
+    );
+    let mut receipt: Value = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    receipt["logs"] = json!(logs);
+    receipt
+}
+
+#[tokio::test]
+async fn store_fee_once_per_tx() {
+    let config = AppConfig::new().unwrap();
+    // Two swaps of the same transaction on the pool
+    let logs: Vec<Value> = (0..2)
+        .map(|log_index| {
+            let mut log = swap_log(&config, LEGACY_TX_HASH, LEGACY_BLOCK_HASH, 12487760);
+            log["logIndex"] = json!(format!("{:#x}", log_index));
+            log
+        })
+        .collect();
+    let fixtures = Fixtures::default()
+        .with(
+            "eth_getTransactionReceipt",
+            LEGACY_TX_HASH,
+            legacy_receipt(logs.clone()),
+        )
+        .with_file("eth_getTransactionByHash", LEGACY_TX_HASH, "legacy_tx.json")
+        .with_file(
+            "eth_getBlockByHash",
+            LEGACY_BLOCK_HASH,
+            "legacy_tx_block.json",
+        );
+    let server = MockServer::start(fixtures);
+    let mut config = config;
+    config.rpc_urls_http = vec![server.url.clone()];
+    config.binance_host = server.url.clone();
+    let (sender, receiver) = mpsc::channel();
+    let mut processor =
+        SwapProcessor::new(config.clone(), sender, lazy_db_connection(&config), None);
+
+    for log in logs {
+        let (log, meta) = decode_swap_log(log);
+        assert!(processor.handle(log, meta, true).await.unwrap());
+    }
+    processor.flush().await;
+    let records: Vec<DbRecord> = receiver.try_iter().collect();
+    let fees = records
+        .iter()
+        .filter(|record| matches!(record, DbRecord::TxFee(_)))
+        .count();
+    let swaps = records
+        .iter()
+        .filter(|record| matches!(record, DbRecord::Swap(_)))
+        .count();
+    assert_eq!((fees, swaps), (1, 2));
+}
+
+#[tokio::test]
+async fn fetch_receipts_per_block() {
+    let mut config = AppConfig::new().unwrap();
+    // Two swaps of the legacy tx in block 107, after a swap of the blob tx in block 105
+    let legacy_logs: Vec<Value> = (0..2)
+        .map(|log_index| {
+            let mut log = swap_log(&config, LEGACY_TX_HASH, LEGACY_BLOCK_HASH, 107);
+            log["logIndex"] = json!(format!("{:#x}", log_index));
+            log
+        })
+        .collect();
+    let mut logs = vec![swap_log(&config, BLOB_TX_HASH, BLOB_BLOCK_HASH, 105)];
+    logs.extend(legacy_logs.clone());
+    let fixtures = Fixtures::default()
+        .with(
+            "eth_getTransactionReceipt",
+            LEGACY_TX_HASH,
+            legacy_receipt(legacy_logs),
+        )
+        .with_file("eth_getTransactionByHash", LEGACY_TX_HASH, "legacy_tx.json")
+        .with_file(
+            "eth_getBlockByHash",
+            LEGACY_BLOCK_HASH,
+            "legacy_tx_block.json",
+        )
+        .with_file(
+            "eth_getTransactionReceipt",
+            BLOB_TX_HASH,
+            "blob_tx_receipt.json",
+        )
+        .with_file("eth_getBlockByHash", BLOB_BLOCK_HASH, "blob_tx_block.json")
+        .with_logs(105, 107, json!(logs));
+    let server = MockServer::start(fixtures);
+    config.rpc_urls_http = vec![server.url.clone()];
+    config.binance_host = server.url.clone();
+    config.pipeline.workers = 2;
+    let (sender, receiver) = mpsc::channel();
+    let mut processor = SwapProcessor::new(
+        config.clone(),
+        sender,
+        lazy_db_connection(&config),
+        Some(104),
+    );
+
+    // The receipts of each block are fetched once, by the first of its swaps
+    processor.catch_up(107).await.unwrap();
+    assert_eq!(server.requests("eth_getTransactionReceipt"), 2);
+    let swaps: Vec<i64> = receiver
+        .try_iter()
+        .filter_map(|record| match record {
+            DbRecord::Swap(swap) => Some(swap.block_number),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(swaps, vec![105, 107, 107]);
+}
+
+#[tokio::test]
+async fn slow_swap_does_not_stall_fetches() {
+    let fixtures = Fixtures::default()
+        .with("eth_getTransactionReceipt", PENDING_TX_HASH, Value::Null)
+        .with_file(
+            "eth_getTransactionReceipt",
+            LEGACY_TX_HASH,
+            "legacy_tx_receipt.json",
+        )
+        .with_file("eth_getTransactionByHash", LEGACY_TX_HASH, "legacy_tx.json")
+        .with_file(
+            "eth_getBlockByHash",
+            LEGACY_BLOCK_HASH,
+            "legacy_tx_block.json",
+        );
+    let server = MockServer::start(fixtures);
+    let mut config = AppConfig::new().unwrap();
+    config.rpc_urls_http = vec![server.url.clone()];
+    config.binance_host = server.url.clone();
+    config.pipeline.workers = 2;
+    let (sender, receiver) = mpsc::channel();
+    let mut processor =
+        SwapProcessor::new(config.clone(), sender, lazy_db_connection(&config), None);
+
+    let (log, meta) = decode_swap_log(swap_log(&config, PENDING_TX_HASH, PENDING_BLOCK_HASH, 105));
+    assert!(processor.handle(log, meta, true).await.unwrap());
+    let (log, meta) = decode_swap_log(swap_log(&config, LEGACY_TX_HASH, LEGACY_BLOCK_HASH, 106));
+    assert!(processor.handle(log, meta, true).await.unwrap());
+
+    // Both swaps are fetched at the same time, but the second one is only stored after the first
+    let next = tokio::time::timeout(Duration::from_millis(500), processor.next_step()).await;
+    assert!(next.is_err());
+    assert!(receiver.try_recv().is_err());
+    assert_eq!(processor.monitor.read().unwrap().queue_depth, 2);
+
+    processor.flush().await;
+    let events: Vec<String> = receiver
+        .try_iter()
+        .filter_map(|record| match record {
+            DbRecord::IngestionError(error) => Some(format!("error {}", error.block_number)),
+            DbRecord::Checkpoint(checkpoint) => {
+                Some(format!("checkpoint {}", checkpoint.block_number))
+            }
+            DbRecord::Swap(swap) => Some(format!("swap {}", swap.block_number)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(events, vec!["error 105", "checkpoint 105", "swap 106"]);
+    assert_eq!(
+        *processor.monitor.read().unwrap(),
+        PipelineStats {
+            workers: 2,
+            queue_depth: 0,
+            max_queue_depth: 2,
+            processed: 2,
+        }
+    );
+}
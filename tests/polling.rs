@@ -0,0 +1,82 @@
+mod common;
+
+use common::{lazy_db_connection, swap_log, Fixtures, MockServer};
+use ethers::types::{Block, H256, U64};
+use serde_json::json;
+use std::sync::mpsc;
+use uniswap_watcher::db::DbRecord;
+use uniswap_watcher::ingest::SwapProcessor;
+use uniswap_watcher::AppConfig;
+
+const LEGACY_TX_HASH: &str = "0x5c504ed432cb51138bcf09aa5e8a410dd4a1e204ef84bfed1be16dfba1b22060";
+const LEGACY_BLOCK_HASH: &str =
+    "0x4e3a3754410177e6937ef1f84bba68ea139e8d1a2258c5f85db9f1cd715a1bdd";
+
+fn head(number: u64) -> Block<H256> {
+    Block {
+        number: Some(U64::from(number)),
+        hash: Some(H256::from_low_u64_be(number)),
+        parent_hash: H256::from_low_u64_be(number - 1),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn poll_new_swaps() {
+    let mut config = AppConfig::new().unwrap();
+    let fixtures = Fixtures::default()
+        .with_file(
+            "eth_getTransactionReceipt",
+            LEGACY_TX_HASH,
+            "legacy_tx_receipt.json",
+        )
+        .with_file("eth_getTransactionByHash", LEGACY_TX_HASH, "legacy_tx.json")
+        .with_file(
+            "eth_getBlockByHash",
+            LEGACY_BLOCK_HASH,
+            "legacy_tx_block.json",
+        )
+        .with_logs(
+            105,
+            107,
+            json!([swap_log(&config, LEGACY_TX_HASH, LEGACY_BLOCK_HASH, 107)]),
+        );
+    let server = MockServer::start(fixtures);
+    config.rpc_urls_http = vec![server.url.clone()];
+    config.binance_host = server.url.clone();
+
+    // On the first start, swaps are processed from the first polled head
+    let (sender, receiver) = mpsc::channel();
+    let mut processor =
+        SwapProcessor::new(config.clone(), sender, lazy_db_connection(&config), None);
+    processor.poll(&head(104)).await.unwrap();
+    let records: Vec<DbRecord> = receiver.try_iter().collect();
+    assert_eq!(records.len(), 1);
+    assert!(
+        matches!(&records[0], DbRecord::Checkpoint(checkpoint) if checkpoint.block_number == 104)
+    );
+
+    // The swaps of the blocks mined since the previous poll are processed as live swaps
+    processor.poll(&head(107)).await.unwrap();
+    let records: Vec<DbRecord> = receiver.try_iter().collect();
+    let swaps: Vec<_> = records
+        .iter()
+        .filter_map(|record| match record {
+            DbRecord::Swap(swap) => Some((swap.block_number, swap.timestamp_ms)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(swaps.len(), 1);
+    assert_eq!(swaps[0].0, 107);
+    // Live swaps have the time of their block, like caught up ones
+    assert_eq!(swaps[0].1, 1623456160000);
+    assert!(matches!(records.first(), Some(DbRecord::Finality(_))));
+    assert!(
+        matches!(records.last(), Some(DbRecord::Checkpoint(checkpoint)) if checkpoint.block_number == 107)
+    );
+    assert_eq!(processor.checkpoint, Some(107));
+
+    // Polling the same head again does nothing
+    processor.poll(&head(107)).await.unwrap();
+    assert!(receiver.try_recv().is_err());
+}
@@ -0,0 +1,187 @@
+mod common;
+
+use common::{Fixtures, MockServer};
+use ethers::types::{TxHash, H256};
+use serde_json::Value;
+use std::str::FromStr;
+use uniswap_watcher::receipts::{ReceiptFetcher, ReceiptSettings};
+use uniswap_watcher::rpc::{connect_http, EthClient, RpcSettings};
+
+const BLOCK_NUMBER: u64 = 19531264;
+const UNKNOWN_TX_HASH: &str = "0x00000000000000000000000000000000000000000000000000000000000dead3";
+
+fn block_receipts() -> Vec<Value> {
+    let path = format!(
+        "{}/tests/fixtures/gas_block_receipts.json",
+        env!("CARGO_MANIFEST_DIR")
+    );
+    serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+}
+
+fn tx_hashes() -> Vec<TxHash> {
+    block_receipts()
+        .iter()
+        .map(|receipt| H256::from_str(receipt["transactionHash"].as_str().unwrap()).unwrap())
+        .collect()
+}
+
+/// Node answering `eth_getTransactionReceipt` for every tx of the block, and `eth_getBlockReceipts` if `supported`
+fn fixtures(supported: bool) -> Fixtures {
+    let mut fixtures = Fixtures::default()
+        .with_file("eth_getBlockByNumber", "0x12a0600", "gas_block.json")
+        .with("eth_getTransactionReceipt", UNKNOWN_TX_HASH, Value::Null);
+    for receipt in block_receipts() {
+        let tx_hash = receipt["transactionHash"].as_str().unwrap().to_string();
+        fixtures = fixtures.with("eth_getTransactionReceipt", &tx_hash, receipt);
+    }
+    if supported {
+        fixtures = fixtures.with_file(
+            "eth_getBlockReceipts",
+            "0x12a0600",
+            "gas_block_receipts.json",
+        );
+    }
+    fixtures
+}
+
+fn new_fetcher(server: &MockServer, batch_size: usize) -> (ReceiptFetcher, EthClient) {
+    let eth_client =
+        connect_http(std::slice::from_ref(&server.url), &RpcSettings::default()).unwrap();
+    let fetcher = ReceiptFetcher::new(
+        eth_client.clone(),
+        ReceiptSettings {
+            batch_size,
+            block_receipts_min_txs: 2,
+            block_cache_size: 2,
+        },
+    );
+    (fetcher, eth_client)
+}
+
+fn requests(eth_client: &EthClient) -> u64 {
+    eth_client.as_ref().health()[0].1.requests
+}
+
+#[tokio::test]
+async fn batch_tx_receipts() {
+    let server = MockServer::start(fixtures(false));
+    let (fetcher, eth_client) = new_fetcher(&server, 2);
+    let mut tx_hashes = tx_hashes();
+    tx_hashes.push(H256::from_str(UNKNOWN_TX_HASH).unwrap());
+
+    // The tx without receipt yet is left out
+    let receipts = fetcher.get_receipts(&tx_hashes).await.unwrap();
+    assert_eq!(requests(&eth_client), 3);
+    assert_eq!(receipts.len(), 5);
+    for tx_hash in &tx_hashes[..5] {
+        assert_eq!(receipts[tx_hash].transaction_hash, *tx_hash);
+    }
+}
+
+#[tokio::test]
+async fn failed_call_within_batch() {
+    let fixtures = || {
+        fixtures(false).with_error(
+            "eth_getTransactionReceipt",
+            UNKNOWN_TX_HASH,
+            "internal error",
+        )
+    };
+    let servers = [MockServer::start(fixtures()), MockServer::start(fixtures())];
+    let urls: Vec<String> = servers.iter().map(|server| server.url.clone()).collect();
+    let eth_client = connect_http(&urls, &RpcSettings::default()).unwrap();
+    let fetcher = ReceiptFetcher::new(
+        eth_client,
+        ReceiptSettings {
+            batch_size: 10,
+            block_receipts_min_txs: 2,
+            block_cache_size: 2,
+        },
+    );
+    let mut tx_hashes = tx_hashes();
+    tx_hashes.push(H256::from_str(UNKNOWN_TX_HASH).unwrap());
+
+    // Only the failed call is left out, and the batch is not sent again to the other endpoint
+    let receipts = fetcher.get_receipts(&tx_hashes).await.unwrap();
+    assert_eq!(receipts.len(), 5);
+    let sent: usize = servers
+        .iter()
+        .map(|server| server.requests("eth_getTransactionReceipt"))
+        .sum();
+    assert_eq!(sent, tx_hashes.len());
+}
+
+#[tokio::test]
+async fn block_receipts_fall_back_to_batches() {
+    // With `eth_getBlockReceipts`
+    let server = MockServer::start(fixtures(true));
+    let (fetcher, eth_client) = new_fetcher(&server, 50);
+    let receipts = fetcher.get_block_receipts(BLOCK_NUMBER).await.unwrap();
+    assert_eq!(requests(&eth_client), 1);
+    let hashes: Vec<TxHash> = receipts
+        .iter()
+        .map(|receipt| receipt.transaction_hash)
+        .collect();
+    assert_eq!(hashes, tx_hashes());
+
+    // Without, the receipts of the txs of the block are fetched in a batch, in block order
+    let server = MockServer::start(fixtures(false));
+    let (fetcher, eth_client) = new_fetcher(&server, 50);
+    let receipts = fetcher.get_block_receipts(BLOCK_NUMBER).await.unwrap();
+    assert_eq!(requests(&eth_client), 3);
+    let hashes: Vec<TxHash> = receipts
+        .iter()
+        .map(|receipt| receipt.transaction_hash)
+        .collect();
+    assert_eq!(hashes, tx_hashes());
+
+    // `eth_getBlockReceipts` is not tried again
+    fetcher.get_block_receipts(BLOCK_NUMBER).await.unwrap();
+    assert_eq!(requests(&eth_client), 5);
+}
+
+#[tokio::test]
+async fn cache_block_receipts() {
+    let server = MockServer::start(fixtures(true));
+    let (fetcher, eth_client) = new_fetcher(&server, 50);
+    let block_hash = H256::from_str(block_receipts()[0]["blockHash"].as_str().unwrap()).unwrap();
+
+    let receipts = fetcher
+        .get_cached_block_receipts(BLOCK_NUMBER, block_hash)
+        .await
+        .unwrap();
+    assert_eq!(receipts.len(), 5);
+    fetcher
+        .get_cached_block_receipts(BLOCK_NUMBER, block_hash)
+        .await
+        .unwrap();
+    assert_eq!(requests(&eth_client), 1);
+
+    // The block was reorged: the receipts of the wanted block are fetched again, and not cached
+    let reorged_hash = H256::from_low_u64_be(0xb10c);
+    for _ in 0..2 {
+        fetcher
+            .get_cached_block_receipts(BLOCK_NUMBER, reorged_hash)
+            .await
+            .unwrap();
+    }
+    assert_eq!(requests(&eth_client), 3);
+}
+
+#[tokio::test]
+async fn receipts_by_block() {
+    let server = MockServer::start(fixtures(true));
+    let (fetcher, _) = new_fetcher(&server, 50);
+    let tx_hashes = tx_hashes();
+
+    // A single tx of the block is fetched on its own, several ones with the whole block
+    let txs = vec![(BLOCK_NUMBER, tx_hashes[1]), (BLOCK_NUMBER, tx_hashes[3])];
+    let receipts = fetcher.get_receipts_by_block(&txs).await.unwrap();
+    assert_eq!(receipts.len(), 2);
+    assert!(receipts.contains_key(&tx_hashes[1]) && receipts.contains_key(&tx_hashes[3]));
+
+    let txs = vec![(BLOCK_NUMBER, tx_hashes[2])];
+    let receipts = fetcher.get_receipts_by_block(&txs).await.unwrap();
+    assert_eq!(receipts.len(), 1);
+    assert!(receipts.contains_key(&tx_hashes[2]));
+}
@@ -0,0 +1,308 @@
+mod common;
+
+use common::{lazy_db_connection, swap_log, Fixtures, MockServer};
+use ethers::types::{Block, Log, H256, U64};
+use serde_json::{json, Value};
+use sqlx::postgres::PgPoolOptions;
+use sqlx::PgPool;
+use std::str::FromStr;
+use std::sync::mpsc;
+use uniswap_watcher::db::{
+    get_checkpoint_from_db, get_reorgs_from_db, get_tx_fee_from_db, insert_swap, insert_tx_fee,
+    rollback_reorg, run_migrations, upsert_checkpoint, Checkpoint, DbRecord, Reorg, Swap, TxFee,
+};
+use uniswap_watcher::ingest::SwapProcessor;
+use uniswap_watcher::AppConfig;
+
+const LEGACY_TX_HASH: &str = "0x5c504ed432cb51138bcf09aa5e8a410dd4a1e204ef84bfed1be16dfba1b22060";
+const LEGACY_BLOCK_HASH: &str =
+    "0x4e3a3754410177e6937ef1f84bba68ea139e8d1a2258c5f85db9f1cd715a1bdd";
+const BLOB_TX_HASH: &str = "0xbb1e0f1c1accfedcc1dddfcc48d7d0bf893263a77e74c3d26c63adf729e89e69";
+const BLOB_BLOCK_HASH: &str = "0xd3d17a9c8eb69157f59feb3c42cc46126d390efae0c8525f7377115d5d476235";
+/// Hash of `gas_block.json`, answered as the canonical block 105
+const CANONICAL_BLOCK_HASH: &str =
+    "0x9b3f1c0e6a0f4f3ad5c2e1b7f8a9d0c1b2e3f4a5b6c7d8e9f0a1b2c3d4e5f6a7";
+
+fn fixtures() -> Fixtures {
+    Fixtures::default()
+        .with_file(
+            "eth_getTransactionReceipt",
+            LEGACY_TX_HASH,
+            "legacy_tx_receipt.json",
+        )
+        .with_file("eth_getTransactionByHash", LEGACY_TX_HASH, "legacy_tx.json")
+        .with_file(
+            "eth_getBlockByHash",
+            LEGACY_BLOCK_HASH,
+            "legacy_tx_block.json",
+        )
+        .with_file(
+            "eth_getTransactionReceipt",
+            BLOB_TX_HASH,
+            "blob_tx_receipt.json",
+        )
+        .with_file("eth_getBlockByHash", BLOB_BLOCK_HASH, "blob_tx_block.json")
+}
+
+fn log(value: Value) -> Log {
+    serde_json::from_value(value).unwrap()
+}
+
+fn removed(mut value: Value) -> Log {
+    value["removed"] = json!(true);
+    log(value)
+}
+
+/// Head whose hash differs with its parent
+fn head(number: u64, parent_hash: &str) -> Block<H256> {
+    let parent_hash = H256::from_str(parent_hash).unwrap();
+    Block {
+        number: Some(U64::from(number)),
+        hash: Some(H256::from_low_u64_be(number) ^ parent_hash),
+        parent_hash,
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn removed_log_rolls_back_and_reingests() {
+    let mut config = AppConfig::new().unwrap();
+    // On the canonical chain, block 105 has no swap and the next swap is in block 107
+    let fixtures = fixtures()
+        .with("eth_blockNumber", "null", json!("0x6b"))
+        .with_logs(
+            105,
+            107,
+            json!([swap_log(&config, LEGACY_TX_HASH, LEGACY_BLOCK_HASH, 107)]),
+        );
+    let server = MockServer::start(fixtures);
+    config.rpc_urls_http = vec![server.url.clone()];
+    config.binance_host = server.url.clone();
+    let (sender, receiver) = mpsc::channel();
+    let mut processor = SwapProcessor::new(
+        config.clone(),
+        sender,
+        lazy_db_connection(&config),
+        Some(104),
+    );
+
+    let orphaned = swap_log(&config, BLOB_TX_HASH, BLOB_BLOCK_HASH, 105);
+    assert!(processor
+        .handle_log(log(orphaned.clone()), true)
+        .await
+        .unwrap());
+    assert!(!processor
+        .handle_log(removed(orphaned.clone()), true)
+        .await
+        .unwrap());
+
+    let records: Vec<DbRecord> = receiver.try_iter().collect();
+    let events: Vec<String> = records
+        .iter()
+        .filter_map(|record| match record {
+            DbRecord::Swap(swap) => Some(format!("swap {}", swap.block_number)),
+            DbRecord::Reorg(reorg) => Some(format!("reorg {}", reorg.block_number)),
+            DbRecord::Checkpoint(checkpoint) => {
+                Some(format!("checkpoint {}", checkpoint.block_number))
+            }
+            _ => None,
+        })
+        .collect();
+    // The orphaned swap is rolled back before the canonical swaps are ingested
+    assert_eq!(
+        events,
+        vec!["swap 105", "reorg 105", "swap 107", "checkpoint 107"]
+    );
+    let reorg = records
+        .iter()
+        .find_map(|record| match record {
+            DbRecord::Reorg(reorg) => Some(reorg.clone()),
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!(reorg.orphaned_block_hash, BLOB_BLOCK_HASH);
+    assert_eq!(reorg.canonical_block_hash, None);
+    assert_eq!(reorg.detected_by, "removed_log");
+    let swap_block_hash = records.iter().find_map(|record| match record {
+        DbRecord::Swap(swap) if swap.block_number == 107 => swap.block_hash.clone(),
+        _ => None,
+    });
+    assert_eq!(swap_block_hash.as_deref(), Some(LEGACY_BLOCK_HASH));
+    assert_eq!(processor.checkpoint, Some(107));
+
+    // The other removed logs of the orphaned block are ignored
+    assert!(!processor.handle_log(removed(orphaned), true).await.unwrap());
+    assert!(receiver.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn parent_hash_mismatch_rolls_back() {
+    let mut config = AppConfig::new().unwrap();
+    let fixtures = fixtures()
+        .with_file("eth_getBlockByNumber", "0x69", "gas_block.json")
+        .with_logs(105, 106, json!([]));
+    let server = MockServer::start(fixtures);
+    config.rpc_urls_http = vec![server.url.clone()];
+    config.binance_host = server.url.clone();
+    let (sender, receiver) = mpsc::channel();
+    let mut processor = SwapProcessor::new(
+        config.clone(),
+        sender,
+        lazy_db_connection(&config),
+        Some(104),
+    );
+
+    let swap = swap_log(&config, BLOB_TX_HASH, BLOB_BLOCK_HASH, 105);
+    assert!(processor.handle_log(log(swap), true).await.unwrap());
+    processor.flush().await;
+    _ = receiver.try_iter().count();
+
+    // A head extending the processed block is not checked, only the finality of the rows is promoted
+    processor
+        .on_head(&head(106, BLOB_BLOCK_HASH))
+        .await
+        .unwrap();
+    assert!(matches!(receiver.try_recv(), Ok(DbRecord::Finality(_))));
+    assert!(receiver.try_recv().is_err());
+
+    // A head at the same height with another parent means block 105 was replaced
+    processor
+        .on_head(&head(106, CANONICAL_BLOCK_HASH))
+        .await
+        .unwrap();
+    let records: Vec<DbRecord> = receiver.try_iter().collect();
+    assert_eq!(records.len(), 3);
+    match &records[0] {
+        DbRecord::Reorg(reorg) => {
+            assert_eq!(reorg.block_number, 105);
+            assert_eq!(reorg.orphaned_block_hash, BLOB_BLOCK_HASH);
+            assert_eq!(
+                reorg.canonical_block_hash.as_deref(),
+                Some(CANONICAL_BLOCK_HASH)
+            );
+            assert_eq!(reorg.detected_by, "parent_hash");
+        }
+        record => panic!("expected a reorg, got {:?}", record),
+    }
+    assert!(
+        matches!(&records[1], DbRecord::Checkpoint(checkpoint) if checkpoint.block_number == 106)
+    );
+    assert!(matches!(&records[2], DbRecord::Finality(_)));
+}
+
+async fn store_swap(tx_hash: &str, pool_address: &str, block_number: i64, pool: &PgPool) {
+    _ = sqlx::query("DELETE FROM fees WHERE tx_hash = $1")
+        .bind(tx_hash)
+        .execute(pool)
+        .await;
+    _ = sqlx::query("DELETE FROM swaps WHERE tx_hash = $1")
+        .bind(tx_hash)
+        .execute(pool)
+        .await;
+    insert_tx_fee(
+        &TxFee {
+            tx_hash: tx_hash.into(),
+            fee_wei: "0".into(),
+            eth_usdt_price: "2000".into(),
+            ..Default::default()
+        },
+        pool,
+    )
+    .await
+    .unwrap();
+    insert_swap(
+        &Swap {
+            tx_hash: tx_hash.into(),
+            log_index: 0,
+            pool_address: pool_address.into(),
+            block_number,
+            block_hash: Some(format!("{:#066x}", block_number)),
+            timestamp_ms: 0,
+            sender: pool_address.into(),
+            recipient: pool_address.into(),
+            tx_from: pool_address.into(),
+            amount0: "0".into(),
+            amount1: "0".into(),
+            price: 0.0,
+            notional_usd: 0.0,
+            fee_wei: None,
+            gas_usd: None,
+            gas_share: None,
+        },
+        pool,
+    )
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn store_reorg_rollback() {
+    let config = AppConfig::new().unwrap();
+    let db_connection = PgPoolOptions::new()
+        .connect_timeout(std::time::Duration::from_secs(2))
+        .connect(&config.database.connection_string(false))
+        .await
+        .expect("Failed to create db connection");
+    run_migrations(&db_connection)
+        .await
+        .expect("Failed to run db migrations");
+    let pool_address = "0x00000000000000000000000000000000000000e9";
+    let kept_tx_hash = "0x00000000000000000000000000000000000000000000000000000000000e9200";
+    let orphaned_tx_hash = "0x00000000000000000000000000000000000000000000000000000000000e9201";
+    store_swap(kept_tx_hash, pool_address, 200, &db_connection).await;
+    store_swap(orphaned_tx_hash, pool_address, 201, &db_connection).await;
+    upsert_checkpoint(
+        &Checkpoint {
+            pool_address: pool_address.into(),
+            block_number: 201,
+        },
+        &db_connection,
+    )
+    .await
+    .unwrap();
+
+    let timestamp_ms = 1_700_000_000_201;
+    rollback_reorg(
+        &Reorg {
+            pool_address: pool_address.into(),
+            block_number: 201,
+            orphaned_block_hash: format!("{:#066x}", 201),
+            canonical_block_hash: None,
+            detected_by: "removed_log".into(),
+            swaps_rolled_back: 0,
+            timestamp_ms,
+        },
+        &db_connection,
+    )
+    .await
+    .unwrap();
+
+    let swaps: Vec<(i64,)> =
+        sqlx::query_as("SELECT block_number FROM swaps WHERE pool_address = $1")
+            .bind(pool_address)
+            .fetch_all(&db_connection)
+            .await
+            .unwrap();
+    assert_eq!(swaps, vec![(200,)]);
+    let kept_tx_hash = H256::from_str(kept_tx_hash).unwrap();
+    let orphaned_tx_hash = H256::from_str(orphaned_tx_hash).unwrap();
+    assert!(get_tx_fee_from_db(&kept_tx_hash, &db_connection)
+        .await
+        .is_ok());
+    assert!(get_tx_fee_from_db(&orphaned_tx_hash, &db_connection)
+        .await
+        .is_err());
+    let checkpoint = get_checkpoint_from_db(pool_address, &db_connection)
+        .await
+        .unwrap();
+    assert_eq!(checkpoint, Some(200));
+
+    let reorgs = get_reorgs_from_db(Some(timestamp_ms), Some(timestamp_ms), 10, &db_connection)
+        .await
+        .unwrap();
+    let reorg = reorgs
+        .iter()
+        .find(|reorg| reorg.pool_address == pool_address)
+        .unwrap();
+    assert_eq!(reorg.swaps_rolled_back, 1);
+}
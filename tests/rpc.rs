@@ -0,0 +1,111 @@
+mod common;
+
+use common::{Fixtures, MockServer};
+use ethers::middleware::Middleware;
+use ethers::types::{TransactionReceipt, H256, U256};
+use serde_json::{json, Value};
+use std::str::FromStr;
+use uniswap_watcher::rpc::{connect_http, RpcSettings};
+
+const LEGACY_TX_HASH: &str = "0x5c504ed432cb51138bcf09aa5e8a410dd4a1e204ef84bfed1be16dfba1b22060";
+/// Nothing listens on this port, so requests fail to connect
+const DEAD_URL: &str = "http://127.0.0.1:1";
+
+fn receipt(gas_used: u64) -> Value {
+    let path = format!(
+        "{}/tests/fixtures/legacy_tx_receipt.json",
+        env!("CARGO_MANIFEST_DIR")
+    );
+    let mut receipt: Value = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    receipt["gasUsed"] = json!(format!("{:#x}", gas_used));
+    receipt
+}
+
+fn receipt_server(gas_used: u64) -> MockServer {
+    MockServer::start(Fixtures::default().with(
+        "eth_getTransactionReceipt",
+        LEGACY_TX_HASH,
+        receipt(gas_used),
+    ))
+}
+
+#[tokio::test]
+async fn fail_over_unreachable_endpoint() {
+    let server = MockServer::start(Fixtures::default().with("eth_chainId", "null", json!("0x1")));
+    let urls = vec![DEAD_URL.to_string(), server.url.clone()];
+    let eth_client = connect_http(&urls, &RpcSettings::default()).unwrap();
+
+    // The head check before the first request fails on the unreachable endpoint, which is then ranked last
+    assert_eq!(eth_client.get_chainid().await.unwrap(), U256::from(1));
+    let health = eth_client.as_ref().health();
+    assert_eq!((health[0].1.requests, health[0].1.errors), (1, 1));
+    assert_eq!((health[1].1.requests, health[1].1.errors), (2, 0));
+    assert!(health[0].1.error_rate > health[1].1.error_rate);
+
+    // JSON-RPC errors are answers of a healthy endpoint, and are not failed over
+    assert!(eth_client.get_gas_price().await.is_err());
+    let health = eth_client.as_ref().health();
+    assert_eq!((health[0].1.requests, health[0].1.errors), (1, 1));
+    assert_eq!((health[1].1.requests, health[1].1.errors), (3, 0));
+}
+
+#[tokio::test]
+async fn avoid_lagging_endpoint() {
+    let lagging = MockServer::start(
+        Fixtures::default()
+            .with("eth_blockNumber", "null", json!("0x64"))
+            .with("eth_chainId", "null", json!("0x1")),
+    );
+    let synced = MockServer::start(
+        Fixtures::default()
+            .with("eth_blockNumber", "null", json!("0x6e"))
+            .with("eth_chainId", "null", json!("0x2")),
+    );
+    let urls = vec![lagging.url.clone(), synced.url.clone()];
+    let eth_client = connect_http(&urls, &RpcSettings::default()).unwrap();
+
+    // The heads of both endpoints are checked before the first request
+    assert_eq!(eth_client.get_chainid().await.unwrap(), U256::from(2));
+    let heads: Vec<Option<u64>> = eth_client
+        .as_ref()
+        .health()
+        .into_iter()
+        .map(|(_, health)| health.head)
+        .collect();
+    assert_eq!(heads, vec![Some(100), Some(110)]);
+}
+
+#[tokio::test]
+async fn receipt_quorum() {
+    let servers = [
+        receipt_server(21000),
+        receipt_server(50000),
+        receipt_server(21000),
+    ];
+    let urls: Vec<String> = servers.iter().map(|server| server.url.clone()).collect();
+    let tx_hash = H256::from_str(LEGACY_TX_HASH).unwrap();
+
+    let settings = RpcSettings {
+        receipt_quorum: 2,
+        ..Default::default()
+    };
+    let eth_client = connect_http(&urls, &settings).unwrap();
+    let receipt: TransactionReceipt = eth_client
+        .get_transaction_receipt(tx_hash)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(receipt.gas_used, Some(U256::from(21000)));
+
+    // Only two endpoints agree
+    let settings = RpcSettings {
+        receipt_quorum: 3,
+        ..Default::default()
+    };
+    let eth_client = connect_http(&urls, &settings).unwrap();
+    let err = eth_client
+        .get_transaction_receipt(tx_hash)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("no quorum"));
+}
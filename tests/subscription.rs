@@ -0,0 +1,181 @@
+mod common;
+
+use common::{decode_swap_log, lazy_db_connection, swap_log, Fixtures, MockServer};
+use serde_json::json;
+use sqlx::postgres::PgPoolOptions;
+use std::sync::{mpsc, Arc, RwLock};
+use std::time::Duration;
+use uniswap_watcher::db::{
+    get_ingestion_errors_from_db, insert_ingestion_error, run_migrations, DbRecord, IngestionError,
+};
+use uniswap_watcher::ingest::{SubscriptionSettings, SwapProcessor};
+use uniswap_watcher::oracle::FeeWindow;
+use uniswap_watcher::{subscribe_blocks, AppConfig};
+
+const LEGACY_TX_HASH: &str = "0x5c504ed432cb51138bcf09aa5e8a410dd4a1e204ef84bfed1be16dfba1b22060";
+const LEGACY_BLOCK_HASH: &str =
+    "0x4e3a3754410177e6937ef1f84bba68ea139e8d1a2258c5f85db9f1cd715a1bdd";
+const BLOB_TX_HASH: &str = "0xbb1e0f1c1accfedcc1dddfcc48d7d0bf893263a77e74c3d26c63adf729e89e69";
+const BLOB_BLOCK_HASH: &str = "0xd3d17a9c8eb69157f59feb3c42cc46126d390efae0c8525f7377115d5d476235";
+const UNKNOWN_TX_HASH: &str = "0x00000000000000000000000000000000000000000000000000000000000dead1";
+
+fn fixtures() -> Fixtures {
+    Fixtures::default()
+        .with_file(
+            "eth_getTransactionReceipt",
+            LEGACY_TX_HASH,
+            "legacy_tx_receipt.json",
+        )
+        .with_file("eth_getTransactionByHash", LEGACY_TX_HASH, "legacy_tx.json")
+        .with_file(
+            "eth_getBlockByHash",
+            LEGACY_BLOCK_HASH,
+            "legacy_tx_block.json",
+        )
+        .with_file(
+            "eth_getTransactionReceipt",
+            BLOB_TX_HASH,
+            "blob_tx_receipt.json",
+        )
+        .with_file("eth_getBlockByHash", BLOB_BLOCK_HASH, "blob_tx_block.json")
+}
+
+fn swaps(records: &[DbRecord]) -> Vec<(String, i64)> {
+    records
+        .iter()
+        .filter_map(|record| match record {
+            DbRecord::Swap(swap) => Some((swap.tx_hash.clone(), swap.block_number)),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn exponential_backoff() {
+    let settings = SubscriptionSettings {
+        initial_backoff_ms: 1000,
+        max_backoff_ms: 60000,
+    };
+    assert_eq!(settings.backoff(0), Duration::from_secs(1));
+    assert_eq!(settings.backoff(3), Duration::from_secs(8));
+    assert_eq!(settings.backoff(6), Duration::from_secs(60));
+    assert_eq!(settings.backoff(100), Duration::from_secs(60));
+}
+
+#[tokio::test]
+async fn block_ingestion_restarts() {
+    let mut config = AppConfig::new().unwrap();
+    // Nothing listens on this port, so the subscription fails to connect
+    config.rpc_urls_ws = vec!["ws://127.0.0.1:1".into()];
+    config.subscription = SubscriptionSettings {
+        initial_backoff_ms: 10,
+        max_backoff_ms: 50,
+    };
+    let (sender, _) = mpsc::channel();
+    let gas_oracle = Arc::new(RwLock::new(FeeWindow::new(config.oracle.window_blocks)));
+    let ingestion = tokio::spawn(subscribe_blocks(config, sender, gas_oracle));
+
+    // The failed connection is retried rather than ending the ingestion
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(!ingestion.is_finished());
+    ingestion.abort();
+}
+
+#[tokio::test]
+async fn failed_swap_does_not_stop_ingestion() {
+    let server = MockServer::start(fixtures());
+    let mut config = AppConfig::new().unwrap();
+    config.rpc_urls_http = vec![server.url.clone()];
+    config.binance_host = server.url.clone();
+    let (sender, receiver) = mpsc::channel();
+    let mut processor =
+        SwapProcessor::new(config.clone(), sender, lazy_db_connection(&config), None);
+
+    // The receipt of the first swap cannot be fetched
+    let (log, meta) = decode_swap_log(swap_log(&config, UNKNOWN_TX_HASH, BLOB_BLOCK_HASH, 105));
+    assert!(processor.handle(log, meta, true).await.unwrap());
+    let (log, meta) = decode_swap_log(swap_log(&config, BLOB_TX_HASH, BLOB_BLOCK_HASH, 105));
+    assert!(processor.handle(log, meta, true).await.unwrap());
+    processor.flush().await;
+
+    let records: Vec<DbRecord> = receiver.try_iter().collect();
+    let errors: Vec<&IngestionError> = records
+        .iter()
+        .filter_map(|record| match record {
+            DbRecord::IngestionError(error) => Some(error),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].tx_hash, UNKNOWN_TX_HASH);
+    assert_eq!(errors[0].block_number, 105);
+    assert_eq!(swaps(&records), vec![(BLOB_TX_HASH.to_string(), 105)]);
+}
+
+#[tokio::test]
+async fn reconnect_fills_gap() {
+    let mut config = AppConfig::new().unwrap();
+    let fixtures = fixtures().with_logs(
+        105,
+        107,
+        json!([
+            swap_log(&config, BLOB_TX_HASH, BLOB_BLOCK_HASH, 105),
+            swap_log(&config, LEGACY_TX_HASH, LEGACY_BLOCK_HASH, 107)
+        ]),
+    );
+    let server = MockServer::start(fixtures);
+    config.rpc_urls_http = vec![server.url.clone()];
+    config.binance_host = server.url.clone();
+    let (sender, receiver) = mpsc::channel();
+    let mut processor =
+        SwapProcessor::new(config.clone(), sender, lazy_db_connection(&config), None);
+
+    // A live swap is processed before the connection drops, without any checkpoint yet
+    let (log, meta) = decode_swap_log(swap_log(&config, BLOB_TX_HASH, BLOB_BLOCK_HASH, 105));
+    assert!(processor.handle(log, meta, true).await.unwrap());
+    assert_eq!(processor.checkpoint, None);
+
+    // After reconnecting, the swaps are caught up from its block, without processing it twice
+    processor.catch_up(107).await.unwrap();
+    let records: Vec<DbRecord> = receiver.try_iter().collect();
+    assert_eq!(
+        swaps(&records),
+        vec![
+            (BLOB_TX_HASH.to_string(), 105),
+            (LEGACY_TX_HASH.to_string(), 107)
+        ]
+    );
+    assert_eq!(processor.checkpoint, Some(107));
+}
+
+#[tokio::test]
+async fn store_ingestion_errors() {
+    let config = AppConfig::new().unwrap();
+    let db_connection = PgPoolOptions::new()
+        .connect_timeout(std::time::Duration::from_secs(2))
+        .connect(&config.database.connection_string(false))
+        .await
+        .expect("Failed to create db connection");
+    run_migrations(&db_connection)
+        .await
+        .expect("Failed to run db migrations");
+    let error = IngestionError {
+        tx_hash: UNKNOWN_TX_HASH.into(),
+        log_index: 3,
+        block_number: 105,
+        error: "Tx receipt not found".into(),
+        timestamp_ms: 1709294499123,
+    };
+    insert_ingestion_error(&error, &db_connection)
+        .await
+        .unwrap();
+    let errors = get_ingestion_errors_from_db(
+        Some(error.timestamp_ms),
+        Some(error.timestamp_ms),
+        10,
+        &db_connection,
+    )
+    .await
+    .unwrap();
+    assert!(errors.contains(&error));
+}